@@ -1,5 +1,4 @@
 use audio_engine::{AudioEngine, OggDecoder, WavDecoder};
-use std::io::Cursor;
 
 fn main() {
     env_logger::init();
@@ -7,13 +6,13 @@ fn main() {
     let engine = AudioEngine::new().unwrap();
     let mut sounds = [
         engine
-            .new_sound(OggDecoder::new(Cursor::new(&include_bytes!("pipe.ogg")[..])).unwrap())
+            .new_sound(OggDecoder::from_bytes(include_bytes!("pipe.ogg")).unwrap())
             .unwrap(),
         engine
-            .new_sound(WavDecoder::new(Cursor::new(&include_bytes!("ilussion.wav")[..])).unwrap())
+            .new_sound(WavDecoder::from_bytes(include_bytes!("ilussion.wav")).unwrap())
             .unwrap(),
         engine
-            .new_sound(WavDecoder::new(Cursor::new(&include_bytes!("0.wav")[..])).unwrap())
+            .new_sound(WavDecoder::from_bytes(include_bytes!("0.wav")).unwrap())
             .unwrap(),
     ];
     sounds[0].set_loop(true);
@@ -49,9 +48,7 @@ fn main() {
             Some("new") => {
                 sound_stack.push(
                     engine
-                        .new_sound(
-                            OggDecoder::new(Cursor::new(&include_bytes!("pipe.ogg")[..])).unwrap(),
-                        )
+                        .new_sound(OggDecoder::from_bytes(include_bytes!("pipe.ogg")).unwrap())
                         .unwrap(),
                 );
                 sound_stack.last_mut().unwrap().play();
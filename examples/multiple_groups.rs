@@ -18,7 +18,7 @@ fn main() {
     let la = 440.0;
 
     let la_minor = [la, la * s.powi(3), la * s.powi(7)];
-    la_minor.map(|freq| {
+    let _minor_tracks = la_minor.map(|freq| {
         let mut track = engine
             .new_sound_with_group(Groups::Minor, SineWave::new(engine.sample_rate(), freq))
             .unwrap();
@@ -29,7 +29,7 @@ fn main() {
     });
 
     let la_maior = [la, la * s.powi(4), la * s.powi(7)];
-    la_maior.map(|freq| {
+    let _major_tracks = la_maior.map(|freq| {
         let mut track = engine
             .new_sound_with_group(Groups::Major, SineWave::new(engine.sample_rate(), freq))
             .unwrap();
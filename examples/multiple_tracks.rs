@@ -1,5 +1,5 @@
 use audio_engine::{AudioEngine, OggDecoder, SineWave};
-use std::{io::Cursor, time::Instant};
+use std::time::Instant;
 
 fn log_panic() {
     let default_hook = std::panic::take_hook();
@@ -51,7 +51,7 @@ fn main() {
     let engine = AudioEngine::new().unwrap();
 
     let mut track1 = engine
-        .new_sound(OggDecoder::new(Cursor::new(&include_bytes!("pipe.ogg")[..])).unwrap())
+        .new_sound(OggDecoder::from_bytes(include_bytes!("pipe.ogg")).unwrap())
         .unwrap();
     track1.set_loop(true);
     track1.play();
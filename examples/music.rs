@@ -1,5 +1,7 @@
+// Built as a `cdylib` for android, where `main` is called by `ndk_glue`.
+#![cfg_attr(not(target_os = "android"), allow(dead_code))]
+
 use audio_engine::{AudioEngine, OggDecoder};
-use std::io::Cursor;
 
 fn log_panic() {
     let default_hook = std::panic::take_hook();
@@ -50,7 +52,7 @@ fn main() {
 
     let engine = AudioEngine::new().unwrap();
     let mut music = engine
-        .new_sound(OggDecoder::new(Cursor::new(&include_bytes!("pipe.ogg")[..])).unwrap())
+        .new_sound(OggDecoder::from_bytes(include_bytes!("pipe.ogg")).unwrap())
         .unwrap();
     music.set_loop(true);
     music.play();
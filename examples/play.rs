@@ -1,4 +1,4 @@
-use audio_engine::{AudioEngine, OggDecoder, WavDecoder};
+use audio_engine::{decode, AudioEngine, FormatHint};
 use std::path::PathBuf;
 
 fn log_panic() {
@@ -84,28 +84,19 @@ fn main() {
     let buffered = std::io::BufReader::new(file);
 
     let engine = AudioEngine::new().unwrap();
-    let mut music = match path.extension() {
-        Some(x) if x == "wav" => engine
-            .new_sound(WavDecoder::new(buffered).unwrap())
-            .unwrap(),
-        Some(x) if x == "ogg" => engine
-            .new_sound(OggDecoder::new(buffered).unwrap())
-            .unwrap(),
-        Some(x) => {
-            eprintln!(
-                "unsupported file format {}",
-                format!("'{}'", x.to_string_lossy())
-            );
-            std::process::exit(3);
-        }
-        _ => {
-            eprintln!("unkown file format",);
+    let source = match decode(buffered, FormatHint::from_path(&path)) {
+        Ok(x) => x,
+        Err(err) => {
+            eprintln!("error decoding file: {}", err);
             std::process::exit(3);
         }
     };
+    let mut music = engine.new_sound_boxed(source).unwrap();
 
     music.set_loop(looping);
     music.play();
 
-    loop {}
+    loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
 }
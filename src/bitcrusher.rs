@@ -0,0 +1,215 @@
+use crate::{Effect, SoundSource};
+
+/// A effect that reduces the bit depth and the effective sample rate of a buffer.
+///
+/// This gives a lo-fi, retro sound. The bit depth is reduced by quantizing each sample to the
+/// given number of bits, and the sample rate is reduced by holding each frame for the given
+/// number of frames.
+///
+/// To process a single source, see [`BitCrusher`].
+pub struct BitCrusherEffect {
+    bits: u32,
+    downsample: u32,
+    /// The last frame read, that is repeated while being held.
+    held: Vec<i16>,
+    /// The number of frames that `held` was already repeated.
+    hold_count: u32,
+}
+impl BitCrusherEffect {
+    /// Create a new BitCrusherEffect, with the given bit depth and downsample factor.
+    ///
+    /// See [`set_bits`](Self::set_bits) and [`set_downsample`](Self::set_downsample).
+    pub fn new(bits: u32, downsample: u32) -> Self {
+        let mut this = Self {
+            bits: 16,
+            downsample: 1,
+            held: Vec::new(),
+            hold_count: 0,
+        };
+        this.set_bits(bits);
+        this.set_downsample(downsample);
+        this
+    }
+
+    /// Set the bit depth that the samples are quantized to, clamped to the range `1..=16`.
+    ///
+    /// A bit depth of 16 leaves the samples unchanged.
+    pub fn set_bits(&mut self, bits: u32) {
+        self.bits = bits.clamp(1, 16);
+    }
+
+    /// The bit depth that the samples are quantized to.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Set by how much the effective sample rate is divided, by holding each frame for this number
+    /// of frames. A value of 0 is treated as 1.
+    ///
+    /// A factor of 1 leaves the sample rate unchanged.
+    pub fn set_downsample(&mut self, downsample: u32) {
+        self.downsample = downsample.max(1);
+    }
+
+    /// By how much the effective sample rate is divided.
+    pub fn downsample(&self) -> u32 {
+        self.downsample
+    }
+
+    /// Reduce the bit depth and sample rate of the given samples.
+    fn crush(&mut self, buffer: &mut [i16], channels: u16) {
+        if self.held.len() != channels as usize {
+            self.held = vec![0; channels as usize];
+            self.hold_count = 0;
+        }
+        let mask = !((1u16 << (16 - self.bits)) - 1) as i16;
+        for frame in buffer.chunks_exact_mut(channels as usize) {
+            if self.hold_count == 0 {
+                self.held.copy_from_slice(frame);
+            }
+            self.hold_count = (self.hold_count + 1) % self.downsample;
+            for (out, &held) in frame.iter_mut().zip(&self.held) {
+                *out = held & mask;
+            }
+        }
+    }
+}
+impl Effect for BitCrusherEffect {
+    fn process(&mut self, buffer: &mut [f32], channels: u16, _: u32) {
+        // quantized in chunks, to not allocate
+        crate::with_scratch(channels, |samples| {
+            for chunk in buffer.chunks_mut(samples.len()) {
+                let samples = &mut samples[..chunk.len()];
+                for (out, x) in samples.iter_mut().zip(chunk.iter()) {
+                    *out = <i16 as cpal::Sample>::from(x);
+                }
+                self.crush(samples, channels);
+                for (out, x) in chunk.iter_mut().zip(samples.iter()) {
+                    *out = cpal::Sample::to_f32(x);
+                }
+            }
+        });
+    }
+
+    fn reset(&mut self) {
+        self.hold_count = 0;
+    }
+}
+
+/// A SoundSource that reduces the bit depth and the effective sample rate of another source.
+///
+/// This applies a [`BitCrusherEffect`] to the samples of the inner source.
+pub struct BitCrusher<T: SoundSource> {
+    inner: T,
+    effect: BitCrusherEffect,
+}
+impl<T: SoundSource> BitCrusher<T> {
+    /// Create a new BitCrusher, with the given bit depth and downsample factor.
+    ///
+    /// See [`set_bits`](Self::set_bits) and [`set_downsample`](Self::set_downsample).
+    pub fn new(inner: T, bits: u32, downsample: u32) -> Self {
+        let effect = BitCrusherEffect::new(bits, downsample);
+        Self { inner, effect }
+    }
+
+    /// Set the bit depth that the samples are quantized to. See [`BitCrusherEffect::set_bits`].
+    pub fn set_bits(&mut self, bits: u32) {
+        self.effect.set_bits(bits);
+    }
+
+    /// The bit depth that the samples are quantized to.
+    pub fn bits(&self) -> u32 {
+        self.effect.bits()
+    }
+
+    /// Set by how much the effective sample rate is divided. See
+    /// [`BitCrusherEffect::set_downsample`].
+    pub fn set_downsample(&mut self, downsample: u32) {
+        self.effect.set_downsample(downsample);
+    }
+
+    /// By how much the effective sample rate is divided.
+    pub fn downsample(&self) -> u32 {
+        self.effect.downsample()
+    }
+}
+impl<T: SoundSource> SoundSource for BitCrusher<T> {
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        self.inner.size_hint()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.effect.reset();
+    }
+
+    fn seek(&mut self, frame: u64) {
+        self.inner.seek(frame);
+        self.effect.reset();
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let len = self.inner.write_samples(buffer);
+        self.effect.crush(&mut buffer[..len], self.inner.channels());
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Effect, SliceSource, SoundSource};
+
+    use super::{BitCrusher, BitCrusherEffect};
+
+    #[test]
+    fn bit_crusher() {
+        let samples = vec![0x0123, 0x4567, -0x0123, 0x7fff, 0x1111, 0x2222];
+        let mut crusher = BitCrusher::new(SliceSource::new(samples, 2, 10), 16, 1);
+        let mut buffer = [0; 6];
+        crusher.write_samples(&mut buffer);
+        assert_eq!(buffer, [0x0123, 0x4567, -0x0123, 0x7fff, 0x1111, 0x2222]);
+
+        crusher.reset();
+        crusher.set_bits(8);
+        crusher.write_samples(&mut buffer);
+        assert_eq!(buffer, [0x0100, 0x4500, -0x0200, 0x7f00, 0x1100, 0x2200]);
+
+        crusher.reset();
+        crusher.set_bits(16);
+        crusher.set_downsample(2);
+        crusher.write_samples(&mut buffer);
+        assert_eq!(buffer, [0x0123, 0x4567, 0x0123, 0x4567, 0x1111, 0x2222]);
+    }
+
+    #[test]
+    fn bit_crusher_effect() {
+        let samples = [0x0123i16, 0x4567, -0x0123, 0x7fff, 0x1111, 0x2222];
+        let mut buffer: Vec<f32> = samples.iter().map(cpal::Sample::to_f32).collect();
+        let mut crusher = BitCrusherEffect::new(8, 2);
+        crusher.process(&mut buffer, 2, 10);
+        let buffer: Vec<i16> = buffer.iter().map(<i16 as cpal::Sample>::from).collect();
+        let expected = [0x0100, 0x4500, 0x0100, 0x4500, 0x1100, 0x2200];
+        for (a, b) in buffer.iter().zip(&expected) {
+            assert!((a - b).abs() <= 1, "{:?} != {:?}", buffer, expected);
+        }
+
+        // the held frame restarts on reset
+        crusher.reset();
+        let mut buffer = [0.5, 0.5, 0.0, 0.0];
+        crusher.process(&mut buffer, 2, 10);
+        assert!(buffer[2] > 0.49, "{:?}", buffer);
+
+        // a frame larger than the buffer used for quantizing
+        let mut buffer = vec![0.5; 2 * 2000];
+        crusher.process(&mut buffer, 2000, 10);
+        assert!(buffer.iter().all(|&x| (x - 0.5).abs() < 1e-2));
+    }
+}
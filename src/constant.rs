@@ -0,0 +1,112 @@
+use crate::SoundSource;
+
+/// A SoundSource that outputs the same sample value, in all channels.
+///
+/// This can be used to generate a DC offset, or for testing. By default it never ends, but a
+/// length can be set with [`len`](Constant::len).
+pub struct Constant {
+    /// The number of frames already written.
+    i: u64,
+    /// The value of all samples.
+    pub value: i16,
+    /// The number of channels of this SoundSource.
+    pub channels: u16,
+    /// The sample_rate of this SoundSource.
+    pub sample_rate: u32,
+    /// The number of frames of this SoundSource, or `None` if it never ends.
+    pub len: Option<u64>,
+}
+impl Constant {
+    /// Create a new Constant SoundSource, that never ends.
+    pub fn new(value: i16, channels: u16, sample_rate: u32) -> Self {
+        Self {
+            i: 0,
+            value,
+            channels,
+            sample_rate,
+            len: None,
+        }
+    }
+}
+impl SoundSource for Constant {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn reset(&mut self) {
+        self.i = 0;
+    }
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let len = remaining_len(&mut self.i, self.len, self.channels, buffer.len());
+        buffer[..len].fill(self.value);
+        len
+    }
+    fn size_hint(&self) -> Option<u64> {
+        self.len.map(|len| len * self.channels as u64)
+    }
+    fn seek(&mut self, frame: u64) {
+        self.i = frame;
+    }
+}
+
+/// A SoundSource that outputs only silence.
+///
+/// This can be used for padding, or to delay another sound. By default it never ends, but a
+/// length can be set with [`len`](Silence::len).
+pub struct Silence {
+    /// The number of frames already written.
+    i: u64,
+    /// The number of channels of this SoundSource.
+    pub channels: u16,
+    /// The sample_rate of this SoundSource.
+    pub sample_rate: u32,
+    /// The number of frames of this SoundSource, or `None` if it never ends.
+    pub len: Option<u64>,
+}
+impl Silence {
+    /// Create a new Silence SoundSource, that never ends.
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            i: 0,
+            channels,
+            sample_rate,
+            len: None,
+        }
+    }
+}
+impl SoundSource for Silence {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn reset(&mut self) {
+        self.i = 0;
+    }
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let len = remaining_len(&mut self.i, self.len, self.channels, buffer.len());
+        buffer[..len].fill(0);
+        len
+    }
+    fn size_hint(&self) -> Option<u64> {
+        self.len.map(|len| len * self.channels as u64)
+    }
+    fn seek(&mut self, frame: u64) {
+        self.i = frame;
+    }
+}
+
+/// Return how many samples can be written to a buffer of the given length, advancing the frame
+/// counter `i`.
+fn remaining_len(i: &mut u64, len: Option<u64>, channels: u16, buffer_len: usize) -> usize {
+    let channels = channels as u64;
+    let mut frames = buffer_len as u64 / channels;
+    if let Some(len) = len {
+        frames = frames.min(len.saturating_sub(*i));
+    }
+    *i = i.saturating_add(frames);
+    (frames * channels) as usize
+}
@@ -1,13 +1,586 @@
 //! Structs for converting SoundSource parameters, like number of channels and sample rate.
 
-use super::SoundSource;
+use super::{AudioEngineError, SoundSource};
 use std::vec;
 
+/// How a [`ChannelConverter`] distributes a source to a larger number of channels.
+///
+/// The channels of the output are assumed to be in the usual order of surround layouts: front
+/// left and right, followed by, if there are 6 or more channels, center and LFE, and then the
+/// surround channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Upmix {
+    /// Every output channel receives the average of all input channels.
+    #[default]
+    Duplicate,
+    /// Only the front left and right channels are used. A stereo source keeps its left and right
+    /// channels, and other sources are averaged to mono and played in both channels.
+    Front,
+    /// Like [`Front`](Upmix::Front), but the surround channels also receive the front channels,
+    /// attenuated by 6 dB. The center and LFE channels are kept silent.
+    FrontSurround,
+}
+
+/// How a [`ChannelConverter`] mixes a source down to a smaller number of channels.
+///
+/// The channels of the source are assumed to be in the same order as in [`Upmix`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Downmix {
+    /// Every output channel receives the average of all input channels.
+    #[default]
+    Mean,
+    /// When mixing a quad, 5.1 or 7.1 source down to stereo, each front channel keeps its side,
+    /// and the center and surround channels are added to it attenuated by 3 dB, following ITU-R
+    /// BS.775. The LFE channel is dropped. Other conversions use [`Mean`](Downmix::Mean).
+    Itu,
+}
+
+/// The interpolation used by a [`SampleRateConverter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quality {
+    /// Linear interpolation between the two nearest frames. It is the fastest, but adds some
+    /// aliasing, mostly audible when downsampling music.
+    #[default]
+    Linear,
+    /// Catmull-Rom cubic interpolation between the four nearest frames, with less aliasing than
+    /// `Linear`.
+    Cubic,
+}
+impl Quality {
+    /// The number of frames after the interpolated one that are needed by the interpolation.
+    fn lookahead(self) -> usize {
+        match self {
+            Quality::Linear => 1,
+            Quality::Cubic => 2,
+        }
+    }
+}
+
+/// Convert a SoundSource to a diferent number of channels.
+///
+/// If the number of channels in the inner SoundSource is equal to the output number of channels,
+/// no conversion will be performed. Otherwise, each channel of the output will receive the average
+/// of all input channels. A surround source can be mixed down to stereo keeping its stereo image
+/// with [`with_downmix`](ChannelConverter::with_downmix), and a custom routing between the
+/// channels can be set with [`with_matrix`](ChannelConverter::with_matrix).
+pub struct ChannelConverter<T: SoundSource> {
+    inner: T,
+    /// The number of channels to convert to.
+    channels: u16,
+    /// The gain from each input channel to each output channel, with a row of gains for each
+    /// output channel, or `None` to use the average of the input channels.
+    matrix: Option<Vec<f32>>,
+    /// A buffer to temporary hold the input samples.
+    in_buffer: Vec<i16>,
+}
+impl<T: SoundSource> ChannelConverter<T> {
+    /// Create a new ChannelConverter.
+    ///
+    /// This will convert from the number of channels of `inner`, outputing the given number of
+    /// `channels`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `inner` or `channels` has zero channels.
+    pub fn new(inner: T, channels: u16) -> Self {
+        assert!(inner.channels() > 0, "the inner source has zero channels");
+        assert!(channels > 0, "can't convert to zero channels");
+        Self {
+            inner,
+            channels,
+            matrix: None,
+            in_buffer: Vec::new(),
+        }
+    }
+
+    /// Create a new ChannelConverter, that distributes the channels of `inner` to a larger
+    /// number of `channels` in the given way.
+    ///
+    /// If `inner` has the same number of channels or more, this is the same as
+    /// [`new`](Self::new).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `inner` or `channels` has zero channels.
+    pub fn with_upmix(inner: T, channels: u16, upmix: Upmix) -> Self {
+        assert!(inner.channels() > 0, "the inner source has zero channels");
+        let in_channels = inner.channels() as usize;
+        let out_channels = channels as usize;
+        if upmix == Upmix::Duplicate || in_channels >= out_channels {
+            return Self::new(inner, channels);
+        }
+
+        let mean = vec![1.0 / in_channels as f32; in_channels];
+        let (left, right) = if in_channels == 2 {
+            (vec![1.0, 0.0], vec![0.0, 1.0])
+        } else {
+            (mean.clone(), mean)
+        };
+        let matrix = (0..out_channels)
+            .map(|c| {
+                let surround = upmix == Upmix::FrontSurround
+                    && c >= 2
+                    && !(out_channels >= 6 && (c == 2 || c == 3));
+                let gain = match c {
+                    0 | 1 => 1.0,
+                    _ if surround => 0.5,
+                    _ => 0.0,
+                };
+                let side = if c % 2 == 0 { &left } else { &right };
+                side.iter().map(|x| x * gain).collect()
+            })
+            .collect();
+        Self::with_matrix(inner, channels, matrix).unwrap()
+    }
+
+    /// Create a new ChannelConverter, that mixes the channels of `inner` down to a smaller
+    /// number of `channels` in the given way.
+    ///
+    /// If `inner` has the same number of channels or less, this is the same as
+    /// [`new`](Self::new).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `inner` or `channels` has zero channels.
+    pub fn with_downmix(inner: T, channels: u16, downmix: Downmix) -> Self {
+        assert!(inner.channels() > 0, "the inner source has zero channels");
+        const C: f32 = std::f32::consts::FRAC_1_SQRT_2;
+        // the gains of the left output channel, the right one is symmetric
+        let left: &[f32] = match (downmix, inner.channels(), channels) {
+            (Downmix::Itu, 4, 2) => &[1.0, 0.0, C, 0.0],
+            (Downmix::Itu, 6, 2) => &[1.0, 0.0, C, 0.0, C, 0.0],
+            (Downmix::Itu, 8, 2) => &[1.0, 0.0, C, 0.0, C, 0.0, C, 0.0],
+            _ => return Self::new(inner, channels),
+        };
+        let right = left
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| match i {
+                // the center and LFE are not swapped
+                2 | 3 if left.len() >= 6 => x,
+                _ => left[i ^ 1],
+            })
+            .collect();
+        Self::with_matrix(inner, channels, vec![left.to_vec(), right]).unwrap()
+    }
+
+    /// Create a new ChannelConverter, that routes the channels with the given matrix.
+    ///
+    /// `matrix[out][in]` is the gain from the input channel `in` to the output channel `out`, so
+    /// each output channel receives the sum of the input channels multiplied by its row of
+    /// gains. This is used even if the number of channels don't change.
+    ///
+    /// Return an error if `matrix` doesn't have a row for each output channel, with a gain for
+    /// each channel of `inner`.
+    ///
+    /// # Example
+    ///
+    /// Extract the third channel of a source, and play it in the left channel, and with half the
+    /// volume in the right channel:
+    ///
+    /// ```
+    /// # fn main() -> Result<(), audio_engine::AudioEngineError> {
+    /// # let source = audio_engine::Constant::new(0, 4, 48000);
+    /// use audio_engine::converter::ChannelConverter;
+    ///
+    /// let matrix = vec![vec![0.0, 0.0, 1.0, 0.0], vec![0.0, 0.0, 0.5, 0.0]];
+    /// let converter = ChannelConverter::with_matrix(source, 2, matrix)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_matrix(
+        inner: T,
+        channels: u16,
+        matrix: Vec<Vec<f32>>,
+    ) -> Result<Self, AudioEngineError> {
+        let in_channels = inner.channels() as usize;
+        if in_channels == 0 || channels == 0 {
+            return Err(AudioEngineError::ZeroChannels);
+        }
+        if matrix.len() != channels as usize || matrix.iter().any(|x| x.len() != in_channels) {
+            return Err(AudioEngineError::InvalidMatrix);
+        }
+        Ok(Self {
+            inner,
+            channels,
+            matrix: Some(matrix.concat()),
+            in_buffer: Vec::new(),
+        })
+    }
+}
+impl<T: SoundSource> SoundSource for ChannelConverter<T> {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+    fn seek(&mut self, frame: u64) {
+        self.inner.seek(frame)
+    }
+    fn size_hint(&self) -> Option<u64> {
+        let in_channels = self.inner.channels() as u64;
+        let len = self.inner.size_hint()?;
+        Some(len / in_channels * self.channels as u64)
+    }
+    fn write_samples(&mut self, out_buffer: &mut [i16]) -> usize {
+        let out_channels = self.channels as usize;
+        let in_channels = self.inner.channels() as usize;
+
+        if let Some(matrix) = &self.matrix {
+            let in_buffer = {
+                let len = out_buffer.len() / out_channels * in_channels;
+                if len > self.in_buffer.len() {
+                    self.in_buffer.resize(len, 0);
+                }
+                &mut self.in_buffer[0..len]
+            };
+            let in_len = self.inner.write_samples(in_buffer);
+
+            let in_frames = in_buffer[0..in_len].chunks_exact(in_channels);
+            for (in_frame, out_frame) in in_frames.zip(out_buffer.chunks_exact_mut(out_channels)) {
+                for (out_sample, gains) in
+                    out_frame.iter_mut().zip(matrix.chunks_exact(in_channels))
+                {
+                    let sum: f32 = in_frame.iter().zip(gains).map(|(&x, g)| x as f32 * g).sum();
+                    *out_sample = sum as i16;
+                }
+            }
+            return in_len * out_channels / in_channels;
+        }
+
+        use std::cmp::Ordering;
+        match in_channels.cmp(&out_channels) {
+            Ordering::Equal => self.inner.write_samples(out_buffer),
+            Ordering::Less => {
+                // To avoid a allocation, the input samples will be written to `out_buffer`, and
+                // then converted to output samples.
+                let in_len = out_buffer.len() / out_channels * in_channels;
+                let in_len = self.inner.write_samples(&mut out_buffer[0..in_len]);
+
+                let mut sum: i32 = 0;
+                for i in (0..in_len).rev() {
+                    sum += out_buffer[i] as i32;
+                    if i % in_channels == 0 {
+                        let frame_index = i / in_channels * out_channels;
+                        let mean = (sum / in_channels as i32) as i16;
+                        for c in 0..out_channels {
+                            out_buffer[frame_index + c] = mean;
+                        }
+                        sum = 0;
+                    }
+                }
+                in_len * out_channels / in_channels
+            }
+            Ordering::Greater => {
+                // There are more input samples than output samples, so the allocation avoidance of
+                // the previous arm does not work.
+                let in_buffer = {
+                    let len = out_buffer.len() / out_channels * in_channels;
+                    if len > self.in_buffer.len() {
+                        self.in_buffer.resize(len, 0);
+                    }
+                    &mut self.in_buffer[0..len]
+                };
+                let in_len = self.inner.write_samples(in_buffer);
+
+                let mut sum: i32 = 0;
+                for (i, &in_sample) in in_buffer[0..in_len].iter().enumerate() {
+                    sum += in_sample as i32;
+                    if (i + 1) % in_channels == 0 {
+                        let frame_index = i / in_channels * out_channels;
+                        let mean = (sum / in_channels as i32) as i16;
+                        for c in 0..out_channels {
+                            out_buffer[frame_index + c] = mean;
+                        }
+                        sum = 0;
+                    }
+                }
+                in_len * out_channels / in_channels
+            }
+        }
+    }
+    fn write_samples_f32(&mut self, out_buffer: &mut [f32]) -> usize {
+        if self.inner.channels() == self.channels && self.matrix.is_none() {
+            return self.inner.write_samples_f32(out_buffer);
+        }
+        // FIXME: the conversion is only implemented for i16 samples.
+        crate::write_samples_f32_from_i16(self, out_buffer)
+    }
+}
+
+/// Do a sample rate convertion using linear interpolation, or cubic interpolation if created with
+/// [`with_quality`](SampleRateConverter::with_quality).
+///
+/// Each output frame is interpolated at its exact position in the input, so the output is not
+/// delayed relative to the input. The last frame of `inner` is held for its full duration, so a
+/// source of `n` frames is converted to `n * output_sample_rate / input_sample_rate` frames,
+/// rounded up, and looping it don't drift.
+pub struct SampleRateConverter<T: SoundSource> {
+    inner: T,
+    /// The output sample_rate
+    output_sample_rate: u32,
+    /// a buffer contained a `in_len` of input samples, that will be completelly converted in
+    /// `out_len` of ouput samples, followed by the first frames of the next buffer needed by the
+    /// interpolation.
+    in_buffer: Box<[i16]>,
+    /// The frame before the first frame of `in_buffer`, used by the cubic interpolation.
+    prev: Box<[i16]>,
+    quality: Quality,
+    out_len: usize,
+    /// The current length of valid samples in `in_buffer`.
+    len: usize,
+    /// If `inner` reached its end while filling `in_buffer`.
+    ended: bool,
+    /// If `in_buffer` needs to be filled with the first samples of `inner`, which is done lazily,
+    /// so creating or resetting the converter don't read from `inner`.
+    empty: bool,
+    /// The index of the next sample to be generated in the `out_buffer`. `out_buffer` don't exist
+    /// in fact, and it samples are directly outputed in `write_samples`.
+    iter: usize,
+}
+impl<T: SoundSource> SampleRateConverter<T> {
+    /// Create a new SampleRateConverter.
+    ///
+    /// This will convert from the sample rate of `inner`, outputing with the given `sample_rate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `inner` has zero channels, or if any of the sample rates is zero.
+    pub fn new(inner: T, output_sample_rate: u32) -> Self {
+        Self::with_quality(inner, output_sample_rate, Quality::Linear)
+    }
+
+    /// Create a new SampleRateConverter, that uses the given interpolation.
+    ///
+    /// # Panics
+    ///
+    /// Panics like [`new`](Self::new).
+    pub fn with_quality(inner: T, output_sample_rate: u32, quality: Quality) -> Self {
+        use gcd::Gcd;
+
+        assert!(inner.channels() > 0, "the inner source has zero channels");
+        assert!(
+            inner.sample_rate() > 0 && output_sample_rate > 0,
+            "can't convert from or to a sample rate of zero"
+        );
+
+        // divide the input sample_rate and the ouput sample_rate by its gcd, to find to smallest
+        // pair of input/output buffers that can be fully converted between.
+        let gcd = inner.sample_rate().gcd(output_sample_rate) as usize;
+        let in_len = inner.sample_rate() as usize / gcd * inner.channels() as usize;
+        let out_len = output_sample_rate as usize / gcd * inner.channels() as usize;
+
+        let channels = inner.channels() as usize;
+
+        // in_buffer also contains the first frames of the next buffer.
+        let in_buffer = vec![0; in_len + quality.lookahead() * channels].into_boxed_slice();
+
+        Self {
+            len: 0,
+            in_buffer,
+            prev: vec![0; channels].into_boxed_slice(),
+            quality,
+            ended: false,
+            empty: true,
+            iter: 0,
+            out_len,
+            inner,
+            output_sample_rate,
+        }
+    }
+
+    /// The number of samples that were already read from `inner`, but not fully outputed yet.
+    ///
+    /// This is how much the read position of `inner` is ahead of the output, because `inner` is
+    /// read in chunks. The interpolation itself don't add any latency, so this can be used to
+    /// know the position of the output in `inner`.
+    pub fn latency_samples(&self) -> usize {
+        let channels = self.inner.channels() as usize;
+        if self.empty || self.output_sample_rate == self.inner.sample_rate() {
+            return 0;
+        }
+        let in_len = self.in_len();
+        let j = (self.iter / channels) * in_len / self.out_len;
+        self.len.saturating_sub(j * channels)
+    }
+
+    /// The number of samples of `in_buffer` that are converted in each cycle.
+    fn in_len(&self) -> usize {
+        self.in_buffer.len() - self.quality.lookahead() * self.inner.channels() as usize
+    }
+
+    /// Fill `in_buffer` with the next samples of `inner`, starting the conversion from them.
+    fn fill_first_buffer(&mut self) {
+        self.len = self.inner.write_samples(&mut self.in_buffer[..]);
+        self.ended = self.len < self.in_buffer.len();
+        self.empty = false;
+        self.iter = 0;
+        // before the first frame, the first frame is held
+        let channels = self.prev.len();
+        if self.len >= channels {
+            self.prev.copy_from_slice(&self.in_buffer[..channels]);
+        }
+    }
+
+    /// Move the last frames of `in_buffer` to its start, and fill the rest with the next samples
+    /// of `inner`.
+    fn refill(&mut self, in_len: usize) {
+        let channels = self.prev.len();
+        self.prev
+            .copy_from_slice(&self.in_buffer[in_len - channels..in_len]);
+        // less than the lookahead is carried if `inner` already ended
+        let carried = self.len - in_len;
+        self.in_buffer.copy_within(in_len..self.len, 0);
+        let read = if self.ended {
+            0
+        } else {
+            let start = self.in_buffer.len() - in_len;
+            self.inner.write_samples(&mut self.in_buffer[start..])
+        };
+        self.len = carried + read;
+        self.ended = self.ended || read < in_len;
+        self.iter = 0;
+    }
+}
+impl<T: SoundSource> SoundSource for SampleRateConverter<T> {
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.output_sample_rate
+    }
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.empty = true;
+    }
+    fn seek(&mut self, frame: u64) {
+        // Computed in u128, to not overflow for far positions.
+        let in_rate = self.inner.sample_rate() as u128;
+        let frame = frame as u128 * in_rate / self.output_sample_rate as u128;
+        self.inner.seek(frame.min(u64::MAX as u128) as u64);
+        self.empty = true;
+    }
+    fn size_hint(&self) -> Option<u64> {
+        let channels = self.inner.channels() as u64;
+        let frames = self.inner.size_hint()? / channels;
+        let frames = frames * self.output_sample_rate as u64 / self.inner.sample_rate() as u64;
+        Some(frames * channels)
+    }
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let channels = self.inner.channels() as usize;
+
+        if self.output_sample_rate == self.inner.sample_rate() {
+            return self.inner.write_samples(buffer);
+        }
+
+        if self.empty {
+            self.fill_first_buffer();
+        }
+
+        fn div_up(a: usize, b: usize) -> usize {
+            a / b + !a.is_multiple_of(b) as usize
+        }
+
+        let in_len = self.in_len();
+        let mut i = 0;
+        // only whole frames are written, leaving a trailing partial frame untouched
+        while i + channels <= buffer.len() {
+            // if `inner` ended, the buffer is only converted up to the end of its last frame.
+            let last_buffer = self.ended && self.len <= in_len;
+            let curr_out_len = if last_buffer {
+                div_up(self.out_len * self.len, in_len) / channels * channels
+            } else {
+                self.out_len
+            };
+
+            // if next sample is out of bounds, reset in_buffer
+            if self.iter >= curr_out_len {
+                if last_buffer {
+                    return i;
+                }
+
+                // the last frames of the last buffer are the start of this buffer.
+                self.refill(in_len);
+                continue;
+            }
+
+            // j is the float position in in_buffer.
+            let j = ((self.iter / channels) * in_len) as f32 / self.out_len as f32;
+
+            let t = j.fract();
+            let j = j as usize * channels;
+            // past the last frame, the last frame is held.
+            let next = if j + channels < self.len {
+                j + channels
+            } else {
+                j
+            };
+
+            match self.quality {
+                Quality::Linear => {
+                    for c in 0..channels {
+                        // interpolate by t, curr and next sample
+                        buffer[i + c] = (self.in_buffer[j + c] as f32 * (1.0 - t)
+                            + self.in_buffer[next + c] as f32 * t)
+                            as i16;
+                    }
+                }
+                Quality::Cubic => {
+                    let after = if next + channels < self.len {
+                        next + channels
+                    } else {
+                        next
+                    };
+                    for c in 0..channels {
+                        let p0 = if j == 0 {
+                            self.prev[c]
+                        } else {
+                            self.in_buffer[j - channels + c]
+                        };
+                        let p = [
+                            p0 as f32,
+                            self.in_buffer[j + c] as f32,
+                            self.in_buffer[next + c] as f32,
+                            self.in_buffer[after + c] as f32,
+                        ];
+                        // saturates on overshoots
+                        buffer[i + c] = catmull_rom(p, t) as i16;
+                    }
+                }
+            }
+
+            self.iter += channels;
+            i += channels;
+        }
+
+        i
+    }
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        if self.output_sample_rate == self.inner.sample_rate() {
+            return self.inner.write_samples_f32(buffer);
+        }
+        // FIXME: the conversion is only implemented for i16 samples.
+        crate::write_samples_f32_from_i16(self, buffer)
+    }
+}
+
+/// Interpolate between `p[1]` and `p[2]` by `t`, using a Catmull-Rom spline.
+fn catmull_rom(p: [f32; 4], t: f32) -> f32 {
+    let a = 3.0 * (p[1] - p[2]) + p[3] - p[0];
+    let b = 2.0 * p[0] - 5.0 * p[1] + 4.0 * p[2] - p[3];
+    let c = p[2] - p[0];
+    p[1] + 0.5 * t * (c + t * (b + t * a))
+}
+
 #[cfg(test)]
 mod test {
     use crate::SoundSource;
 
-    use super::{ChannelConverter, SampleRateConverter};
+    use super::{ChannelConverter, Quality, SampleRateConverter};
 
     struct BufferSource {
         sample_rate: u32,
@@ -63,9 +636,10 @@ mod test {
         assert_eq!(len, output.len());
         assert_eq!(output, [7, 8, 9, 10]);
 
+        // the last frame is held for its full duration
         let len = outer.write_samples(&mut output[..]);
-        assert_eq!(len, 2);
-        assert_eq!(output[..len], [11, 12]);
+        assert_eq!(len, 4);
+        assert_eq!(output[..len], [11, 12, 12, 12]);
 
         let len = outer.write_samples(&mut output[..]);
         assert_eq!(len, 0);
@@ -94,8 +668,8 @@ mod test {
         assert_eq!(output, [6, 8, 10, 12]);
 
         let len = outer.write_samples(&mut output[..]);
-        assert_eq!(len, 1);
-        assert_eq!(output[..len], [14]);
+        assert_eq!(len, 2);
+        assert_eq!(output[..len], [14, 15]);
 
         let len = outer.write_samples(&mut output[..]);
         assert_eq!(len, 0);
@@ -131,6 +705,118 @@ mod test {
         assert_eq!(len, 0);
     }
 
+    #[test]
+    fn sample_rate_cubic() {
+        let inner = BufferSource {
+            sample_rate: 10,
+            channels: 1,
+            buffer: vec![0, 1000, 0, -1000, 0],
+            i: 0,
+        };
+        let mut outer = SampleRateConverter::with_quality(inner, 20, Quality::Cubic);
+
+        // the midpoints are (-p0 + 9 p1 + 9 p2 - p3) / 16, with the first and last frames held
+        let mut output = [0; 12];
+        let len = outer.write_samples(&mut output[..]);
+        assert_eq!(len, 10);
+        assert_eq!(
+            output[..len],
+            [0, 562, 1000, 625, 0, -625, -1000, -562, 0, 62]
+        );
+    }
+
+    #[test]
+    fn sample_rate_cubic_ramp() {
+        let inner = BufferSource {
+            sample_rate: 20,
+            channels: 2,
+            buffer: (0..10).flat_map(|x| [x * 300, -x * 300]).collect(),
+            i: 0,
+        };
+        let mut outer = SampleRateConverter::with_quality(inner, 30, Quality::Cubic);
+
+        // a ramp is interpolated exactly, away from its ends
+        let mut output = [0; 30];
+        assert_eq!(outer.write_samples(&mut output), 30);
+        for (n, frame) in output.chunks(2).enumerate().skip(2).take(9) {
+            let expected = n as i16 * 200;
+            assert!((frame[0] - expected).abs() <= 1, "{:?}", output);
+            assert!((frame[1] + expected).abs() <= 1, "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn sample_rate_keep_position() {
+        let mut inner = BufferSource {
+            sample_rate: 10,
+            channels: 1,
+            buffer: vec![0, 3, 6, 9, 12],
+            i: 0,
+        };
+
+        let mut output = [0; 2];
+        inner.write_samples(&mut output[..]);
+
+        // creating the converter don't reset the inner sound
+        let mut outer = SampleRateConverter::new(inner, 30);
+        let mut output = [0; 10];
+        let len = outer.write_samples(&mut output[..]);
+        assert_eq!(output[..len], [6, 7, 8, 9, 10, 11, 12, 12, 12]);
+    }
+
+    #[test]
+    fn sample_rate_partial_frame() {
+        let inner = BufferSource {
+            sample_rate: 10,
+            channels: 2,
+            buffer: vec![0, 0, 3, 3, 6, 6],
+            i: 0,
+        };
+        let mut outer = SampleRateConverter::new(inner, 20);
+
+        // the trailing partial frame is left untouched
+        let mut output = [-1; 5];
+        assert_eq!(outer.write_samples(&mut output), 4);
+        assert_eq!(output[4], -1);
+    }
+
+    #[test]
+    fn sample_rate_reset() {
+        let inner = BufferSource {
+            sample_rate: 20,
+            channels: 2,
+            buffer: (0..10).flat_map(|x| [x * 30, -x * 30]).collect(),
+            i: 0,
+        };
+        let mut outer = SampleRateConverter::new(inner, 30);
+
+        // resetting don't read from the inner sound
+        outer.reset();
+        assert_eq!(outer.inner.i, 0);
+        assert_eq!(outer.latency_samples(), 0);
+
+        let mut output = [0; 4];
+        outer.write_samples(&mut output);
+        assert_eq!(output, [0, 0, 20, -20]);
+        assert_eq!(outer.latency_samples(), 4);
+
+        // looping the ramp many times don't accumulate any offset
+        let mut output = vec![0; 15 * 2 * 100];
+        let mut len = 0;
+        outer.reset();
+        while len < output.len() {
+            let n = outer.write_samples(&mut output[len..]);
+            len += n;
+            if len < output.len() {
+                outer.reset();
+            }
+        }
+        for (i, frame) in output.chunks(2).enumerate() {
+            let expected = ((i % 15) * 20).min(270) as i16;
+            assert_eq!(frame, [expected, -expected], "{}", i);
+        }
+    }
+
     #[test]
     fn channels_1_3() {
         let inner = BufferSource {
@@ -231,207 +917,95 @@ mod test {
         assert_eq!(&output[..len], &[]);
         assert_eq!(len, 0);
     }
-}
 
-/// Convert a SoundSource to a diferent number of channels.
-///
-/// If the number of channels in the inner SoundSource is equal to the output number of channels,
-/// no conversion will be performed. Otherwise, each channel of the output will receive the average
-/// of all input channels.
-pub struct ChannelConverter<T: SoundSource> {
-    inner: T,
-    /// The number of channels to convert to.
-    channels: u16,
-    /// A buffer to temporary hold the input samples.
-    in_buffer: Vec<i16>,
-}
-impl<T: SoundSource> ChannelConverter<T> {
-    /// Create a new ChannelConverter.
-    ///
-    /// This will convert from the number of channels of `inner`, outputing the given number of
-    /// `channels`.
-    pub fn new(inner: T, channels: u16) -> Self {
-        Self {
-            inner,
-            channels,
-            in_buffer: Vec::new(),
-        }
-    }
-}
-impl<T: SoundSource> SoundSource for ChannelConverter<T> {
-    fn channels(&self) -> u16 {
-        self.channels
-    }
-    fn sample_rate(&self) -> u32 {
-        self.inner.sample_rate()
-    }
-    fn reset(&mut self) {
-        self.inner.reset()
-    }
-    fn write_samples(&mut self, out_buffer: &mut [i16]) -> usize {
-        let out_channels = self.channels as usize;
-        let in_channels = self.inner.channels() as usize;
-
-        use std::cmp::Ordering;
-        match in_channels.cmp(&out_channels) {
-            Ordering::Equal => self.inner.write_samples(out_buffer),
-            Ordering::Less => {
-                // To avoid a allocation, the input samples will be written to `out_buffer`, and
-                // then converted to output samples.
-                let in_len = out_buffer.len() / out_channels * in_channels;
-                let in_len = self.inner.write_samples(&mut out_buffer[0..in_len]);
-
-                let mut sum: i32 = 0;
-                for i in (0..in_len).rev() {
-                    sum += out_buffer[i] as i32;
-                    if i % in_channels == 0 {
-                        let frame_index = i / in_channels * out_channels;
-                        let mean = (sum / in_channels as i32) as i16;
-                        for c in 0..out_channels {
-                            out_buffer[frame_index + c] = mean;
-                        }
-                        sum = 0;
-                    }
-                }
-                in_len * out_channels / in_channels
-            }
-            Ordering::Greater => {
-                // There are more input samples than output samples, so the allocation avoidance of
-                // the previous arm does not work.
-                let in_buffer = {
-                    let len = out_buffer.len() / out_channels * in_channels;
-                    if len > self.in_buffer.len() {
-                        self.in_buffer.resize(len, 0);
-                    }
-                    &mut self.in_buffer[0..len]
-                };
-                let in_len = self.inner.write_samples(in_buffer);
+    #[test]
+    fn channels_matrix() {
+        let inner = BufferSource {
+            sample_rate: 30,
+            channels: 3,
+            buffer: vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
+            i: 0,
+        };
+        let matrix = vec![vec![0.0, 0.0, 1.0], vec![2.0, 0.0, 0.5]];
+        let mut output = vec![0; 6];
+        let mut outer = ChannelConverter::with_matrix(inner, 2, matrix).unwrap();
+        outer.write_samples(&mut output);
+        assert_eq!(output, [3, 3, 6, 11, 9, 18]);
 
-                let mut sum: i32 = 0;
-                for (i, &in_sample) in in_buffer[0..in_len].iter().enumerate() {
-                    sum += in_sample as i32;
-                    if (i + 1) % in_channels == 0 {
-                        let frame_index = i / in_channels * out_channels;
-                        let mean = (sum / in_channels as i32) as i16;
-                        for c in 0..out_channels {
-                            out_buffer[frame_index + c] = mean;
-                        }
-                        sum = 0;
-                    }
-                }
-                in_len * out_channels / in_channels
-            }
-        }
+        let inner = BufferSource {
+            sample_rate: 30,
+            channels: 3,
+            buffer: vec![],
+            i: 0,
+        };
+        let matrix = vec![vec![0.0, 1.0], vec![1.0, 0.0]];
+        assert!(ChannelConverter::with_matrix(inner, 2, matrix).is_err());
     }
-}
-
-/// Do a sample rate convertion using linear interpolation.
-pub struct SampleRateConverter<T: SoundSource> {
-    inner: T,
-    /// The output sample_rate
-    output_sample_rate: u32,
-    /// a buffer contained a `in_len` of input samples, that will be completelly converted in
-    /// `out_len` of ouput samples.
-    in_buffer: Box<[i16]>,
-    out_len: usize,
-    /// The current length of valid samples in `in_buffer`.
-    len: usize,
-    /// The index of the next sample to be generated in the `out_buffer`. `out_buffer` don't exist
-    /// in fact, and it samples are directly outputed in `write_samples`.
-    iter: usize,
-}
-impl<T: SoundSource> SampleRateConverter<T> {
-    /// Create a new SampleRateConverter.
-    ///
-    /// This will convert from the sample rate of `inner`, outputing with the given `sample_rate`.
-    pub fn new(inner: T, output_sample_rate: u32) -> Self {
-        use gcd::Gcd;
-
-        // divide the input sample_rate and the ouput sample_rate by its gcd, to find to smallest
-        // pair of input/output buffers that can be fully converted between.
-        let gcd = inner.sample_rate().gcd(output_sample_rate) as usize;
-        let in_len = inner.sample_rate() as usize / gcd * inner.channels() as usize;
-        let out_len = output_sample_rate as usize / gcd * inner.channels() as usize;
-
-        let channels = inner.channels() as usize;
 
-        // in_buffer also contains the first sample of the next buffer.
-        let in_buffer = vec![0; in_len + channels].into_boxed_slice();
+    #[test]
+    fn channels_downmix() {
+        use super::Downmix;
 
-        let mut this = Self {
-            len: in_buffer.len() - 1,
-            in_buffer,
-            iter: out_len,
-            out_len,
-            inner,
-            output_sample_rate,
+        let source = |channels, buffer| BufferSource {
+            sample_rate: 30,
+            channels,
+            buffer,
+            i: 0,
         };
+        let surround = vec![1000, 2000, 1000, 5000, 1000, 0];
 
-        this.reset();
-
-        this
-    }
-}
-impl<T: SoundSource> SoundSource for SampleRateConverter<T> {
-    fn channels(&self) -> u16 {
-        self.inner.channels()
-    }
-    fn sample_rate(&self) -> u32 {
-        self.output_sample_rate
-    }
-    fn reset(&mut self) {
-        self.inner.reset();
-
-        let channels = self.inner.channels() as usize;
-        self.len = self.inner.write_samples(&mut self.in_buffer[..]) - channels;
-        self.iter = 0;
-    }
-    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
-        let channels = self.inner.channels() as usize;
-
-        if self.output_sample_rate == self.inner.sample_rate() {
-            return self.inner.write_samples(buffer);
-        }
+        let mut output = vec![0; 2];
+        let inner = source(6, surround.clone());
+        let mut outer = ChannelConverter::with_downmix(inner, 2, Downmix::Mean);
+        outer.write_samples(&mut output);
+        assert_eq!(output, [1666, 1666]);
 
-        let mut i = 0;
-        while i < buffer.len() {
-            let in_len = self.in_buffer.len() - channels;
-            fn div_up(a: usize, b: usize) -> usize {
-                a / b + (a % b != 0) as usize
-            }
-            let curr_out_len = div_up(self.out_len * self.len, in_len) / channels * channels;
+        // L + 0.707 C + 0.707 Ls, R + 0.707 C + 0.707 Rs
+        let mut outer = ChannelConverter::with_downmix(source(6, surround), 2, Downmix::Itu);
+        outer.write_samples(&mut output);
+        assert_eq!(output, [2414, 2707]);
 
-            // if next sample is out of bounds, reset in_buffer
-            if self.iter >= curr_out_len {
-                // if self.len is smaller than in_len, the inner sound already finished.
-                if self.len < in_len {
-                    return i;
-                }
+        let inner = source(4, vec![1000, 2000, 0, 1000]);
+        let mut outer = ChannelConverter::with_downmix(inner, 2, Downmix::Itu);
+        outer.write_samples(&mut output);
+        assert_eq!(output, [1000, 2707]);
 
-                // the last sample of the last buffer is the start sample of this buffer.
-                self.in_buffer.copy_within(self.len.., 0);
+        // not a standard layout, so it falls back to the mean
+        let inner = source(3, vec![30, 60, 90]);
+        let mut outer = ChannelConverter::with_downmix(inner, 2, Downmix::Itu);
+        outer.write_samples(&mut output);
+        assert_eq!(output, [60, 60]);
+    }
 
-                self.len = self.inner.write_samples(&mut self.in_buffer[channels..]);
-                self.iter = 0;
-            }
+    #[test]
+    fn channels_upmix() {
+        use super::Upmix;
 
-            // j is the float position in in_buffer.
-            let j = ((self.iter / channels) * in_len) as f32 / self.out_len as f32;
+        let source = |channels, buffer| BufferSource {
+            sample_rate: 30,
+            channels,
+            buffer,
+            i: 0,
+        };
 
-            let t = j.fract();
-            let j = j as usize * channels;
+        let mut output = vec![0; 6];
+        let mut outer = ChannelConverter::with_upmix(source(1, vec![100]), 6, Upmix::Duplicate);
+        outer.write_samples(&mut output);
+        assert_eq!(output, [100; 6]);
 
-            for c in 0..channels {
-                // interpolate by t, curr and next sample
-                buffer[i + c] = (self.in_buffer[j + c] as f32 * (1.0 - t)
-                    + self.in_buffer[j + c + channels] as f32 * t)
-                    as i16;
-            }
+        let mut outer = ChannelConverter::with_upmix(source(1, vec![100]), 6, Upmix::Front);
+        outer.write_samples(&mut output);
+        assert_eq!(output, [100, 100, 0, 0, 0, 0]);
 
-            self.iter += channels;
-            i += channels;
-        }
+        let inner = source(2, vec![100, 200]);
+        let mut outer = ChannelConverter::with_upmix(inner, 6, Upmix::FrontSurround);
+        outer.write_samples(&mut output);
+        assert_eq!(output, [100, 200, 0, 0, 50, 100]);
 
-        buffer.len()
+        let mut output = vec![0; 4];
+        let inner = source(3, vec![30, 60, 90]);
+        let mut outer = ChannelConverter::with_upmix(inner, 4, Upmix::FrontSurround);
+        outer.write_samples(&mut output);
+        assert_eq!(output, [60, 60, 30, 30]);
     }
 }
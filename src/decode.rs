@@ -0,0 +1,207 @@
+use std::{
+    fmt,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::SoundSource;
+
+/// A hint of the format of some encoded sound data, given to [`decode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FormatHint<'a> {
+    /// The format is not known, and is detected from the first bytes of the data.
+    #[default]
+    Unknown,
+    /// The extension of the file, like `"ogg"` or `".wav"`. Case insensitive.
+    Extension(&'a str),
+    /// The MIME type of the data, like `"audio/ogg"`.
+    MimeType(&'a str),
+}
+impl<'a> FormatHint<'a> {
+    /// A hint from the extension of the given path.
+    pub fn from_path(path: &'a Path) -> Self {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some(x) => FormatHint::Extension(x),
+            None => FormatHint::Unknown,
+        }
+    }
+
+    fn format(self) -> Option<Format> {
+        match self {
+            FormatHint::Unknown => None,
+            FormatHint::Extension(ext) => {
+                let ext = ext.trim_start_matches('.').to_ascii_lowercase();
+                match ext.as_str() {
+                    "ogg" | "oga" => Some(Format::Ogg),
+                    "wav" | "wave" => Some(Format::Wav),
+                    _ => None,
+                }
+            }
+            FormatHint::MimeType(mime) => {
+                // ignore parameters, like in "audio/ogg; codecs=vorbis"
+                let mime = mime.split(';').next().unwrap_or("").trim();
+                let mime = mime.to_ascii_lowercase();
+                match mime.as_str() {
+                    "audio/ogg" | "audio/vorbis" | "application/ogg" => Some(Format::Ogg),
+                    "audio/wav" | "audio/wave" | "audio/x-wav" | "audio/vnd.wave" => {
+                        Some(Format::Wav)
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// The formats recognized by [`decode`], even if their feature is disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ogg,
+    Wav,
+}
+impl Format {
+    /// Detect the format from the first bytes of the data.
+    fn sniff(header: &[u8]) -> Option<Format> {
+        if header.starts_with(b"OggS") {
+            Some(Format::Ogg)
+        } else if (header.starts_with(b"RIFF") || header.starts_with(b"RF64"))
+            && header.get(8..12) == Some(b"WAVE")
+        {
+            Some(Format::Wav)
+        } else {
+            None
+        }
+    }
+
+    /// The name of the feature that enables the decoder of this format.
+    fn feature(self) -> &'static str {
+        match self {
+            Format::Ogg => "ogg",
+            Format::Wav => "wav",
+        }
+    }
+}
+
+/// The error returned by [`decode`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DecodeError {
+    /// The format of the data could not be detected.
+    UnknownFormat,
+    /// The format was detected, but the feature of its decoder is disabled. Contains the name of
+    /// the feature.
+    Unsupported(&'static str),
+    /// A error happened while detecting the format.
+    Io(io::Error),
+    /// The ogg data is invalid.
+    #[cfg(feature = "ogg")]
+    Ogg(lewton::VorbisError),
+    /// The wav data is invalid.
+    #[cfg(feature = "wav")]
+    Wav(hound::Error),
+}
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownFormat => write!(f, "unknown audio format"),
+            DecodeError::Unsupported(feature) => {
+                write!(f, "audio format requires the '{}' feature", feature)
+            }
+            DecodeError::Io(err) => write!(f, "failed to read audio data: {}", err),
+            #[cfg(feature = "ogg")]
+            DecodeError::Ogg(err) => write!(f, "failed to decode ogg: {}", err),
+            #[cfg(feature = "wav")]
+            DecodeError::Wav(err) => write!(f, "failed to decode wav: {}", err),
+        }
+    }
+}
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::UnknownFormat | DecodeError::Unsupported(_) => None,
+            DecodeError::Io(err) => Some(err),
+            #[cfg(feature = "ogg")]
+            DecodeError::Ogg(err) => Some(err),
+            #[cfg(feature = "wav")]
+            DecodeError::Wav(err) => Some(err),
+        }
+    }
+}
+impl From<io::Error> for DecodeError {
+    fn from(err: io::Error) -> Self {
+        DecodeError::Io(err)
+    }
+}
+
+/// Create a SoundSource from encoded sound data, picking the decoder from its format.
+///
+/// The format is taken from `hint` if it names a known format, or else is detected from the first
+/// bytes of the data. Only the formats whose feature is enabled can be decoded.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use audio_engine::{decode, AudioEngine, FormatHint};
+/// let path = std::path::Path::new("music.ogg");
+/// let file = std::io::BufReader::new(std::fs::File::open(path)?);
+/// let source = decode(file, FormatHint::from_path(path))?;
+///
+/// let audio_engine = AudioEngine::new()?;
+/// let mut sound = audio_engine.new_sound_boxed(source)?;
+/// sound.play();
+/// # Ok(())
+/// # }
+/// ```
+pub fn decode<R: Read + Seek + Send + 'static>(
+    mut reader: R,
+    hint: FormatHint,
+) -> Result<Box<dyn SoundSource + Send>, DecodeError> {
+    let format = match hint.format() {
+        Some(x) => x,
+        None => {
+            let start = reader.stream_position()?;
+            let mut header = Vec::with_capacity(12);
+            (&mut reader).take(12).read_to_end(&mut header)?;
+            reader.seek(SeekFrom::Start(start))?;
+            Format::sniff(&header).ok_or(DecodeError::UnknownFormat)?
+        }
+    };
+
+    match format {
+        #[cfg(feature = "ogg")]
+        Format::Ogg => Ok(Box::new(
+            crate::OggDecoder::new(reader).map_err(DecodeError::Ogg)?,
+        )),
+        #[cfg(feature = "wav")]
+        Format::Wav => Ok(Box::new(
+            crate::WavDecoder::new(reader).map_err(DecodeError::Wav)?,
+        )),
+        #[allow(unreachable_patterns)]
+        format => Err(DecodeError::Unsupported(format.feature())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Format, FormatHint};
+
+    #[test]
+    fn hint() {
+        assert_eq!(FormatHint::Extension(".OGG").format(), Some(Format::Ogg));
+        assert_eq!(FormatHint::Extension("wav").format(), Some(Format::Wav));
+        assert_eq!(FormatHint::Extension("mp4").format(), None);
+        let mime = FormatHint::MimeType("audio/ogg; codecs=vorbis");
+        assert_eq!(mime.format(), Some(Format::Ogg));
+        let path = std::path::Path::new("dir/sound.wav");
+        assert_eq!(FormatHint::from_path(path), FormatHint::Extension("wav"));
+    }
+
+    #[test]
+    fn sniff() {
+        assert_eq!(Format::sniff(b"OggS\0\x02"), Some(Format::Ogg));
+        assert_eq!(Format::sniff(b"RIFF\0\0\0\0WAVEfmt "), Some(Format::Wav));
+        assert_eq!(Format::sniff(b"RIFF\0\0\0\0AVI "), None);
+        assert_eq!(Format::sniff(b"Og"), None);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::SoundSource;
+
+/// A effect that processes a buffer of samples in place.
+///
+/// A effect can process the sub-mix of a group of sounds, set with
+/// [`AudioEngine::set_group_effect`](crate::AudioEngine::set_group_effect), or the samples of a
+/// single source, in a [`EffectChain`]. The built-in effects are
+/// [`TremoloEffect`](crate::TremoloEffect), [`VibratoEffect`](crate::VibratoEffect),
+/// [`BitCrusherEffect`](crate::BitCrusherEffect), [`BiquadFilterEffect`](crate::BiquadFilterEffect)
+/// and [`DelayEffect`](crate::DelayEffect), each also available as a SoundSource that wraps
+/// another source, like [`Tremolo`](crate::Tremolo).
+///
+/// For groups, the sounds of the group are mixed together in their own buffer, which is processed
+/// by the effect before being added to the output. The effect is called in the audio thread, on
+/// every buffer, even while no sound of its group is playing, so the tail of effects like reverb
+/// is not cut.
+pub trait Effect {
+    /// Process the given buffer of interleaved samples, in place.
+    ///
+    /// The samples are nominally in the range `-1.0..=1.0`, but may exceed it. The number of
+    /// channels and the sample rate are the ones of the output, and may change between calls.
+    fn process(&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32);
+
+    /// Clear the state of the effect, like the samples buffered by a delay.
+    ///
+    /// Called when the source of a [`EffectChain`] is reset. Does nothing by default.
+    fn reset(&mut self) {}
+}
+
+/// A SoundSource that processes the samples of another source through a chain of effects.
+///
+/// The effects are applied in the order they were added, and are [reset](Effect::reset) together
+/// with the source, including when it seeks.
+pub struct EffectChain<T: SoundSource> {
+    inner: T,
+    effects: Vec<Box<dyn Effect + Send>>,
+    /// A buffer to process the samples of `inner` in `f32`.
+    buffer: Vec<f32>,
+}
+impl<T: SoundSource> EffectChain<T> {
+    /// Create a new EffectChain, without any effect.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            effects: Vec::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Add a effect to the end of the chain.
+    pub fn push(&mut self, effect: Box<dyn Effect + Send>) {
+        self.effects.push(effect);
+    }
+
+    /// Add a effect to the end of the chain, returning the chain.
+    pub fn with(mut self, effect: impl Effect + Send + 'static) -> Self {
+        self.push(Box::new(effect));
+        self
+    }
+
+    /// The effects of the chain, in the order they are applied.
+    pub fn effects_mut(&mut self) -> &mut Vec<Box<dyn Effect + Send>> {
+        &mut self.effects
+    }
+}
+impl<T: SoundSource> SoundSource for EffectChain<T> {
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        self.inner.size_hint()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        for effect in &mut self.effects {
+            effect.reset();
+        }
+    }
+
+    fn seek(&mut self, frame: u64) {
+        self.inner.seek(frame);
+        for effect in &mut self.effects {
+            effect.reset();
+        }
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        // Only allocates if the buffer is larger than any previous one.
+        let mut samples = std::mem::take(&mut self.buffer);
+        samples.resize(buffer.len(), 0.0);
+        let len = self.write_samples_f32(&mut samples);
+        for (out, x) in buffer.iter_mut().zip(&samples[..len]) {
+            *out = <i16 as cpal::Sample>::from(x);
+        }
+        self.buffer = samples;
+        len
+    }
+
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        let len = self.inner.write_samples_f32(buffer);
+        let (channels, sample_rate) = (self.inner.channels(), self.inner.sample_rate());
+        for effect in &mut self.effects {
+            effect.process(&mut buffer[..len], channels, sample_rate);
+        }
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{SliceSource, SoundSource};
+
+    use super::{Effect, EffectChain};
+
+    struct Gain(f32);
+    impl Effect for Gain {
+        fn process(&mut self, buffer: &mut [f32], _: u16, _: u32) {
+            buffer.iter_mut().for_each(|x| *x *= self.0);
+        }
+    }
+
+    /// Adds the number of buffers processed since the last reset.
+    struct Count(f32);
+    impl Effect for Count {
+        fn process(&mut self, buffer: &mut [f32], _: u16, _: u32) {
+            self.0 += 1.0;
+            buffer.iter_mut().for_each(|x| *x += self.0 / 1000.0);
+        }
+
+        fn reset(&mut self) {
+            self.0 = 0.0;
+        }
+    }
+
+    #[test]
+    fn effect_chain() {
+        let samples = vec![1000, 2000, 3000, 4000];
+        let source = SliceSource::new(samples, 2, 10);
+        let mut chain = EffectChain::new(source).with(Gain(0.5)).with(Count(0.0));
+
+        // 0.001 is about 33 in i16
+        let mut buffer = [0; 2];
+        assert_eq!(chain.write_samples(&mut buffer), 2);
+        assert!((buffer[0] - 533).abs() <= 1, "{:?}", buffer);
+        assert!((buffer[1] - 1033).abs() <= 1, "{:?}", buffer);
+
+        chain.reset();
+        let mut buffer = [0.0; 6];
+        assert_eq!(chain.write_samples_f32(&mut buffer), 4);
+        let expected = 500.0 / 32767.0 + 0.001;
+        assert!((buffer[0] - expected).abs() < 1e-4, "{:?}", buffer);
+    }
+
+    #[test]
+    fn builtin_effects() {
+        let samples = vec![0x0123, 0x4567, -0x0123, 0x7fff];
+        let source = SliceSource::new(samples, 2, 10);
+        let crusher = crate::BitCrusherEffect::new(8, 1);
+        let tremolo = crate::TremoloEffect::new(1.0, 0.0);
+        let mut chain = EffectChain::new(source).with(crusher).with(tremolo);
+
+        let mut buffer = [0; 4];
+        assert_eq!(chain.write_samples(&mut buffer), 4);
+        let expected = [0x0100, 0x4500, -0x0200, 0x7f00];
+        for (a, b) in buffer.iter().zip(&expected) {
+            assert!((a - b).abs() <= 1, "{:?} != {:?}", buffer, expected);
+        }
+    }
+}
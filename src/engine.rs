@@ -1,6 +1,11 @@
 use std::{
     hash::Hash,
-    sync::{Arc, Mutex},
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
 use cpal::{
@@ -8,48 +13,81 @@ use cpal::{
     SampleRate, StreamError,
 };
 
-use super::{Mixer, Sound, SoundSource};
-use crate::converter::{ChannelConverter, SampleRateConverter};
+use super::{AudioEngineError, Effect, Mixer, PreparedSound, Sound, SoundSource, VoiceStealing};
+use crate::converter::{ChannelConverter, SampleRateConverter, Upmix};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::mixer::Mirror;
+#[cfg(feature = "wav")]
+use crate::mixer::Recording;
+use crate::mixer::{GroupKey, Levels, MixerHandle};
+use crate::time;
 
 use backend::Backend;
 
 #[cfg(not(target_arch = "wasm32"))]
 mod backend {
-    use super::create_device;
-    use crate::Mixer;
+    use super::{
+        create_device, emit, MixerSlot, StreamEvent, StreamListener, StreamOptions,
+        UnderrunMonitor, UNDERRUN_REPORT_INTERVAL,
+    };
+    use crate::{mixer::MixerHandle, AudioEngineError};
     use std::{
         hash::Hash,
-        sync::{Arc, Mutex},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc::RecvTimeoutError,
+            Arc,
+        },
     };
 
     struct StreamEventLoop<G: Eq + Hash + Send + 'static> {
-        mixer: Arc<Mutex<Mixer<G>>>,
+        slot: Arc<MixerSlot>,
+        handle: MixerHandle<G>,
+        options: StreamOptions,
+        monitor: UnderrunMonitor,
         stream: Option<cpal::platform::Stream>,
+        listener: StreamListener,
+        running: Arc<AtomicBool>,
+        /// The name of the device of the current stream.
+        device_name: Option<String>,
     }
 
     impl<G: Eq + Hash + Send + 'static> StreamEventLoop<G> {
         fn run(
             &mut self,
-            event_channel: std::sync::mpsc::Sender<StreamEvent>,
-            stream_event_receiver: std::sync::mpsc::Receiver<StreamEvent>,
+            event_channel: std::sync::mpsc::Sender<BackendEvent>,
+            stream_event_receiver: std::sync::mpsc::Receiver<BackendEvent>,
         ) {
             // Trigger first device creation
-            event_channel.send(StreamEvent::RecreateStream).unwrap();
+            event_channel.send(BackendEvent::RecreateStream).unwrap();
 
             let mut handled = false;
-            let error_callback = move |err| {
+            let listener = self.listener.clone();
+            let running = self.running.clone();
+            let error_callback = move |err: cpal::StreamError| {
                 log::error!("stream error: {}", err);
                 if !handled {
                     // The Stream could have send multiple errors. I confirmed this happening on
                     // android (a error before the stream close, and a error after closing it).
                     handled = true;
-                    event_channel.send(StreamEvent::RecreateStream).unwrap()
+                    running.store(false, Ordering::Relaxed);
+                    emit(&listener, StreamEvent::Error(err.to_string()));
+                    event_channel.send(BackendEvent::RecreateStream).unwrap()
                 }
             };
 
-            while let Ok(event) = stream_event_receiver.recv() {
+            loop {
+                let event = stream_event_receiver.recv_timeout(UNDERRUN_REPORT_INTERVAL);
+                self.monitor.report();
+                // drop the values removed from the mixer, even if no command is being sent
+                self.handle.collect_garbage();
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                };
                 match event {
-                    StreamEvent::RecreateStream => {
+                    BackendEvent::RecreateStream => {
                         log::debug!("recreating audio device");
 
                         // Droping the stream is unsound in android, see:
@@ -60,17 +98,44 @@ mod backend {
                         #[cfg(not(target_os = "android"))]
                         drop(self.stream.take());
 
-                        let stream = create_device(&self.mixer, error_callback.clone());
-                        let stream = match stream {
+                        let previous_config = (self.handle.sample_rate(), self.handle.channels());
+                        let stream = create_device(
+                            &self.slot,
+                            &self.options,
+                            &self.monitor,
+                            error_callback.clone(),
+                        );
+                        let (stream, device_name) = match stream {
                             Ok(x) => x,
                             Err(x) => {
                                 log::error!("creating audio device failed: {}", x);
-                                return;
+                                self.running.store(false, Ordering::Relaxed);
+                                emit(&self.listener, StreamEvent::Failed(x.to_string()));
+                                // Keep handling events, so the stream creation can be retried.
+                                continue;
                             }
                         };
                         self.stream = Some(stream);
+                        self.running.store(true, Ordering::Relaxed);
+
+                        let previous = self.device_name.replace(device_name.clone());
+                        if let Some(previous) = previous {
+                            emit(&self.listener, StreamEvent::Recreated);
+                            if previous != device_name {
+                                emit(&self.listener, StreamEvent::DeviceChanged(device_name));
+                            }
+                            let (sample_rate, channels) =
+                                (self.handle.sample_rate(), self.handle.channels());
+                            if previous_config != (sample_rate, channels) {
+                                let event = StreamEvent::ConfigChanged {
+                                    sample_rate,
+                                    channels,
+                                };
+                                emit(&self.listener, event);
+                            }
+                        }
                     }
-                    StreamEvent::Drop => {
+                    BackendEvent::Drop => {
                         // Droping the stream is unsound in android, see:
                         // https://github.com/katyo/oboe-rs/issues/41
                         #[cfg(target_os = "android")]
@@ -83,27 +148,38 @@ mod backend {
         }
     }
 
-    enum StreamEvent {
+    enum BackendEvent {
         RecreateStream,
         Drop,
     }
 
     pub struct Backend {
         join: Option<std::thread::JoinHandle<()>>,
-        sender: std::sync::mpsc::Sender<StreamEvent>,
+        sender: std::sync::mpsc::Sender<BackendEvent>,
     }
     impl Backend {
         pub(super) fn start<G: Eq + Hash + Send + 'static>(
-            mixer: Arc<Mutex<Mixer<G>>>,
-        ) -> Result<Self, &'static str> {
-            let (sender, receiver) = std::sync::mpsc::channel::<StreamEvent>();
+            slot: Arc<MixerSlot>,
+            handle: MixerHandle<G>,
+            options: StreamOptions,
+            listener: StreamListener,
+            running: Arc<AtomicBool>,
+            monitor: UnderrunMonitor,
+        ) -> Result<Self, AudioEngineError> {
+            let (sender, receiver) = std::sync::mpsc::channel::<BackendEvent>();
             let join = {
                 let sender = sender.clone();
                 std::thread::spawn(move || {
                     log::trace!("starting thread");
                     StreamEventLoop {
-                        mixer,
+                        slot,
+                        handle,
+                        options,
+                        monitor,
                         stream: None,
+                        listener,
+                        running,
+                        device_name: None,
                     }
                     .run(sender, receiver)
                 })
@@ -113,55 +189,705 @@ mod backend {
                 sender,
             })
         }
+
+        pub(super) fn reload(&self) {
+            let _ = self.sender.send(BackendEvent::RecreateStream);
+        }
     }
 
     impl Drop for Backend {
         fn drop(&mut self) {
-            self.sender.send(StreamEvent::Drop).unwrap();
+            self.sender.send(BackendEvent::Drop).unwrap();
             self.join.take().unwrap().join().unwrap();
         }
     }
 }
 #[cfg(target_arch = "wasm32")]
 mod backend {
-    use super::create_device;
-    use crate::Mixer;
+    use super::{
+        create_device, emit, MixerSlot, StreamEvent, StreamListener, StreamOptions, UnderrunMonitor,
+    };
+    use crate::{mixer::MixerHandle, AudioEngineError};
     use std::{
+        cell::RefCell,
         hash::Hash,
-        sync::{Arc, Mutex},
+        rc::Rc,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
     };
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    /// Create a new stream, returning it and the sample rate and number of channels of the mixer.
+    type RecreateFn = Box<dyn Fn() -> Result<(cpal::Stream, (u32, u16)), AudioEngineError>>;
+
+    /// The events that count as a user gesture, allowing a `AudioContext` to be resumed.
+    const GESTURE_EVENTS: [&str; 3] = ["click", "touchstart", "keydown"];
+
+    /// A event listener that resumes the `AudioContext` on the first user gesture.
+    struct GestureListener {
+        _closure: Closure<dyn FnMut()>,
+        /// The function registered as listener, or `None` if it was already removed.
+        function: Rc<RefCell<Option<js_sys::Function>>>,
+        document: web_sys::Document,
+    }
+    impl GestureListener {
+        fn remove(&self) {
+            if let Some(function) = self.function.borrow_mut().take() {
+                remove_listeners(&self.document, &function);
+            }
+        }
+    }
+    impl Drop for GestureListener {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    fn remove_listeners(document: &web_sys::Document, function: &js_sys::Function) {
+        for event in GESTURE_EVENTS {
+            let _ = document.remove_event_listener_with_callback(event, function);
+        }
+    }
 
     pub struct Backend {
         _stream: cpal::Stream,
+        recreate: RecreateFn,
+        /// The sample rate and number of channels of the current stream.
+        config: (u32, u16),
+        listener: StreamListener,
+        running: Arc<AtomicBool>,
+        gesture_listener: Option<GestureListener>,
     }
     impl Backend {
         pub(super) fn start<G: Eq + Hash + Send + 'static>(
-            mixer: Arc<Mutex<Mixer<G>>>,
-        ) -> Result<Self, &'static str> {
+            slot: Arc<MixerSlot>,
+            handle: MixerHandle<G>,
+            options: StreamOptions,
+            listener: StreamListener,
+            running: Arc<AtomicBool>,
+            monitor: UnderrunMonitor,
+        ) -> Result<Self, AudioEngineError> {
             // On Wasm backend, I cannot created a second thread to handle stream errors, but
             // errors in the wasm backend (AudioContext) is unexpected. In fact, cpal doesn't create
             // any StreamError in its wasm backend.
-            let stream = create_device(&mixer, |err| log::error!("stream error: {err}"));
-            let stream = match stream {
+            let recreate: RecreateFn = {
+                let listener = listener.clone();
+                Box::new(move || {
+                    let listener = listener.clone();
+                    let (stream, _) = create_device(&slot, &options, &monitor, move |err| {
+                        log::error!("stream error: {err}");
+                        emit(&listener, StreamEvent::Error(err.to_string()));
+                    })?;
+                    // there is no other thread, so the values are dropped here
+                    handle.collect_garbage();
+                    Ok((stream, (handle.sample_rate(), handle.channels())))
+                })
+            };
+            let (stream, config) = match recreate() {
                 Ok(x) => x,
                 Err(x) => {
                     log::error!("creating audio device failed: {}", x);
                     return Err(x);
                 }
             };
-            Ok(Self { _stream: stream })
+            running.store(true, Ordering::Relaxed);
+            Ok(Self {
+                _stream: stream,
+                recreate,
+                config,
+                listener,
+                running,
+                gesture_listener: None,
+            })
+        }
+
+        pub(super) fn reload(&mut self) {
+            log::debug!("recreating audio device");
+            match (self.recreate)() {
+                Ok((stream, config)) => {
+                    self._stream = stream;
+                    self.running.store(true, Ordering::Relaxed);
+                    // The listener must resume the new AudioContext.
+                    let pending = self
+                        .gesture_listener
+                        .as_ref()
+                        .is_some_and(|x| x.function.borrow().is_some());
+                    if pending {
+                        self.resume_on_user_gesture();
+                    }
+                    emit(&self.listener, StreamEvent::Recreated);
+                    if config != self.config {
+                        self.config = config;
+                        let (sample_rate, channels) = config;
+                        let event = StreamEvent::ConfigChanged {
+                            sample_rate,
+                            channels,
+                        };
+                        emit(&self.listener, event);
+                    }
+                }
+                Err(x) => {
+                    log::error!("creating audio device failed: {}", x);
+                    self.running.store(false, Ordering::Relaxed);
+                    emit(&self.listener, StreamEvent::Failed(x.to_string()));
+                }
+            }
         }
 
         pub(super) fn resume(&self) {
+            if let Some(context) = self.audio_context() {
+                let _ = context.resume();
+            }
+        }
+
+        fn audio_context(&self) -> Option<web_sys::AudioContext> {
             match self._stream.as_inner() {
-                cpal::platform::StreamInner::WebAudio(x) => {
-                    let _ = x.audio_context().resume();
-                }
+                cpal::platform::StreamInner::WebAudio(x) => Some(x.audio_context().clone()),
                 #[allow(unreachable_patterns)]
-                _ => {}
+                _ => None,
+            }
+        }
+
+        pub(super) fn resume_on_user_gesture(&mut self) {
+            if let Some(listener) = self.gesture_listener.take() {
+                listener.remove();
+            }
+            let context = match self.audio_context() {
+                Some(x) => x,
+                None => return,
+            };
+            let document = match web_sys::window().and_then(|x| x.document()) {
+                Some(x) => x,
+                None => {
+                    log::warn!("no document to listen for user gestures");
+                    return;
+                }
+            };
+
+            let function: Rc<RefCell<Option<js_sys::Function>>> = Rc::default();
+            let closure = {
+                let function = function.clone();
+                let document = document.clone();
+                Closure::wrap(Box::new(move || {
+                    let _ = context.resume();
+                    if let Some(function) = function.borrow_mut().take() {
+                        remove_listeners(&document, &function);
+                    }
+                }) as Box<dyn FnMut()>)
+            };
+            let callback: &js_sys::Function = closure.as_ref().unchecked_ref();
+            for event in GESTURE_EVENTS {
+                let _ = document.add_event_listener_with_callback(event, callback);
+            }
+            *function.borrow_mut() = Some(callback.clone());
+
+            self.gesture_listener = Some(GestureListener {
+                _closure: closure,
+                function,
+                document,
+            });
+        }
+    }
+}
+
+/// What to do when a sound has a sample rate different from the output, and needs to be
+/// resampled.
+///
+/// Resampling costs CPU and lowers the quality of the sound, so it may be useful to notice it
+/// during development, to encode the assets in the sample rate of the device. See
+/// [`AudioEngineBuilder::resample_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResamplePolicy {
+    /// Resample the sound silently.
+    #[default]
+    Allow,
+    /// Resample the sound, and log a warning.
+    Warn,
+    /// Don't add the sound, returning a error instead.
+    Deny,
+}
+
+/// A event of the output stream.
+///
+/// See [`AudioEngine::on_stream_event`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StreamEvent {
+    /// The output stream was recreated, after a error or a call to
+    /// [`reload_device`](AudioEngine::reload_device).
+    Recreated,
+    /// The output stream was recreated using a different output device. Contains the name of the
+    /// new device.
+    DeviceChanged(String),
+    /// The output stream reported a error. The stream will be recreated.
+    Error(String),
+    /// Creating the output stream failed. No sound will be outputed until
+    /// [`reload_device`](AudioEngine::reload_device) succeeds.
+    Failed(String),
+    /// The output stream was recreated with a different sample rate or number of channels, so
+    /// [`AudioEngine::sample_rate`] and [`AudioEngine::channels`] changed.
+    ///
+    /// Sources created for the previous config are converted to the new one, but sources
+    /// generated at the native sample rate may need to be recreated.
+    ConfigChanged {
+        /// The new sample rate.
+        sample_rate: u32,
+        /// The new number of channels.
+        channels: u16,
+    },
+    /// Mixing a buffer took longer than the time available for it, which may cause a audible gap.
+    ///
+    /// The underruns are reported by the thread that handles the stream, up to a tenth of a second
+    /// after they happen, so the audio thread is not blocked by the listener. Multiple underruns in
+    /// that time are reported as one event, with the last of them, but each one is counted in
+    /// [`AudioEngine::underrun_count`]. See [`AudioEngineBuilder::underrun_threshold`].
+    Underrun {
+        /// The time taken to mix the buffer.
+        elapsed: Duration,
+        /// The duration of the buffer.
+        budget: Duration,
+    },
+}
+
+type StreamListener = Arc<Mutex<Option<Box<dyn FnMut(StreamEvent) + Send>>>>;
+
+/// The mixer of a [`AudioEngine`], while it is not owned by a output stream.
+///
+/// The callback of the output stream takes the mixer on its first buffer, and keeps it until the
+/// stream is dropped, so the audio thread never waits for a lock. Only the newest stream can take
+/// it.
+struct MixerSlot {
+    state: Mutex<SlotState>,
+}
+struct SlotState {
+    mixer: Option<Mixer<GroupKey>>,
+    /// The id of the newest stream.
+    stream: u64,
+}
+impl MixerSlot {
+    fn new(mixer: Mixer<GroupKey>) -> Self {
+        Self {
+            state: Mutex::new(SlotState {
+                mixer: Some(mixer),
+                stream: 0,
+            }),
+        }
+    }
+
+    /// Return the id of a new stream, that replaces the previous one.
+    fn next_stream(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.stream += 1;
+        state.stream
+    }
+
+    /// Take the mixer, waiting for the previous stream to return it.
+    fn take(&self) -> Mixer<GroupKey> {
+        loop {
+            if let Some(mixer) = self.state.lock().unwrap().mixer.take() {
+                return mixer;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Take the mixer, if it is available and `stream` is the newest stream, without waiting.
+    fn try_take(&self, stream: u64) -> Option<Mixer<GroupKey>> {
+        let mut state = self.state.try_lock().ok()?;
+        if state.stream != stream {
+            return None;
+        }
+        state.mixer.take()
+    }
+
+    fn put(&self, mixer: Mixer<GroupKey>) {
+        let mut state = self.state.lock().unwrap_or_else(|x| x.into_inner());
+        state.mixer = Some(mixer);
+    }
+}
+
+/// If the output stream returns the mixer to its [`MixerSlot`] after each buffer.
+///
+/// On Android and on the web, the callback of a stream may never be dropped: the stream is leaked
+/// on Android, because dropping it is unsound, and the callbacks of a WebAudio stream reference
+/// each other. The slot is only locked while a stream is being created, so the callback rarely
+/// misses the mixer.
+const RETURN_MIXER_EACH_BUFFER: bool = cfg!(any(target_os = "android", target_arch = "wasm32"));
+
+/// The mixer owned by the callback of a output stream.
+struct StreamMixer {
+    slot: Arc<MixerSlot>,
+    /// The id of the stream, returned by [`MixerSlot::next_stream`].
+    stream: u64,
+    mixer: Option<Mixer<GroupKey>>,
+}
+impl StreamMixer {
+    fn new(slot: Arc<MixerSlot>, stream: u64) -> Self {
+        Self {
+            slot,
+            stream,
+            mixer: None,
+        }
+    }
+
+    /// The mixer, taking it from the slot if necessary, or `None` if it is not available.
+    fn get(&mut self) -> Option<&mut Mixer<GroupKey>> {
+        if self.mixer.is_none() {
+            self.mixer = self.slot.try_take(self.stream);
+        }
+        self.mixer.as_mut()
+    }
+
+    /// Return the mixer to the slot, if it was taken.
+    fn release(&mut self) {
+        if let Some(mixer) = self.mixer.take() {
+            self.slot.put(mixer);
+        }
+    }
+
+    /// Called after each buffer is mixed.
+    fn end_buffer(&mut self) {
+        if RETURN_MIXER_EACH_BUFFER {
+            self.release();
+        }
+    }
+}
+impl Drop for StreamMixer {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+fn emit(listener: &StreamListener, event: StreamEvent) {
+    if let Some(callback) = listener.lock().unwrap().as_mut() {
+        callback(event);
+    }
+}
+
+/// How often the underruns measured in the audio thread are reported.
+#[cfg(not(target_arch = "wasm32"))]
+const UNDERRUN_REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How much of a source is decoded by [`AudioEngine::prepare`].
+const PREPARE_DURATION: Duration = Duration::from_millis(100);
+
+/// Measures how long each buffer takes to be mixed, reporting the buffers that take longer than
+/// the time available for them.
+///
+/// The audio thread only updates atomics, so logging and calling the listener, which may block,
+/// is left to [`report`](Self::report), called periodically by the thread that handles the stream.
+#[derive(Clone)]
+struct UnderrunMonitor {
+    count: Arc<AtomicU64>,
+    /// The value of `count` when the underruns were last reported.
+    reported: Arc<AtomicU64>,
+    /// The time taken to mix the last late buffer, and its duration, in nanoseconds.
+    last_elapsed: Arc<AtomicU64>,
+    last_budget: Arc<AtomicU64>,
+    /// The number of frames of the last buffer that was mixed.
+    buffer_frames: Arc<AtomicU32>,
+    /// The fraction of the duration of a buffer that mixing it can take.
+    threshold: f32,
+    listener: StreamListener,
+}
+impl UnderrunMonitor {
+    fn new(count: Arc<AtomicU64>, threshold: f32, listener: StreamListener) -> Self {
+        Self {
+            count,
+            reported: Default::default(),
+            last_elapsed: Default::default(),
+            last_budget: Default::default(),
+            buffer_frames: Default::default(),
+            threshold,
+            listener,
+        }
+    }
+
+    /// Call `mix`, that mixes a buffer with the given number of frames, measuring its duration.
+    fn measure(&self, frames: usize, sample_rate: u32, mix: impl FnOnce()) {
+        self.buffer_frames.store(frames as u32, Ordering::Relaxed);
+        // `Instant` is not available on the web.
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = (frames, sample_rate);
+            mix();
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let start = std::time::Instant::now();
+            mix();
+            let elapsed = start.elapsed();
+            let budget = crate::SampleRate(sample_rate).duration_of(frames as u64);
+            if elapsed.as_secs_f32() > budget.as_secs_f32() * self.threshold {
+                self.last_elapsed
+                    .store(elapsed.as_nanos() as u64, Ordering::Relaxed);
+                self.last_budget
+                    .store(budget.as_nanos() as u64, Ordering::Relaxed);
+                self.count.fetch_add(1, Ordering::Release);
             }
         }
     }
+
+    /// Log and emit the underruns measured since the last call, if any.
+    ///
+    /// Multiple underruns are reported as a single event, with the last one.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn report(&self) {
+        let count = self.count.load(Ordering::Acquire);
+        let reported = self.reported.swap(count, Ordering::Relaxed);
+        if count == reported {
+            return;
+        }
+        let elapsed = Duration::from_nanos(self.last_elapsed.load(Ordering::Relaxed));
+        let budget = Duration::from_nanos(self.last_budget.load(Ordering::Relaxed));
+        log::warn!(
+            "{} underruns, the last one took {:?}, of {:?}",
+            count - reported,
+            elapsed,
+            budget
+        );
+        emit(&self.listener, StreamEvent::Underrun { elapsed, budget });
+    }
+}
+
+/// The options used to create the output stream.
+#[derive(Clone, Default)]
+struct StreamOptions {
+    host: Option<cpal::HostId>,
+    device: Option<String>,
+    buffer_size: Option<u32>,
+    sample_rate: Option<u32>,
+    channels: Option<u16>,
+    realtime_priority: bool,
+}
+
+/// A builder for a [`AudioEngine`], created by [`AudioEngine::builder`].
+pub struct AudioEngineBuilder<G: Eq + Hash + Send + 'static = ()> {
+    options: StreamOptions,
+    resample_policy: ResamplePolicy,
+    upmix: Upmix,
+    underrun_threshold: f32,
+    _group: PhantomData<fn() -> G>,
+}
+impl<G: Eq + Hash + Send + 'static> AudioEngineBuilder<G> {
+    /// Use the given type to represent sound groups.
+    ///
+    /// See [`AudioEngine::with_groups`].
+    pub fn groups<H: Eq + Hash + Send + 'static>(self) -> AudioEngineBuilder<H> {
+        AudioEngineBuilder {
+            options: self.options,
+            resample_policy: self.resample_policy,
+            upmix: self.upmix,
+            underrun_threshold: self.underrun_threshold,
+            _group: PhantomData,
+        }
+    }
+
+    /// Use the given audio host, instead of the default one.
+    ///
+    /// The available hosts can be queried with [`cpal::available_hosts`].
+    pub fn host(mut self, host: cpal::HostId) -> Self {
+        self.options.host = Some(host);
+        self
+    }
+
+    /// Output to the device with the given name, instead of the default output device.
+    ///
+    /// If there is no output device with this name, [`build`](Self::build) fails. The device is
+    /// also used when the output stream is recreated.
+    pub fn device(mut self, name: impl Into<String>) -> Self {
+        self.options.device = Some(name.into());
+        self
+    }
+
+    /// Request a output buffer with the given number of frames.
+    ///
+    /// Smaller buffers reduce latency, but increase the risk of underruns. The value is clamped to
+    /// the range supported by the device, and if the device still rejects it, the default is used.
+    /// By default the buffer size is chosen by the host. The size that was actually granted is
+    /// returned by [`AudioEngine::buffer_size`].
+    ///
+    /// On the web, the samples are scheduled in the `AudioContext` one buffer ahead, by the
+    /// WebAudio host of `cpal`, and the default is 2048 frames. If the page has slow frames that
+    /// cause audible gaps, a larger buffer gives the audio more time to be scheduled.
+    pub fn buffer_size(mut self, frames: u32) -> Self {
+        self.options.buffer_size = Some(frames);
+        self
+    }
+
+    /// Prefer a output config with the given sample rate, in Hertz.
+    ///
+    /// If the device does not support it, 48000 Hz or 44100 Hz are preferred, in that order. The
+    /// sample rate that was actually used can be queried with [`AudioEngine::sample_rate`].
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.options.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Prefer a output config with the given number of channels.
+    ///
+    /// If the device does not support it, stereo or mono are preferred, in that order. The number
+    /// of channels that was actually used can be queried with [`AudioEngine::channels`].
+    pub fn channels(mut self, channels: u16) -> Self {
+        self.options.channels = Some(channels);
+        self
+    }
+
+    /// Request a real-time priority for the audio thread, where the sounds are mixed.
+    ///
+    /// This reduces the risk of underruns when the system is under load. On Linux and other Unix
+    /// systems, this requires the `CAP_SYS_NICE` capability, or a `rtprio` limit set for the user.
+    /// If the system refuses it, a warning is logged and the thread keeps its normal priority. On
+    /// macOS, iOS and Android, the audio thread already has a real-time priority, and on Windows
+    /// and the web this is not supported, so this does nothing. Disabled by default.
+    pub fn realtime_priority(mut self, enabled: bool) -> Self {
+        self.options.realtime_priority = enabled;
+        self
+    }
+
+    /// Set how long mixing a buffer can take, as a fraction of the duration of the buffer, before
+    /// it is counted as a underrun.
+    ///
+    /// When mixing takes longer than the duration of the buffer, the device may run out of samples
+    /// and output a audible gap. Each buffer that exceeds this threshold is logged as a warning,
+    /// counted in [`AudioEngine::underrun_count`], and reported as a [`StreamEvent::Underrun`].
+    /// A value below 1.0 reports buffers that are close to underrunning. The default is 1.0.
+    ///
+    /// On the web, the time is not measured, and no underrun is reported.
+    pub fn underrun_threshold(mut self, threshold: f32) -> Self {
+        self.underrun_threshold = threshold;
+        self
+    }
+
+    /// Set what to do when a sound has a sample rate different from the output.
+    ///
+    /// By default, the sound is silently resampled. See [`ResamplePolicy`].
+    pub fn resample_policy(mut self, policy: ResamplePolicy) -> Self {
+        self.resample_policy = policy;
+        self
+    }
+
+    /// Set how sounds with less channels than the output are distributed to its channels.
+    ///
+    /// By default, every output channel receives the average of the channels of the sound. See
+    /// [`Upmix`].
+    pub fn upmix(mut self, upmix: Upmix) -> Self {
+        self.upmix = upmix;
+        self
+    }
+
+    /// Tries to create the AudioEngine.
+    ///
+    /// `cpal` will spawn a new thread where the sound samples will be sampled, mixed, and outputed
+    /// to the output stream.
+    pub fn build(self) -> Result<AudioEngine<G>, AudioEngineError> {
+        let mut mixer = Mixer::new(2, super::SampleRate(48000));
+        let clipped = mixer.clipped_flag();
+        let levels = mixer.levels();
+        #[cfg(feature = "wav")]
+        let recording = mixer.recording();
+        let mut handle = MixerHandle::new(&mut mixer);
+        handle.resample_policy = self.resample_policy;
+        handle.upmix = self.upmix;
+        let slot = Arc::new(MixerSlot::new(mixer));
+        let listener = StreamListener::default();
+        let running = Arc::new(AtomicBool::new(false));
+        let underruns = Arc::new(AtomicU64::new(0));
+        let monitor =
+            UnderrunMonitor::new(underruns.clone(), self.underrun_threshold, listener.clone());
+        let buffer_frames = monitor.buffer_frames.clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        let host = self.options.host;
+        let backend = Backend::start(
+            slot,
+            handle.clone(),
+            self.options,
+            listener.clone(),
+            running.clone(),
+            monitor,
+        )?;
+
+        Ok(AudioEngine::<G> {
+            mixer: handle,
+            clipped,
+            levels,
+            #[cfg(feature = "wav")]
+            recording,
+            listener,
+            running,
+            underruns,
+            buffer_frames,
+            #[cfg(not(target_arch = "wasm32"))]
+            host,
+            _backend: crate::unshared::Unshared::new(Some(backend)),
+        })
+    }
+
+    /// Create a AudioEngine without a output stream, returning it together with its
+    /// [`HeadlessMixer`].
+    ///
+    /// The mixer outputs the [sample rate](Self::sample_rate) and [number of
+    /// channels](Self::channels) that were set in this builder, or 48000 Hz and stereo by default,
+    /// and all other options are ignored. No sound is outputed until samples are requested by
+    /// calling [`write_samples`](SoundSource::write_samples) on the mixer, which also applies all
+    /// changes made through the AudioEngine and its sounds. This can be used to output to a
+    /// custom backend, or to render the audio offline.
+    pub fn build_headless(self) -> (AudioEngine<G>, HeadlessMixer) {
+        let channels = self.options.channels.unwrap_or(2);
+        let sample_rate = self.options.sample_rate.unwrap_or(48000);
+        let mut mixer = Mixer::new(channels, super::SampleRate(sample_rate));
+        let clipped = mixer.clipped_flag();
+        let levels = mixer.levels();
+        let mut handle = MixerHandle::new(&mut mixer);
+        handle.resample_policy = self.resample_policy;
+        handle.upmix = self.upmix;
+        let engine = AudioEngine::<G> {
+            mixer: handle,
+            listener: StreamListener::default(),
+            running: Arc::new(AtomicBool::new(false)),
+            clipped,
+            levels,
+            #[cfg(feature = "wav")]
+            recording: mixer.recording(),
+            underruns: Arc::new(AtomicU64::new(0)),
+            buffer_frames: Arc::new(AtomicU32::new(0)),
+            #[cfg(not(target_arch = "wasm32"))]
+            host: self.options.host,
+            _backend: crate::unshared::Unshared::new(None),
+        };
+        (engine, HeadlessMixer { mixer })
+    }
+}
+
+/// The mixer of a [headless](AudioEngine::headless) AudioEngine, driven by the caller.
+///
+/// Each call to [`write_samples`](SoundSource::write_samples) or
+/// [`write_samples_f32`](SoundSource::write_samples_f32) first applies the changes made through
+/// the AudioEngine and its sounds, and then mixes the sounds, like the output stream of a
+/// AudioEngine does.
+pub struct HeadlessMixer {
+    mixer: Mixer<GroupKey>,
+}
+impl SoundSource for HeadlessMixer {
+    fn channels(&self) -> u16 {
+        self.mixer.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate()
+    }
+
+    fn reset(&mut self) {}
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        self.mixer.write_samples(buffer)
+    }
+
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        self.mixer.write_samples_f32(buffer)
+    }
 }
 
 /// The main struct of the crate.
@@ -171,8 +897,25 @@ mod backend {
 /// Each sound is associated with a group, which is purely used by
 /// [`set_group_volume`](AudioEngine::set_group_volume), to allow mixing multiple sounds together.
 pub struct AudioEngine<G: Eq + Hash + Send + 'static = ()> {
-    mixer: Arc<Mutex<Mixer<G>>>,
-    _backend: crate::unshared::Unshared<Backend>,
+    mixer: MixerHandle<G>,
+    listener: StreamListener,
+    running: Arc<AtomicBool>,
+    /// The clipped flag of the mixer, to be read without accessing it.
+    clipped: Arc<AtomicBool>,
+    /// The levels of the output of the mixer, to be read without accessing it.
+    levels: Arc<Mutex<Levels>>,
+    /// The recording of the mixer, shared to be started and stopped outside of the audio thread.
+    #[cfg(feature = "wav")]
+    recording: Arc<Mutex<Option<Recording>>>,
+    /// The number of buffers that took too long to be mixed.
+    underruns: Arc<AtomicU64>,
+    /// The number of frames of the last buffer requested by the output stream.
+    buffer_frames: Arc<AtomicU32>,
+    /// The host used to create the output stream, and also the mirror streams.
+    #[cfg(not(target_arch = "wasm32"))]
+    host: Option<cpal::HostId>,
+    /// The backend that drives the output stream, or `None` if the engine is headless.
+    _backend: crate::unshared::Unshared<Option<Backend>>,
 }
 impl<G: Default + Eq + Hash + Send> AudioEngine<G> {
     /// Add a new Sound in the default Group.
@@ -183,17 +926,82 @@ impl<G: Default + Eq + Hash + Send> AudioEngine<G> {
     pub fn new_sound<T: SoundSource + Send + 'static>(
         &self,
         source: T,
-    ) -> Result<Sound<G>, &'static str> {
+    ) -> Result<Sound<G>, AudioEngineError> {
         self.new_sound_with_group(G::default(), source)
     }
+
+    /// Add a new Sound in the default Group, and start playing it.
+    ///
+    /// See [Self::play_once_with_group], for more information.
+    pub fn play_once<T: SoundSource + Send + 'static>(
+        &self,
+        source: T,
+    ) -> Result<Sound<G>, AudioEngineError> {
+        self.play_once_with_group(G::default(), source)
+    }
+
+    /// Play a sound in the default Group, without returning a handle to it.
+    ///
+    /// See [Self::play_detached_with_group], for more information.
+    pub fn play_detached<T: SoundSource + Send + 'static>(
+        &self,
+        source: T,
+    ) -> Result<(), AudioEngineError> {
+        self.play_detached_with_group(G::default(), source)
+    }
+
+    /// Prepare a new Sound in the default Group, decoding its first samples in a worker thread.
+    ///
+    /// See [Self::prepare_with_group], for more information.
+    pub fn prepare<T: SoundSource + Send + 'static>(
+        &self,
+        source: T,
+    ) -> Result<PreparedSound<G>, AudioEngineError> {
+        self.prepare_with_group(G::default(), source)
+    }
+
+    /// Add a new Sound in the default Group, from a already boxed `SoundSource`.
+    ///
+    /// See [Self::new_sound_boxed_with_group], for more information.
+    pub fn new_sound_boxed(
+        &self,
+        source: Box<dyn SoundSource + Send>,
+    ) -> Result<Sound<G>, AudioEngineError> {
+        self.new_sound_boxed_with_group(G::default(), source)
+    }
 }
 impl AudioEngine {
     /// Tries to create a new AudioEngine.
     ///
     /// `cpal` will spawn a new thread where the sound samples will be sampled, mixed, and outputed
     /// to the output stream.
-    pub fn new() -> Result<Self, &'static str> {
-        AudioEngine::with_groups::<()>()
+    pub fn new() -> Result<Self, AudioEngineError> {
+        AudioEngine::builder().build()
+    }
+
+    /// Return a builder, to create a AudioEngine with custom options.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), audio_engine::AudioEngineError> {
+    /// use audio_engine::AudioEngine;
+    ///
+    /// let audio_engine = AudioEngine::builder()
+    ///     .groups::<u32>()
+    ///     .buffer_size(512)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder() -> AudioEngineBuilder {
+        AudioEngineBuilder {
+            options: StreamOptions::default(),
+            resample_policy: ResamplePolicy::default(),
+            upmix: Upmix::default(),
+            underrun_threshold: 1.0,
+            _group: PhantomData,
+        }
     }
 
     /// Tries to create a new AudioEngine, with the given type to represent sound groups.
@@ -204,10 +1012,10 @@ impl AudioEngine {
     /// # Example
     ///
     /// ```no_run
-    /// # fn main() -> Result<(), &'static str> {
+    /// # fn main() -> Result<(), audio_engine::AudioEngineError> {
     /// # let my_fx = audio_engine::SineWave::new(44100, 500.0);
     /// # let my_music = audio_engine::SineWave::new(44100, 440.0);
-    /// use audio_engine::{AudioEngine, WavDecoder};
+    /// use audio_engine::AudioEngine;
     ///
     /// #[derive(Eq, Hash, PartialEq)]
     /// enum Group {
@@ -227,14 +1035,33 @@ impl AudioEngine {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn with_groups<G: Eq + Hash + Send>() -> Result<AudioEngine<G>, &'static str> {
-        let mixer = Arc::new(Mutex::new(Mixer::<G>::new(2, super::SampleRate(48000))));
-        let backend = Backend::start(mixer.clone())?;
+    pub fn with_groups<G: Eq + Hash + Send>() -> Result<AudioEngine<G>, AudioEngineError> {
+        AudioEngine::builder().groups::<G>().build()
+    }
 
-        Ok(AudioEngine::<G> {
-            mixer,
-            _backend: crate::unshared::Unshared::new(backend),
-        })
+    /// Create a AudioEngine without a output stream, whose [`HeadlessMixer`] is driven by the
+    /// caller.
+    ///
+    /// See [`AudioEngineBuilder::build_headless`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use audio_engine::{AudioEngine, SineWave, SoundSource};
+    ///
+    /// let (audio_engine, mut mixer) = AudioEngine::headless(2, 48000);
+    /// let mut sound = audio_engine.new_sound(SineWave::new(48000, 440.0)).unwrap();
+    /// sound.play();
+    ///
+    /// // in the custom output loop
+    /// let mut buffer = [0; 512];
+    /// mixer.write_samples(&mut buffer);
+    /// ```
+    pub fn headless(channels: u16, sample_rate: u32) -> (AudioEngine, HeadlessMixer) {
+        AudioEngine::builder()
+            .channels(channels)
+            .sample_rate(sample_rate)
+            .build_headless()
     }
 }
 impl<G: Eq + Hash + Send> AudioEngine<G> {
@@ -246,19 +1073,129 @@ impl<G: Eq + Hash + Send> AudioEngine<G> {
     /// called.
     #[cfg(target_arch = "wasm32")]
     pub fn resume(&mut self) {
-        self._backend.get_mut().resume()
+        if let Some(backend) = self._backend.get_mut() {
+            backend.resume()
+        }
+    }
+
+    /// Automatically resume the underlying `AudioContext` on the first user gesture.
+    ///
+    /// This adds a listener for `click`, `touchstart` and `keydown` events on the document, that
+    /// calls [`resume`](Self::resume) and removes itself. See [`resume`](Self::resume) for why
+    /// this is necessary.
+    #[cfg(target_arch = "wasm32")]
+    pub fn resume_on_user_gesture(&mut self) {
+        if let Some(backend) = self._backend.get_mut() {
+            backend.resume_on_user_gesture()
+        }
+    }
+
+    /// Recreate the output stream, using the current default output device.
+    ///
+    /// This can be used to move the playback to a new default device, like when headphones are
+    /// plugged in. All sounds are kept, and continue playing from where they were, being
+    /// converted to the sample rate and number of channels of the new device, if necessary.
+    pub fn reload_device(&mut self) {
+        if let Some(backend) = self._backend.get_mut() {
+            backend.reload()
+        }
+    }
+
+    /// Return true if there is a output stream currently playing.
+    ///
+    /// This is false if creating the output stream failed, in which case no sound will be
+    /// outputed. [`reload_device`](Self::reload_device) can be used to try creating the stream
+    /// again. This may also be briefly false right after the engine is created, or while the
+    /// stream is being recreated.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Set a callback that will be called on each event of the output stream, like when the stream
+    /// is recreated, the output device changes, or the stream fails.
+    ///
+    /// The callback is called from the thread that handles the output stream, or the audio thread
+    /// in the case of [`StreamEvent::Error`]. This replaces any previously set callback.
+    pub fn on_stream_event(&self, callback: impl FnMut(StreamEvent) + Send + 'static) {
+        *self.listener.lock().unwrap() = Some(Box::new(callback));
+    }
+
+    /// The number of buffers that took too long to be mixed since the engine was created.
+    ///
+    /// See [`AudioEngineBuilder::underrun_threshold`]. This is always 0 in a headless engine.
+    pub fn underrun_count(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    /// The number of frames of the last buffer requested by the output device.
+    ///
+    /// This is the buffer size that was actually granted, which may differ from the one requested
+    /// in [`AudioEngineBuilder::buffer_size`], and may vary between buffers on some hosts. Return
+    /// `None` if no buffer was requested yet, which is always the case in a headless engine.
+    pub fn buffer_size(&self) -> Option<u32> {
+        match self.buffer_frames.load(Ordering::Relaxed) {
+            0 => None,
+            x => Some(x),
+        }
+    }
+
+    /// Return true if the output was clipped since the last call to this method.
+    ///
+    /// See [`Mixer::clipped_since_last_check`].
+    pub fn clipped_since_last_check(&self) -> bool {
+        self.clipped.swap(false, Ordering::Relaxed)
+    }
+
+    /// The peak level of each channel of the most recently outputed buffer, where 1.0 is the
+    /// maximum value of the output.
+    ///
+    /// This can be used for a level meter, and is read without waiting for the mixer. See
+    /// [`Mixer::peak_level`].
+    pub fn peak_level(&self) -> Vec<f32> {
+        self.levels.lock().unwrap().peak()
+    }
+
+    /// The RMS (root mean square) level of each channel of the most recently outputed buffer.
+    ///
+    /// See [`peak_level`](Self::peak_level).
+    pub fn rms_level(&self) -> Vec<f32> {
+        self.levels.lock().unwrap().rms()
     }
 
     /// The sample rate that is currently being outputed to the device.
+    ///
+    /// May change when the device changes, see [`StreamEvent::ConfigChanged`].
     pub fn sample_rate(&self) -> u32 {
-        self.mixer.lock().unwrap().sample_rate()
+        self.mixer.sample_rate()
     }
 
-    /// The sample rate of the current output device.
+    /// The number of channels of the current output device.
     ///
-    /// May change when the device changes.
+    /// May change when the device changes, see [`StreamEvent::ConfigChanged`].
     pub fn channels(&self) -> u16 {
-        self.mixer.lock().unwrap().channels()
+        self.mixer.channels()
+    }
+
+    /// The number of sounds in the engine, including the sounds that are currently stopped.
+    ///
+    /// Sounds without a [`Sound`] handle are removed when they reach their end.
+    pub fn sound_count(&self) -> usize {
+        self.mixer.sound_count()
+    }
+
+    /// The number of sounds being played currently.
+    pub fn playing_count(&self) -> usize {
+        self.mixer.playing_count()
+    }
+
+    /// The number of sounds in the given group, including the sounds that are currently stopped.
+    pub fn group_sound_count(&self, group: &G) -> usize {
+        self.mixer.group_sound_count(group)
+    }
+
+    /// The number of sounds in the given group that are being played currently.
+    pub fn group_playing_count(&self, group: &G) -> usize {
+        self.mixer.group_playing_count(group)
     }
 
     /// Add a new Sound with the given Group.
@@ -267,68 +1204,405 @@ impl<G: Eq + Hash + Send> AudioEngine<G> {
     /// start playing it.
     ///
     /// If the [number of channels](SoundSource::channels) of `source` mismatch the [output number of
-    /// channel](Self::channels), `source` will be wrapped in a [`ChannelConverter`].
+    /// channel](Self::channels), `source` will be wrapped in a [`ChannelConverter`], using the
+    /// [`Upmix`] set in [`AudioEngineBuilder::upmix`].
     ///
     /// If the [sample rate](SoundSource::sample_rate) of `source` mismatch the [output
     /// sample rate](Self::sample_rate), `source` will be wrapped in a [`SampleRateConverter`].
+    /// This returns a error if the [`ResamplePolicy`] of the engine is `Deny`.
     pub fn new_sound_with_group<T: SoundSource + Send + 'static>(
         &self,
         group: G,
         source: T,
-    ) -> Result<Sound<G>, &'static str> {
-        let mut mixer = self.mixer.lock().unwrap();
-
-        log::debug!(
-            "adding sound: channels {}, sample_rate {}",
-            source.channels(),
-            mixer.channels()
-        );
+    ) -> Result<Sound<G>, AudioEngineError> {
+        let source = self.mixer.convert_source(source)?;
+        let (id, state) = self.mixer.add_sound(group, source, true);
+        Ok(Sound {
+            mixer: self.mixer.clone(),
+            id,
+            state,
+        })
+    }
 
-        let sound: Box<dyn SoundSource + Send> = if source.sample_rate() != mixer.sample_rate() {
-            if source.channels() == mixer.channels() {
-                Box::new(SampleRateConverter::new(source, mixer.sample_rate()))
-            } else {
-                Box::new(ChannelConverter::new(
-                    SampleRateConverter::new(source, mixer.sample_rate()),
-                    mixer.channels(),
-                ))
-            }
-        } else if source.channels() == mixer.channels() {
-            Box::new(source)
+    /// Add a new Sound with the given Group, from a already boxed `SoundSource`.
+    ///
+    /// Same as [`new_sound_with_group`](Self::new_sound_with_group), but if `source` don't need to
+    /// be converted, it is used directly, instead of being boxed again.
+    pub fn new_sound_boxed_with_group(
+        &self,
+        group: G,
+        source: Box<dyn SoundSource + Send>,
+    ) -> Result<Sound<G>, AudioEngineError> {
+        let source = if source.channels() == self.mixer.channels()
+            && source.sample_rate() == self.mixer.sample_rate()
+        {
+            source
         } else {
-            Box::new(ChannelConverter::new(source, mixer.channels()))
+            self.mixer.convert_source(source)?
         };
-
-        let id = mixer.add_sound(group, sound);
-        mixer.mark_to_remove(id, false);
-        drop(mixer);
-
+        let (id, state) = self.mixer.add_sound(group, source, true);
         Ok(Sound {
             mixer: self.mixer.clone(),
             id,
+            state,
         })
     }
 
+    /// Prepare a new Sound with the given Group, decoding its first samples in a worker thread.
+    ///
+    /// The source is converted as in [`new_sound_with_group`](Self::new_sound_with_group), and
+    /// its first 100 ms are decoded in a new thread, so the first buffer in which the sound plays
+    /// don't need to wait for them. Once the [`PreparedSound`] is
+    /// [ready](PreparedSound::is_ready), it can be turned into a [`Sound`] and played without
+    /// delay.
+    pub fn prepare_with_group<T: SoundSource + Send + 'static>(
+        &self,
+        group: G,
+        source: T,
+    ) -> Result<PreparedSound<G>, AudioEngineError> {
+        let source = self.mixer.convert_source(source)?;
+        let frames = time::duration_to_frames(PREPARE_DURATION, self.mixer.sample_rate());
+        Ok(PreparedSound::new(
+            self.mixer.clone(),
+            group,
+            source,
+            frames as usize,
+        ))
+    }
+
+    /// Add a new Sound with the given Group, and start playing it.
+    ///
+    /// This is the same as calling [`new_sound_with_group`](Self::new_sound_with_group) followed by
+    /// [`play`](Sound::play). The returned handle can be used to control the sound, or can be
+    /// dropped to fire and forget the sound: it keeps playing until its end, and is then removed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # #[cfg(feature = "wav")] {
+    /// # let my_wav_sound = std::io::Cursor::new(vec![]);
+    /// use audio_engine::{AudioEngine, WavDecoder};
+    ///
+    /// let audio_engine = AudioEngine::new()?;
+    /// audio_engine.play_once(WavDecoder::new(my_wav_sound)?)?;
+    /// # }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn play_once_with_group<T: SoundSource + Send + 'static>(
+        &self,
+        group: G,
+        source: T,
+    ) -> Result<Sound<G>, AudioEngineError> {
+        let mut sound = self.new_sound_with_group(group, source)?;
+        sound.play();
+        Ok(sound)
+    }
+
+    /// Play a sound with the given Group, without returning a handle to it.
+    ///
+    /// The sound plays until its end, and is then removed. Because there is no handle, the sound
+    /// can't be controlled, except by the volume of its group. Use
+    /// [`play_once_with_group`](Self::play_once_with_group) to get a handle to the sound.
+    pub fn play_detached_with_group<T: SoundSource + Send + 'static>(
+        &self,
+        group: G,
+        source: T,
+    ) -> Result<(), AudioEngineError> {
+        let source = self.mixer.convert_source(source)?;
+        let (id, _) = self.mixer.add_sound(group, source, false);
+        self.mixer.command(move |m| m.play(id));
+        Ok(())
+    }
+
+    /// Start playing all the given sounds on exactly the same sample.
+    ///
+    /// Calling [`Sound::play`] on each sound may start them in different buffers, if the audio
+    /// thread mixes a buffer in between the calls. Here all sounds are started together, at the
+    /// start of the next buffer, keeping them in sync.
+    pub fn play_together(&self, sounds: &mut [&mut Sound<G>]) {
+        let ids = sounds.iter().map(|x| x.id).collect::<Vec<_>>();
+        self.mixer.command(move |m| {
+            for id in ids {
+                m.play(id);
+            }
+        });
+    }
+
     /// Set the volume of the given group.
     ///
-    /// The volume of all sounds associated with this group is multiplied by this volume.
+    /// The volume of all sounds associated with this group is multiplied by this volume. The
+    /// volume is clamped to the range `0.0..=`[`MAX_VOLUME`](crate::MAX_VOLUME), and NaN is
+    /// treated as 0.0.
     pub fn set_group_volume(&self, group: G, volume: f32) {
-        self.mixer.lock().unwrap().set_group_volume(group, volume)
+        self.mixer.set_group_volume(group, volume)
+    }
+
+    /// Lower the volume of the `target` group while any sound of the `trigger` group is playing.
+    ///
+    /// This can be used to make the music quieter while a voice line plays, for example. The
+    /// volume of `target` is multiplied by `1.0 - amount`, where `amount` goes from 0.0 (no
+    /// ducking) to 1.0 (silence). The volume is lowered during `attack` after a sound of `trigger`
+    /// starts playing, and raised back during `release` after all sounds of `trigger` stop.
+    ///
+    /// This replaces any previous ducking between the same groups.
+    pub fn set_ducking(
+        &self,
+        trigger: G,
+        target: G,
+        amount: f32,
+        attack: Duration,
+        release: Duration,
+    ) {
+        let (trigger, target) = (self.mixer.group(trigger), self.mixer.group(target));
+        self.mixer
+            .command(move |m| m.set_ducking(trigger, target, amount, attack, release))
+    }
+
+    /// Remove the ducking set by [`set_ducking`](Self::set_ducking) between the given groups.
+    pub fn remove_ducking(&self, trigger: G, target: G) {
+        let (trigger, target) = (self.mixer.group(trigger), self.mixer.group(target));
+        self.mixer
+            .command(move |m| m.remove_ducking(&trigger, &target))
+    }
+
+    /// The volume of the given group.
+    ///
+    /// If the volume of the group was never set, this is 1.0.
+    pub fn group_volume(&self, group: &G) -> f32 {
+        self.mixer.group_volume(group)
+    }
+
+    /// Set the master volume.
+    ///
+    /// The volume of all sounds is multiplied by this volume, after their group volume. The
+    /// volume is clamped to the range `0.0..=`[`MAX_VOLUME`](crate::MAX_VOLUME), and NaN is
+    /// treated as 0.0.
+    pub fn set_master_volume(&self, volume: f32) {
+        self.mixer.set_master_volume(volume)
+    }
+
+    /// The master volume. This is 1.0 by default.
+    pub fn master_volume(&self) -> f32 {
+        self.mixer.master_volume()
+    }
+
+    /// Set a effect that processes the sub-mix of the given group, like a reverb or a equalizer.
+    ///
+    /// See [`Mixer::set_group_effect`].
+    pub fn set_group_effect(&self, group: G, effect: Box<dyn Effect + Send>) {
+        let group = self.mixer.group(group);
+        self.mixer
+            .command(move |m| m.set_group_effect(group, effect))
+    }
+
+    /// Remove the effect set by [`set_group_effect`](Self::set_group_effect).
+    pub fn remove_group_effect(&self, group: G) {
+        let group = self.mixer.group(group);
+        self.mixer.command(move |m| m.remove_group_effect(&group))
+    }
+
+    /// Set the maximum number of sounds that can be playing at the same time.
+    ///
+    /// See [`Mixer::set_max_voices`].
+    pub fn set_max_voices(&self, max_voices: usize) {
+        self.mixer.command(move |m| m.set_max_voices(max_voices))
+    }
+
+    /// Set the maximum number of sounds of the given group that can be playing at the same time.
+    ///
+    /// See [`Mixer::set_group_max_voices`].
+    pub fn set_group_max_voices(&self, group: G, max_voices: usize) {
+        let group = self.mixer.group(group);
+        self.mixer
+            .command(move |m| m.set_group_max_voices(group, max_voices))
+    }
+
+    /// Set a callback that receives each buffer of samples outputed to the device.
+    ///
+    /// The samples are interleaved, with the number of [channels](Self::channels) of the output.
+    /// See [`Mixer::set_output_tap`].
+    pub fn set_output_tap(&self, tap: impl FnMut(&[i16]) + Send + 'static) {
+        let tap = Box::new(tap);
+        self.mixer
+            .command(move |m| m.set_boxed_output_tap(Some(tap)))
+    }
+
+    /// Remove the callback set by [`set_output_tap`](Self::set_output_tap).
+    pub fn remove_output_tap(&self) {
+        self.mixer.command(|m| m.remove_output_tap())
+    }
+
+    /// Start recording the output of the engine to a wav file at the given path.
+    ///
+    /// The file is created with the current [sample rate](Self::sample_rate) and number of
+    /// [channels](Self::channels) of the engine, with 16-bit samples, and receives exactly the
+    /// samples sent to the device. The samples are written in the audio thread. If a recording is
+    /// already running, it is stopped first. If the config of the engine changes while the file
+    /// is being created, the file is created again with the new config. If it changes later, like
+    /// after a [`StreamEvent::ConfigChanged`], the recording stops.
+    #[cfg(feature = "wav")]
+    pub fn start_recording(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), AudioEngineError> {
+        self.stop_recording()?;
+        let path = path.as_ref();
+        loop {
+            let (channels, sample_rate) = (self.mixer.channels(), self.mixer.sample_rate());
+            let spec = hound::WavSpec {
+                channels,
+                sample_rate,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            // the file is created without holding the recording, to not block the audio thread
+            let recording =
+                hound::WavWriter::create(path, spec).map_err(AudioEngineError::Recording)?;
+            let mut slot = self.recording.lock().unwrap();
+            // the config is published before the mixer stops the recording, so a recording
+            // installed with the current config is always stopped by a later change
+            if self.mixer.channels() == channels && self.mixer.sample_rate() == sample_rate {
+                *slot = Some(recording);
+                return Ok(());
+            }
+            drop(slot);
+            recording.finalize().map_err(AudioEngineError::Recording)?;
+        }
     }
+
+    /// Stop the recording started by [`start_recording`](Self::start_recording), finalizing the
+    /// file.
+    ///
+    /// Does nothing if there is no recording running.
+    #[cfg(feature = "wav")]
+    pub fn stop_recording(&self) -> Result<(), AudioEngineError> {
+        let recording = self.recording.lock().unwrap().take();
+        match recording {
+            Some(recording) => recording.finalize().map_err(AudioEngineError::Recording),
+            None => Ok(()),
+        }
+    }
+
+    /// Set the policy used to choose which sound to stop when the maximum number of voices is
+    /// reached.
+    pub fn set_voice_stealing(&self, policy: VoiceStealing) {
+        self.mixer.command(move |m| m.set_voice_stealing(policy))
+    }
+
+    /// Also output the mix to the device with the given name.
+    ///
+    /// The device must support the current [sample rate](Self::sample_rate) and number of
+    /// [channels](Self::channels) of the engine, because the samples are copied without any
+    /// conversion. The copy is played while the returned [`MirrorOutput`] is alive.
+    ///
+    /// The mirror is fed by the main output stream, so it only plays while that stream is
+    /// running, and is delayed by up to one buffer of the main stream. If the config of the engine
+    /// changes, like after a [`StreamEvent::ConfigChanged`], the mirror needs to be created
+    /// again.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn mirror_to_device(&self, device: &str) -> Result<MirrorOutput, AudioEngineError> {
+        let (channels, sample_rate) = (self.mixer.channels(), self.mixer.sample_rate());
+        // hold up to 250 ms of samples
+        let max_len = (sample_rate / 4) as usize * channels as usize;
+        let mirror = Arc::new(Mutex::new(Mirror::new(max_len)));
+        let stream = create_mirror_stream(self.host, device, channels, sample_rate, &mirror)?;
+        self.mixer.command(move |m| m.add_mirror(mirror));
+        Ok(MirrorOutput { _stream: stream })
+    }
+}
+
+/// A copy of the output of a [`AudioEngine`], played in another device.
+///
+/// Created by [`AudioEngine::mirror_to_device`]. The copy stops playing when this is dropped.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct MirrorOutput {
+    _stream: cpal::Stream,
 }
 
-fn create_device<G: Eq + Hash + Send + 'static>(
-    mixer: &Arc<Mutex<Mixer<G>>>,
+/// Box the given source, wrapping it in converters if its number of channels or sample rate
+/// mismatch the given ones.
+pub(crate) fn convert_source<T: SoundSource + Send + 'static>(
+    source: T,
+    channels: u16,
+    sample_rate: u32,
+    resample_policy: ResamplePolicy,
+    upmix: Upmix,
+) -> Result<Box<dyn SoundSource + Send>, AudioEngineError> {
+    crate::check_source(&source)?;
+    log::debug!(
+        "adding sound: channels {}, sample_rate {}",
+        source.channels(),
+        source.sample_rate()
+    );
+
+    let sound: Box<dyn SoundSource + Send> = if source.sample_rate() != sample_rate {
+        match resample_policy {
+            ResamplePolicy::Allow => {}
+            ResamplePolicy::Warn => log::warn!(
+                "resampling sound from {} Hz to the output sample rate of {} Hz",
+                source.sample_rate(),
+                sample_rate
+            ),
+            ResamplePolicy::Deny => {
+                return Err(AudioEngineError::SampleRateMismatch {
+                    source: source.sample_rate(),
+                    output: sample_rate,
+                })
+            }
+        }
+        if source.channels() == channels {
+            Box::new(SampleRateConverter::new(source, sample_rate))
+        } else {
+            Box::new(ChannelConverter::with_upmix(
+                SampleRateConverter::new(source, sample_rate),
+                channels,
+                upmix,
+            ))
+        }
+    } else if source.channels() == channels {
+        Box::new(source)
+    } else {
+        Box::new(ChannelConverter::with_upmix(source, channels, upmix))
+    };
+    Ok(sound)
+}
+
+/// Create a output stream, returning it together with the name of its device.
+fn create_device(
+    slot: &Arc<MixerSlot>,
+    options: &StreamOptions,
+    monitor: &UnderrunMonitor,
     error_callback: impl FnMut(StreamError) + Send + Clone + 'static,
-) -> Result<cpal::Stream, &'static str> {
-    let host = cpal::default_host();
-    let device = host
-        .default_output_device()
-        .ok_or("no output device available")?;
+) -> Result<(cpal::Stream, String), AudioEngineError> {
+    let host = match options.host {
+        Some(id) => cpal::host_from_id(id).map_err(|_| AudioEngineError::HostUnavailable)?,
+        None => cpal::default_host(),
+    };
+    let device = match &options.device {
+        Some(name) => host
+            .output_devices()
+            .map_err(AudioEngineError::Devices)?
+            .find(|x| x.name().is_ok_and(|x| &x == name))
+            .ok_or(AudioEngineError::DeviceNotFound)?,
+        None => host
+            .default_output_device()
+            .ok_or(AudioEngineError::NoOutputDevice)?,
+    };
+    let device_name = device.name().unwrap_or_else(|_| String::from("unknown"));
+    // a stream that is still alive, like a leaked one, can't take the mixer anymore
+    let stream_id = slot.next_stream();
     let mut supported_configs_range = device
         .supported_output_configs()
-        .map_err(|_| "error while querying formats")?
+        .map_err(AudioEngineError::SupportedConfigs)?
         .map(|x| {
+            if let Some(sample_rate) = options.sample_rate.map(SampleRate) {
+                if x.min_sample_rate() <= sample_rate && sample_rate <= x.max_sample_rate() {
+                    return x.with_sample_rate(sample_rate);
+                }
+            }
+
             let sample_rate = SampleRate(48000);
             if x.min_sample_rate() <= sample_rate && sample_rate <= x.max_sample_rate() {
                 return x.with_sample_rate(sample_rate);
@@ -345,8 +1619,10 @@ fn create_device<G: Eq + Hash + Send + 'static>(
     supported_configs_range.sort_unstable_by(|a, b| {
         let key = |x: &cpal::SupportedStreamConfig| {
             (
+                Some(x.sample_rate().0) == options.sample_rate,
+                Some(x.channels()) == options.channels,
                 x.sample_rate().0 == 48000,
-                x.sample_rate().0 == 441000,
+                x.sample_rate().0 == 44100,
                 x.channels() == 2,
                 x.channels() == 1,
                 x.sample_format() == cpal::SampleFormat::I16,
@@ -355,32 +1631,66 @@ fn create_device<G: Eq + Hash + Send + 'static>(
         };
         key(a).cmp(&key(b))
     });
+    if supported_configs_range.is_empty() {
+        // cpal only reports configs with a sample format it supports (i16, u16 or f32), and all
+        // of them are handled below.
+        log::error!(
+            "output device {:?} has no config with a supported sample format",
+            device_name
+        );
+        return Err(AudioEngineError::NoSupportedConfig);
+    }
     if log::max_level() >= log::LevelFilter::Trace {
         for config in &supported_configs_range {
             log::trace!("config {:?}", config);
         }
     }
+    let mut last_error = None;
     let stream = loop {
         let config = if let Some(config) = supported_configs_range.pop() {
             config
         } else {
-            return Err("no supported config");
+            log::error!("failed to create a stream with any supported config");
+            return Err(last_error.map_or(
+                AudioEngineError::NoSupportedConfig,
+                AudioEngineError::Backend,
+            ));
         };
         let sample_format = config.sample_format();
-        let config = config.config();
-        mixer
-            .lock()
-            .unwrap()
-            .set_config(config.channels, super::SampleRate(config.sample_rate.0));
+        let buffer_size = match (options.buffer_size, config.buffer_size()) {
+            (Some(x), cpal::SupportedBufferSize::Range { min, max }) => {
+                cpal::BufferSize::Fixed(x.clamp(*min, *max))
+            }
+            (Some(x), cpal::SupportedBufferSize::Unknown) => cpal::BufferSize::Fixed(x),
+            (None, _) => cpal::BufferSize::Default,
+        };
+        let mut config = config.config();
+        config.buffer_size = buffer_size;
+        let mut mixer = slot.take();
+        mixer.set_config(config.channels, super::SampleRate(config.sample_rate.0));
+        slot.put(mixer);
 
-        let stream = {
+        let build = |config: &cpal::StreamConfig| {
             use cpal::SampleFormat::*;
+            let error_callback = error_callback.clone();
+            let device = &device;
+            let mixer = StreamMixer::new(slot.clone(), stream_id);
             match sample_format {
-                I16 => stream::<i16, G, _>(mixer, error_callback.clone(), &device, &config),
-                U16 => stream::<u16, G, _>(mixer, error_callback.clone(), &device, &config),
-                F32 => stream::<f32, G, _>(mixer, error_callback.clone(), &device, &config),
+                I16 => stream::<i16, _>(mixer, error_callback, device, config, options, monitor),
+                U16 => stream::<u16, _>(mixer, error_callback, device, config, options, monitor),
+                F32 => stream_f32(mixer, error_callback, device, config, options, monitor),
             }
         };
+        let mut stream = build(&config);
+        if let (Err(e), cpal::BufferSize::Fixed(frames)) = (&stream, &config.buffer_size) {
+            log::warn!(
+                "failed to create stream with a buffer of {} frames, using the default buffer size: {:?}",
+                frames,
+                e
+            );
+            config.buffer_size = cpal::BufferSize::Default;
+            stream = build(&config);
+        }
         let stream = match stream {
             Ok(x) => {
                 log::info!(
@@ -392,34 +1702,130 @@ fn create_device<G: Eq + Hash + Send + 'static>(
             }
             Err(e) => {
                 log::error!("failed to create stream with config {:?}: {:?}", config, e);
+                last_error = Some(e);
                 continue;
             }
         };
         stream.play().unwrap();
         break stream;
     };
+    Ok((stream, device_name))
+}
+
+/// Create a output stream in the given device that plays the samples of `mirror`.
+#[cfg(not(target_arch = "wasm32"))]
+fn create_mirror_stream(
+    host: Option<cpal::HostId>,
+    device: &str,
+    channels: u16,
+    sample_rate: u32,
+    mirror: &Arc<Mutex<Mirror>>,
+) -> Result<cpal::Stream, AudioEngineError> {
+    let host = match host {
+        Some(id) => cpal::host_from_id(id).map_err(|_| AudioEngineError::HostUnavailable)?,
+        None => cpal::default_host(),
+    };
+    let device = host
+        .output_devices()
+        .map_err(AudioEngineError::Devices)?
+        .find(|x| x.name().is_ok_and(|x| x == device))
+        .ok_or(AudioEngineError::DeviceNotFound)?;
+    let sample_rate = SampleRate(sample_rate);
+    let config = device
+        .supported_output_configs()
+        .map_err(AudioEngineError::SupportedConfigs)?
+        .filter(|x| {
+            x.channels() == channels
+                && x.min_sample_rate() <= sample_rate
+                && sample_rate <= x.max_sample_rate()
+        })
+        .max_by_key(|x| x.sample_format() == cpal::SampleFormat::I16)
+        .ok_or(AudioEngineError::NoSupportedConfig)?
+        .with_sample_rate(sample_rate);
+
+    let sample_format = config.sample_format();
+    let config = config.config();
+    let stream = {
+        use cpal::SampleFormat::*;
+        match sample_format {
+            I16 => mirror_stream::<i16>(mirror, &device, &config),
+            U16 => mirror_stream::<u16>(mirror, &device, &config),
+            F32 => mirror_stream::<f32>(mirror, &device, &config),
+        }
+    };
+    let stream = stream.map_err(|e| {
+        log::error!(
+            "failed to create mirror stream with config {:?}: {:?}",
+            config,
+            e
+        );
+        AudioEngineError::Backend(e)
+    })?;
+    log::info!(
+        "created {:?} mirror stream with config {:?}",
+        sample_format,
+        config
+    );
+    stream.play().map_err(AudioEngineError::PlayStream)?;
     Ok(stream)
 }
 
-fn stream<T, G, E>(
-    mixer: &Arc<Mutex<Mixer<G>>>,
+#[cfg(not(target_arch = "wasm32"))]
+fn mirror_stream<T: cpal::Sample>(
+    mirror: &Arc<Mutex<Mirror>>,
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let mirror = mirror.clone();
+    let mut input_buffer = Vec::new();
+    device.build_output_stream(
+        config,
+        move |output_buffer: &mut [T], _| {
+            input_buffer.clear();
+            input_buffer.resize(output_buffer.len(), 0);
+            mirror.lock().unwrap().pop(&mut input_buffer);
+            output_buffer
+                .iter_mut()
+                .zip(input_buffer.iter())
+                .for_each(|(a, b)| *a = T::from(b));
+        },
+        |err| log::error!("mirror stream error: {}", err),
+    )
+}
+
+fn stream<T, E>(
+    mut mixer: StreamMixer,
     error_callback: E,
     device: &cpal::Device,
     config: &cpal::StreamConfig,
+    options: &StreamOptions,
+    monitor: &UnderrunMonitor,
 ) -> Result<cpal::Stream, cpal::BuildStreamError>
 where
     T: cpal::Sample,
-    G: Eq + Hash + Send + 'static,
     E: FnMut(StreamError) + Send + 'static,
 {
-    let mixer = mixer.clone();
+    let monitor = monitor.clone();
+    let (channels, sample_rate) = (config.channels as usize, config.sample_rate.0);
     let mut input_buffer = Vec::new();
+    let mut promote = options.realtime_priority;
     device.build_output_stream(
         config,
         move |output_buffer: &mut [T], _| {
+            // The callback may be called in a thread spawned by the host for this stream.
+            if std::mem::take(&mut promote) {
+                crate::priority::promote_current_thread();
+            }
+            // Only allocates if the buffer is larger than any previous one.
             input_buffer.clear();
             input_buffer.resize(output_buffer.len(), 0);
-            mixer.lock().unwrap().write_samples(&mut input_buffer);
+            monitor.measure(output_buffer.len() / channels, sample_rate, || {
+                // the buffer is left silent if the mixer is not available
+                if let Some(mixer) = mixer.get() {
+                    mixer.write_samples(&mut input_buffer);
+                }
+                mixer.end_buffer();
+            });
             // convert the samples from i16 to T, and write them in the output buffer.
             output_buffer
                 .iter_mut()
@@ -430,7 +1836,103 @@ where
     )
 }
 
+/// Like [`stream`], but mix directly in `f32`, to not lose the precision of `f32` sources.
+fn stream_f32<E>(
+    mut mixer: StreamMixer,
+    error_callback: E,
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    options: &StreamOptions,
+    monitor: &UnderrunMonitor,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let monitor = monitor.clone();
+    let (channels, sample_rate) = (config.channels as usize, config.sample_rate.0);
+    let mut promote = options.realtime_priority;
+    device.build_output_stream(
+        config,
+        move |output_buffer: &mut [f32], _| {
+            if std::mem::take(&mut promote) {
+                crate::priority::promote_current_thread();
+            }
+            monitor.measure(output_buffer.len() / channels, sample_rate, || {
+                match mixer.get() {
+                    Some(mixer) => {
+                        mixer.write_samples_f32(output_buffer);
+                    }
+                    None => output_buffer.fill(0.0),
+                }
+                mixer.end_buffer();
+            });
+            for x in output_buffer.iter_mut() {
+                *x = x.clamp(-1.0, 1.0);
+            }
+        },
+        error_callback,
+    )
+}
+
 fn _assert_sync() {
     fn is_sync<T: Sync>() {}
     is_sync::<AudioEngine>();
+    is_sync::<crate::Sound>();
+    is_sync::<crate::WeakSound>();
+}
+
+/// The stream of the wasm backend is not `Send`, so the engine is only `Send` on other platforms.
+#[cfg(not(target_arch = "wasm32"))]
+fn _assert_send() {
+    fn is_send<T: Send>() {}
+    is_send::<AudioEngine>();
+    is_send::<crate::Sound>();
+    is_send::<crate::WeakSound>();
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use super::{StreamEvent, StreamListener, UnderrunMonitor};
+
+    #[test]
+    fn underrun_monitor() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let listener = StreamListener::default();
+        let events_clone = events.clone();
+        *listener.lock().unwrap() = Some(Box::new(move |x| events_clone.lock().unwrap().push(x)));
+        let mut monitor = UnderrunMonitor::new(Arc::new(AtomicU64::new(0)), 1.0, listener);
+
+        // a buffer of one second is never late
+        monitor.measure(48000, 48000, || {});
+        assert_eq!(monitor.count.load(Ordering::Relaxed), 0);
+        assert_eq!(monitor.buffer_frames.load(Ordering::Relaxed), 48000);
+
+        // any time is more than no time
+        monitor.threshold = 0.0;
+        monitor.measure(48000, 48000, || {
+            std::thread::sleep(Duration::from_millis(1))
+        });
+        monitor.measure(48000, 48000, || {
+            std::thread::sleep(Duration::from_millis(1))
+        });
+        assert_eq!(monitor.count.load(Ordering::Relaxed), 2);
+
+        // the underruns are only reported later, as a single event
+        assert!(events.lock().unwrap().is_empty());
+        monitor.report();
+        monitor.report();
+        let events = events.lock().unwrap();
+        assert!(
+            matches!(events[..], [StreamEvent::Underrun { elapsed, budget }]
+            if elapsed >= Duration::from_millis(1) && budget == Duration::from_secs(1))
+        );
+    }
 }
@@ -0,0 +1,100 @@
+use std::fmt;
+
+/// The error returned by the fallible operations of this crate.
+///
+/// The variants can be matched to tell a failure of the audio device, which may go away by
+/// retrying, from a sound that can never be played, like one with a sample rate that the engine
+/// refuses to convert.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AudioEngineError {
+    /// The requested audio host is not available on this platform.
+    HostUnavailable,
+    /// There is no default output device.
+    NoOutputDevice,
+    /// There is no default input device.
+    NoInputDevice,
+    /// There is no output device with the requested name.
+    DeviceNotFound,
+    /// Failed to list the devices of the host.
+    Devices(cpal::DevicesError),
+    /// Failed to query the configs supported by a device.
+    SupportedConfigs(cpal::SupportedStreamConfigsError),
+    /// Failed to query the default config of a device.
+    DefaultConfig(cpal::DefaultStreamConfigError),
+    /// The device has no config that the engine can use.
+    NoSupportedConfig,
+    /// Failed to create a stream. If many configs were tried, this is the error of the last one.
+    Backend(cpal::BuildStreamError),
+    /// Failed to start a stream.
+    PlayStream(cpal::PlayStreamError),
+    /// The sample rate of the sound is not the one of the output, and the
+    /// [`ResamplePolicy`](crate::ResamplePolicy) denies converting it.
+    SampleRateMismatch {
+        /// The sample rate of the sound.
+        source: u32,
+        /// The sample rate of the output.
+        output: u32,
+    },
+    /// The sound has zero channels.
+    ZeroChannels,
+    /// The sound has a sample rate of zero.
+    ZeroSampleRate,
+    /// The matrix of a [`ChannelConverter`](crate::converter::ChannelConverter) doesn't match its
+    /// number of channels.
+    InvalidMatrix,
+    /// The start of a loop region is not before its end.
+    InvalidLoopRegion,
+    /// The source is longer than the given limit.
+    SourceTooLong,
+    /// The source panicked while being decoded ahead of time.
+    SourcePanicked,
+    /// Failed to create, write or finalize the file of a recording.
+    #[cfg(feature = "wav")]
+    Recording(hound::Error),
+}
+impl fmt::Display for AudioEngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use AudioEngineError::*;
+        match self {
+            HostUnavailable => write!(f, "host unavailable"),
+            NoOutputDevice => write!(f, "no output device available"),
+            NoInputDevice => write!(f, "no input device available"),
+            DeviceNotFound => write!(f, "output device not found"),
+            Devices(err) => write!(f, "error while querying devices: {}", err),
+            SupportedConfigs(err) => write!(f, "error while querying formats: {}", err),
+            DefaultConfig(err) => write!(f, "error while querying formats: {}", err),
+            NoSupportedConfig => write!(f, "output device has no supported config"),
+            Backend(err) => write!(f, "failed to create stream: {}", err),
+            PlayStream(err) => write!(f, "failed to start stream: {}", err),
+            SampleRateMismatch { source, output } => write!(
+                f,
+                "the sample rate of the sound ({} Hz) mismatch the output sample rate ({} Hz)",
+                source, output
+            ),
+            ZeroChannels => write!(f, "the sound has zero channels"),
+            ZeroSampleRate => write!(f, "the sound has a sample rate of zero"),
+            InvalidMatrix => write!(f, "the matrix doesn't match the number of channels"),
+            InvalidLoopRegion => write!(f, "the start of the loop region is not before its end"),
+            SourceTooLong => write!(f, "the source is too long"),
+            SourcePanicked => write!(f, "the source panicked while being prepared"),
+            #[cfg(feature = "wav")]
+            Recording(err) => write!(f, "failed to record the output: {}", err),
+        }
+    }
+}
+impl std::error::Error for AudioEngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use AudioEngineError::*;
+        match self {
+            Devices(err) => Some(err),
+            SupportedConfigs(err) => Some(err),
+            DefaultConfig(err) => Some(err),
+            Backend(err) => Some(err),
+            PlayStream(err) => Some(err),
+            #[cfg(feature = "wav")]
+            Recording(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,522 @@
+use std::time::Duration;
+
+use crate::{clamp_param, Effect, SoundSource};
+
+/// The response of a [`BiquadFilterEffect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Response {
+    LowPass,
+    HighPass,
+}
+
+/// A effect that filters the frequencies of a buffer with a biquad filter.
+///
+/// A low-pass filter attenuates the frequencies above the cutoff, and a high-pass filter the
+/// frequencies below it, both with a slope of 12 dB per octave. The coefficients are the ones of
+/// the Audio EQ Cookbook, and each channel is filtered independently.
+///
+/// To filter a single source, see [`BiquadFilter`].
+pub struct BiquadFilterEffect {
+    response: Response,
+    cutoff: f32,
+    q: f32,
+    /// The sample rate that the coefficients were computed for, or 0 if they are outdated.
+    coefficients_rate: u32,
+    /// The coefficients `[b0, b1, b2, a1, a2]`, normalized by `a0`.
+    coefficients: [f32; 5],
+    /// The two delay elements of each channel.
+    state: Vec<[f32; 2]>,
+}
+impl BiquadFilterEffect {
+    /// Create a new low-pass BiquadFilterEffect, with the given cutoff frequency in Hertz and
+    /// quality factor.
+    ///
+    /// A `q` of `0.707` gives a flat response below the cutoff. See [`set_q`](Self::set_q).
+    pub fn low_pass(cutoff: f32, q: f32) -> Self {
+        Self::new(Response::LowPass, cutoff, q)
+    }
+
+    /// Create a new high-pass BiquadFilterEffect, with the given cutoff frequency in Hertz and
+    /// quality factor.
+    ///
+    /// A `q` of `0.707` gives a flat response above the cutoff. See [`set_q`](Self::set_q).
+    pub fn high_pass(cutoff: f32, q: f32) -> Self {
+        Self::new(Response::HighPass, cutoff, q)
+    }
+
+    fn new(response: Response, cutoff: f32, q: f32) -> Self {
+        let mut this = Self {
+            response,
+            cutoff: 0.0,
+            q: 0.0,
+            coefficients_rate: 0,
+            coefficients: [0.0; 5],
+            state: Vec::new(),
+        };
+        this.set_cutoff(cutoff);
+        this.set_q(q);
+        this
+    }
+
+    /// Set the cutoff frequency, in Hertz.
+    ///
+    /// The cutoff is clamped to be above 1 Hz and below half of the sample rate when filtering.
+    pub fn set_cutoff(&mut self, cutoff: f32) {
+        self.cutoff = clamp_param(cutoff, 1.0, f32::MAX);
+        self.coefficients_rate = 0;
+    }
+
+    /// The cutoff frequency, in Hertz.
+    pub fn cutoff(&self) -> f32 {
+        self.cutoff
+    }
+
+    /// Set the quality factor, clamped to the range `0.1..=20.0`.
+    ///
+    /// Higher values give a resonant peak at the cutoff frequency.
+    pub fn set_q(&mut self, q: f32) {
+        self.q = clamp_param(q, 0.1, 20.0);
+        self.coefficients_rate = 0;
+    }
+
+    /// The quality factor.
+    pub fn q(&self) -> f32 {
+        self.q
+    }
+
+    /// Compute the coefficients for the given sample rate, if they are outdated, and resize the
+    /// state to the given number of channels.
+    fn prepare(&mut self, channels: u16, sample_rate: u32) {
+        self.state.resize(channels as usize, [0.0; 2]);
+        if self.coefficients_rate == sample_rate {
+            return;
+        }
+        self.coefficients_rate = sample_rate;
+
+        let nyquist = sample_rate as f32 / 2.0;
+        let cutoff = self.cutoff.min(nyquist * 0.99);
+        let w0 = std::f32::consts::TAU * cutoff / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * self.q);
+        let (b0, b1) = match self.response {
+            Response::LowPass => ((1.0 - cos) / 2.0, 1.0 - cos),
+            Response::HighPass => ((1.0 + cos) / 2.0, -(1.0 + cos)),
+        };
+        let a0 = 1.0 + alpha;
+        self.coefficients = [
+            b0 / a0,
+            b1 / a0,
+            b0 / a0,
+            -2.0 * cos / a0,
+            (1.0 - alpha) / a0,
+        ];
+    }
+
+    fn clear_state(&mut self) {
+        for z in &mut self.state {
+            *z = [0.0; 2];
+        }
+    }
+}
+
+/// Filter a single sample, in the transposed direct form II.
+fn tick(coefficients: &[f32; 5], z: &mut [f32; 2], x: f32) -> f32 {
+    let [b0, b1, b2, a1, a2] = *coefficients;
+    let y = b0 * x + z[0];
+    z[0] = b1 * x - a1 * y + z[1];
+    z[1] = b2 * x - a2 * y;
+    y
+}
+
+impl Effect for BiquadFilterEffect {
+    fn process(&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32) {
+        self.prepare(channels, sample_rate);
+        for frame in buffer.chunks_exact_mut(channels as usize) {
+            for (x, z) in frame.iter_mut().zip(&mut self.state) {
+                *x = tick(&self.coefficients, z, *x);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.clear_state();
+    }
+}
+
+/// A SoundSource that filters the frequencies of another source with a biquad filter.
+///
+/// This applies a [`BiquadFilterEffect`] to the samples of the inner source.
+pub struct BiquadFilter<T: SoundSource> {
+    inner: T,
+    effect: BiquadFilterEffect,
+}
+impl<T: SoundSource> BiquadFilter<T> {
+    /// Create a new low-pass BiquadFilter, with the given cutoff frequency in Hertz and quality
+    /// factor.
+    ///
+    /// A `q` of `0.707` gives a flat response below the cutoff. See [`set_q`](Self::set_q).
+    pub fn low_pass(inner: T, cutoff: f32, q: f32) -> Self {
+        let effect = BiquadFilterEffect::low_pass(cutoff, q);
+        Self { inner, effect }
+    }
+
+    /// Create a new high-pass BiquadFilter, with the given cutoff frequency in Hertz and quality
+    /// factor.
+    ///
+    /// A `q` of `0.707` gives a flat response above the cutoff. See [`set_q`](Self::set_q).
+    pub fn high_pass(inner: T, cutoff: f32, q: f32) -> Self {
+        let effect = BiquadFilterEffect::high_pass(cutoff, q);
+        Self { inner, effect }
+    }
+
+    /// Set the cutoff frequency, in Hertz. See [`BiquadFilterEffect::set_cutoff`].
+    pub fn set_cutoff(&mut self, cutoff: f32) {
+        self.effect.set_cutoff(cutoff);
+    }
+
+    /// The cutoff frequency, in Hertz.
+    pub fn cutoff(&self) -> f32 {
+        self.effect.cutoff()
+    }
+
+    /// Set the quality factor. See [`BiquadFilterEffect::set_q`].
+    pub fn set_q(&mut self, q: f32) {
+        self.effect.set_q(q);
+    }
+
+    /// The quality factor.
+    pub fn q(&self) -> f32 {
+        self.effect.q()
+    }
+}
+impl<T: SoundSource> SoundSource for BiquadFilter<T> {
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        self.inner.size_hint()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.effect.clear_state();
+    }
+
+    fn seek(&mut self, frame: u64) {
+        self.inner.seek(frame);
+        self.effect.clear_state();
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let len = self.inner.write_samples(buffer);
+        let channels = self.inner.channels();
+        self.effect.prepare(channels, self.inner.sample_rate());
+        let effect = &mut self.effect;
+        for frame in buffer[..len].chunks_exact_mut(channels as usize) {
+            for (sample, z) in frame.iter_mut().zip(&mut effect.state) {
+                let y = tick(&effect.coefficients, z, cpal::Sample::to_f32(sample));
+                *sample = <i16 as cpal::Sample>::from(&y);
+            }
+        }
+        len
+    }
+
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        let len = self.inner.write_samples_f32(buffer);
+        let (channels, sample_rate) = (self.inner.channels(), self.inner.sample_rate());
+        self.effect
+            .process(&mut buffer[..len], channels, sample_rate);
+        len
+    }
+}
+
+/// A effect that adds echoes to a buffer, by mixing in a delayed copy of it.
+///
+/// The delayed signal is fed back into the delay line attenuated by the feedback, so each echo is
+/// followed by a quieter one. As a effect of a group, the echoes keep playing after the sounds of
+/// the group end.
+///
+/// To add echoes to a single source, see [`Delay`].
+pub struct DelayEffect {
+    delay: Duration,
+    feedback: f32,
+    mix: f32,
+    /// The delay line, holding `delay` worth of interleaved samples.
+    ring: Vec<f32>,
+    /// The index of the oldest sample of `ring`, which is the next to be read.
+    pos: usize,
+}
+impl DelayEffect {
+    /// Create a new DelayEffect, with the given delay between echoes, feedback and mix.
+    ///
+    /// See [`set_feedback`](Self::set_feedback) and [`set_mix`](Self::set_mix).
+    pub fn new(delay: Duration, feedback: f32, mix: f32) -> Self {
+        let mut this = Self {
+            delay,
+            feedback: 0.0,
+            mix: 0.0,
+            ring: Vec::new(),
+            pos: 0,
+        };
+        this.set_feedback(feedback);
+        this.set_mix(mix);
+        this
+    }
+
+    /// Set the time between echoes. This clears the echoes that were not played yet.
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+        self.ring.clear();
+    }
+
+    /// The time between echoes.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Set how much of the delayed signal is fed back into the delay line, clamped to the range
+    /// `0.0..=0.99`.
+    ///
+    /// A feedback of 0.0 gives a single echo, and higher values make the echoes take longer to
+    /// fade out.
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = clamp_param(feedback, 0.0, 0.99);
+    }
+
+    /// How much of the delayed signal is fed back into the delay line.
+    pub fn feedback(&self) -> f32 {
+        self.feedback
+    }
+
+    /// Set the proportion of the delayed signal in the output, clamped to the range `0.0..=1.0`.
+    ///
+    /// A mix of 0.0 outputs only the original signal, and a mix of 1.0 only the echoes.
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = clamp_param(mix, 0.0, 1.0);
+    }
+
+    /// The proportion of the delayed signal in the output.
+    pub fn mix(&self) -> f32 {
+        self.mix
+    }
+
+    /// Resize the delay line to the given format, clearing it if its length changes.
+    fn prepare(&mut self, channels: u16, sample_rate: u32) {
+        let frames = crate::SampleRate(sample_rate)
+            .samples_for(self.delay)
+            .max(1);
+        let len = frames as usize * channels as usize;
+        if self.ring.len() != len {
+            self.ring = vec![0.0; len];
+            self.pos = 0;
+        }
+    }
+
+    /// Process a single sample, returning the output sample.
+    fn tick(&mut self, x: f32) -> f32 {
+        let delayed = self.ring[self.pos];
+        self.ring[self.pos] = x + delayed * self.feedback;
+        self.pos = (self.pos + 1) % self.ring.len();
+        x * (1.0 - self.mix) + delayed * self.mix
+    }
+
+    fn clear_ring(&mut self) {
+        self.ring.iter_mut().for_each(|x| *x = 0.0);
+        self.pos = 0;
+    }
+}
+impl Effect for DelayEffect {
+    fn process(&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32) {
+        self.prepare(channels, sample_rate);
+        let len = buffer.len() / channels as usize * channels as usize;
+        for x in &mut buffer[..len] {
+            *x = self.tick(*x);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.clear_ring();
+    }
+}
+
+/// A SoundSource that adds echoes to another source, by mixing in a delayed copy of it.
+///
+/// This applies a [`DelayEffect`] to the samples of the inner source. The echoes stop together
+/// with the inner source, so to let them fade out the effect can be used in a group instead, or
+/// the inner source can be padded with silence.
+pub struct Delay<T: SoundSource> {
+    inner: T,
+    effect: DelayEffect,
+}
+impl<T: SoundSource> Delay<T> {
+    /// Create a new Delay, with the given delay between echoes, feedback and mix.
+    ///
+    /// See [`set_feedback`](Self::set_feedback) and [`set_mix`](Self::set_mix).
+    pub fn new(inner: T, delay: Duration, feedback: f32, mix: f32) -> Self {
+        let effect = DelayEffect::new(delay, feedback, mix);
+        Self { inner, effect }
+    }
+
+    /// Set the time between echoes. See [`DelayEffect::set_delay`].
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.effect.set_delay(delay);
+    }
+
+    /// The time between echoes.
+    pub fn delay(&self) -> Duration {
+        self.effect.delay()
+    }
+
+    /// Set how much of the delayed signal is fed back into the delay line. See
+    /// [`DelayEffect::set_feedback`].
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.effect.set_feedback(feedback);
+    }
+
+    /// How much of the delayed signal is fed back into the delay line.
+    pub fn feedback(&self) -> f32 {
+        self.effect.feedback()
+    }
+
+    /// Set the proportion of the delayed signal in the output. See [`DelayEffect::set_mix`].
+    pub fn set_mix(&mut self, mix: f32) {
+        self.effect.set_mix(mix);
+    }
+
+    /// The proportion of the delayed signal in the output.
+    pub fn mix(&self) -> f32 {
+        self.effect.mix()
+    }
+}
+impl<T: SoundSource> SoundSource for Delay<T> {
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        self.inner.size_hint()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.effect.clear_ring();
+    }
+
+    fn seek(&mut self, frame: u64) {
+        self.inner.seek(frame);
+        self.effect.clear_ring();
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let len = self.inner.write_samples(buffer);
+        self.effect
+            .prepare(self.inner.channels(), self.inner.sample_rate());
+        for sample in &mut buffer[..len] {
+            let y = self.effect.tick(cpal::Sample::to_f32(sample));
+            *sample = <i16 as cpal::Sample>::from(&y);
+        }
+        len
+    }
+
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        let len = self.inner.write_samples_f32(buffer);
+        let (channels, sample_rate) = (self.inner.channels(), self.inner.sample_rate());
+        self.effect
+            .process(&mut buffer[..len], channels, sample_rate);
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{Constant, Effect, SliceSource, SoundSource};
+
+    use super::{BiquadFilter, Delay, DelayEffect};
+
+    #[test]
+    fn high_pass_dc() {
+        let mut filter = BiquadFilter::high_pass(Constant::new(10000, 2, 48000), 100.0, 0.707);
+        let mut buffer = [0; 2 * 4800];
+        filter.write_samples(&mut buffer);
+        assert!(buffer[0] > 9000 && buffer[0] == buffer[1]);
+        // the DC offset decays to zero
+        filter.write_samples(&mut buffer);
+        assert!(buffer.iter().all(|x| x.abs() <= 1), "{:?}", &buffer[..4]);
+
+        // reset clears the state, so the step is seen again
+        filter.reset();
+        filter.write_samples(&mut buffer[..2]);
+        assert!(buffer[0] > 9000);
+
+        let mut buffer = [1.0; 2 * 4800];
+        filter.reset();
+        filter.write_samples_f32(&mut buffer);
+        assert!(buffer[buffer.len() - 1].abs() < 1e-3);
+    }
+
+    #[test]
+    fn low_pass_dc() {
+        let mut filter = BiquadFilter::low_pass(Constant::new(10000, 1, 48000), 1000.0, 0.707);
+        let mut buffer = [0; 4800];
+        filter.write_samples(&mut buffer);
+        assert!(buffer[0] < 1000);
+        // a low-pass keeps the DC offset
+        assert!((buffer[4799] - 10000).abs() <= 1);
+    }
+
+    #[test]
+    fn delay() {
+        let mut samples = vec![0; 16];
+        samples[0] = 16384;
+        samples[1] = -16384;
+        let inner = SliceSource::new(samples, 2, 4);
+        let mut delay = Delay::new(inner, Duration::from_millis(500), 0.5, 0.5);
+
+        // the echoes continue across buffers
+        let mut buffer = [0; 16];
+        assert_eq!(delay.write_samples(&mut buffer[..6]), 6);
+        assert_eq!(delay.write_samples(&mut buffer[6..]), 10);
+        let expected = [
+            8192, -8192, 0, 0, 8192, -8192, 0, 0, 4096, -4096, 0, 0, 2048, -2048, 0, 0,
+        ];
+        assert_eq!(buffer, expected);
+
+        // reset clears the echoes
+        delay.reset();
+        delay.write_samples(&mut buffer[..6]);
+        assert_eq!(buffer[..6], expected[..6]);
+        delay.write_samples(&mut buffer);
+        assert_eq!(buffer[..10], expected[6..]);
+    }
+
+    #[test]
+    fn delay_effect() {
+        let mut delay = DelayEffect::new(Duration::from_millis(500), 0.5, 0.5);
+
+        // the echoes keep going after the input is silent
+        let mut buffer = [1.0, 0.0, 0.0];
+        delay.process(&mut buffer, 1, 4);
+        assert_eq!(buffer, [0.5, 0.0, 0.5]);
+        let mut buffer = [0.0; 3];
+        delay.process(&mut buffer, 1, 4);
+        assert_eq!(buffer, [0.0, 0.25, 0.0]);
+
+        // reset clears the echoes
+        delay.process(&mut [1.0], 1, 4);
+        delay.reset();
+        let mut buffer = [0.0; 4];
+        delay.process(&mut buffer, 1, 4);
+        assert_eq!(buffer, [0.0; 4]);
+    }
+}
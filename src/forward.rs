@@ -0,0 +1,169 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+/// How many of the last bytes read are kept, so the decoders can seek back a little.
+const HISTORY_LEN: usize = 4096;
+
+/// A wrapper that allows a `Read`-only source, like a socket or a pipe, to be used by the
+/// decoders.
+///
+/// Seeking forward reads and discards the bytes. Seeking back is only possible within the last few
+/// kilobytes read, or, if a re-open closure was given, by opening the source again and reading it
+/// from the start. Otherwise seeking back returns an error, and the decoders cannot be reset.
+pub struct ForwardReader<R: Read> {
+    inner: R,
+    reopen: Option<Box<dyn FnMut() -> io::Result<R> + Send>>,
+    /// The last bytes read from `inner`.
+    history: VecDeque<u8>,
+    /// The number of bytes at the end of `history` that will be read again, before reading more
+    /// of `inner`.
+    replay: usize,
+    /// The current position in the stream, in bytes.
+    pos: u64,
+}
+impl<R: Read> ForwardReader<R> {
+    /// Create a new ForwardReader, that cannot seek back to the start of the stream.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            reopen: None,
+            history: VecDeque::new(),
+            replay: 0,
+            pos: 0,
+        }
+    }
+
+    /// Create a new ForwardReader, that calls `reopen` to open the source, and to open it again
+    /// each time it needs to seek back to the start of the stream.
+    pub fn with_reopen(
+        mut reopen: impl FnMut() -> io::Result<R> + Send + 'static,
+    ) -> io::Result<Self> {
+        let inner = reopen()?;
+        Ok(Self {
+            reopen: Some(Box::new(reopen)),
+            ..Self::new(inner)
+        })
+    }
+
+    /// Read and discard the given number of bytes.
+    fn skip(&mut self, mut len: u64) -> io::Result<()> {
+        let mut buffer = [0; 1024];
+        while len > 0 {
+            let n = self.read(&mut buffer[..len.min(1024) as usize])?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            len -= n as u64;
+        }
+        Ok(())
+    }
+}
+impl<R: Read> Read for ForwardReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = if self.replay > 0 {
+            let start = self.history.len() - self.replay;
+            let n = self.replay.min(buf.len());
+            for (out, x) in buf.iter_mut().zip(self.history.range(start..start + n)) {
+                *out = *x;
+            }
+            self.replay -= n;
+            n
+        } else {
+            let n = self.inner.read(buf)?;
+            self.history.extend(&buf[..n]);
+            let excess = self.history.len().saturating_sub(HISTORY_LEN);
+            self.history.drain(..excess);
+            n
+        };
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+impl<R: Read> Seek for ForwardReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(x) => x,
+            SeekFrom::Current(x) => self.pos.checked_add_signed(x).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+            })?,
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "cannot seek from the end of a Read-only source",
+                ))
+            }
+        };
+
+        if target >= self.pos {
+            self.skip(target - self.pos)?;
+        } else if self.pos - target <= (self.history.len() - self.replay) as u64 {
+            self.replay += (self.pos - target) as usize;
+            self.pos = target;
+        } else if let Some(reopen) = &mut self.reopen {
+            self.inner = reopen()?;
+            self.history.clear();
+            self.replay = 0;
+            self.pos = 0;
+            self.skip(target)?;
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cannot seek back in a Read-only source",
+            ));
+        }
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Seek, SeekFrom};
+
+    use super::ForwardReader;
+
+    #[test]
+    fn seek() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut reader = ForwardReader::new(&data[..]);
+
+        let mut buffer = [0; 4];
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, [0, 1, 2, 3]);
+
+        assert_eq!(reader.seek(SeekFrom::Current(-3)).unwrap(), 1);
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, [1, 2, 3, 4]);
+
+        assert_eq!(reader.seek(SeekFrom::Start(50)).unwrap(), 50);
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, [50, 51, 52, 53]);
+
+        assert!(reader.seek(SeekFrom::End(0)).is_err());
+    }
+
+    #[test]
+    fn reopen() {
+        static DATA: [u8; 8000] = {
+            let mut data = [0; 8000];
+            let mut i = 0;
+            while i < data.len() {
+                data[i] = i as u8;
+                i += 1;
+            }
+            data
+        };
+
+        let mut reader = ForwardReader::new(&DATA[..]);
+        reader.seek(SeekFrom::Start(6000)).unwrap();
+        assert!(reader.seek(SeekFrom::Start(10)).is_err());
+
+        let mut reader = ForwardReader::with_reopen(|| Ok(&DATA[..])).unwrap();
+        reader.seek(SeekFrom::Start(6000)).unwrap();
+        assert_eq!(reader.seek(SeekFrom::Start(10)).unwrap(), 10);
+        let mut buffer = [0; 2];
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, [10, 11]);
+    }
+}
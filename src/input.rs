@@ -0,0 +1,156 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::{AudioEngineError, SoundSource};
+
+/// How many seconds of captured audio are kept before the oldest samples start to be discarded.
+const BUFFER_SECONDS: usize = 1;
+
+/// A stream capturing audio from the default input device, like a microphone.
+///
+/// The captured samples can be read through a [`InputSource`], returned by
+/// [`source`](Self::source), which can be added to a [`AudioEngine`](crate::AudioEngine) like any
+/// other `SoundSource`. The capture stops when this is dropped.
+pub struct InputStream {
+    _stream: cpal::Stream,
+    buffer: Arc<Mutex<VecDeque<i16>>>,
+    channels: u16,
+    sample_rate: u32,
+}
+impl InputStream {
+    /// Start capturing audio from the default input device.
+    ///
+    /// The audio is captured using the default config of the device, and the samples are
+    /// converted to `i16`.
+    pub fn new() -> Result<Self, AudioEngineError> {
+        let host = cpal::default_host();
+        let device = host
+            .default_input_device()
+            .ok_or(AudioEngineError::NoInputDevice)?;
+        let config = device
+            .default_input_config()
+            .map_err(AudioEngineError::DefaultConfig)?;
+
+        let sample_format = config.sample_format();
+        let config = config.config();
+        let capacity = BUFFER_SECONDS * config.sample_rate.0 as usize * config.channels as usize;
+        let buffer = Arc::new(Mutex::new(VecDeque::with_capacity(capacity)));
+
+        let stream = {
+            use cpal::SampleFormat::*;
+            match sample_format {
+                I16 => stream::<i16>(&device, &config, buffer.clone(), capacity),
+                U16 => stream::<u16>(&device, &config, buffer.clone(), capacity),
+                F32 => stream::<f32>(&device, &config, buffer.clone(), capacity),
+            }
+        };
+        let stream = match stream {
+            Ok(x) => x,
+            Err(e) => {
+                log::error!(
+                    "failed to create input stream with config {:?}: {:?}",
+                    config,
+                    e
+                );
+                return Err(AudioEngineError::Backend(e));
+            }
+        };
+        stream.play().map_err(AudioEngineError::PlayStream)?;
+        log::info!(
+            "created {:?} input stream with config {:?}",
+            sample_format,
+            config
+        );
+
+        Ok(Self {
+            _stream: stream,
+            buffer,
+            channels: config.channels,
+            sample_rate: config.sample_rate.0,
+        })
+    }
+
+    /// Return a `SoundSource` that outputs the captured samples.
+    ///
+    /// All returned sources read from the same buffer, so each captured sample is only outputed
+    /// by one of them.
+    pub fn source(&self) -> InputSource {
+        InputSource {
+            buffer: self.buffer.clone(),
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+        }
+    }
+
+    /// The number of channels of the captured audio.
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// The sample rate of the captured audio.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
+
+fn stream<T: cpal::Sample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    buffer: Arc<Mutex<VecDeque<i16>>>,
+    capacity: usize,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let channels = config.channels as usize;
+    device.build_input_stream(
+        config,
+        move |input: &[T], _| {
+            let mut buffer = buffer.lock().unwrap();
+            buffer.extend(input.iter().map(|x| x.to_i16()));
+            if buffer.len() > capacity {
+                // discard the oldest frames
+                let excess = buffer.len() - capacity;
+                let excess = excess.div_ceil(channels) * channels;
+                buffer.drain(..excess);
+            }
+        },
+        |err| log::error!("input stream error: {}", err),
+    )
+}
+
+/// A `SoundSource` that outputs the audio captured by a [`InputStream`].
+///
+/// This source never ends. If there are not enough captured samples, the remaining of the buffer
+/// is filled with silence.
+pub struct InputSource {
+    buffer: Arc<Mutex<VecDeque<i16>>>,
+    channels: u16,
+    sample_rate: u32,
+}
+impl SoundSource for InputSource {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Discard all samples captured until now.
+    fn reset(&mut self) {
+        self.buffer.lock().unwrap().clear();
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let mut captured = self.buffer.lock().unwrap();
+        let channels = self.channels as usize;
+        let len = captured.len().min(buffer.len()) / channels * channels;
+        for (o, i) in buffer.iter_mut().zip(captured.drain(..len)) {
+            *o = i;
+        }
+        buffer[len..].fill(0);
+        buffer.len()
+    }
+}
@@ -0,0 +1,73 @@
+use std::f64::consts::TAU;
+
+/// The maximum frequency of a [`Lfo`], in Hertz.
+pub(crate) const MAX_RATE: f32 = 20.0;
+
+/// The shape of the wave of a low-frequency oscillator, like the ones of [`Tremolo`](crate::Tremolo)
+/// and [`Vibrato`](crate::Vibrato).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LfoShape {
+    /// A smooth sine wave.
+    Sine,
+    /// A triangle wave, that changes linearly.
+    Triangle,
+}
+
+/// A low-frequency oscillator, used to modulate the parameters of a source.
+pub(crate) struct Lfo {
+    /// The frequency of the oscillator, in Hertz.
+    pub rate: f32,
+    pub shape: LfoShape,
+    /// The phase of the oscillator, from 0.0 to 1.0.
+    phase: f64,
+}
+impl Lfo {
+    pub fn new(rate: f32, shape: LfoShape) -> Self {
+        Self {
+            rate,
+            shape,
+            phase: 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    /// Return the value of the oscillator in the next frame, in the range `0.0..=1.0`.
+    ///
+    /// The value starts at 0.0, and reaches 1.0 in the middle of each period.
+    pub fn next(&mut self, sample_rate: u32) -> f32 {
+        let value = match self.shape {
+            LfoShape::Sine => (1.0 - (self.phase * TAU).cos()) / 2.0,
+            LfoShape::Triangle => 1.0 - (2.0 * self.phase - 1.0).abs(),
+        };
+        self.phase = (self.phase + self.rate as f64 / sample_rate as f64).rem_euclid(1.0);
+        value as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Lfo, LfoShape};
+
+    #[test]
+    fn shapes() {
+        let mut lfo = Lfo::new(1.0, LfoShape::Triangle);
+        let values: Vec<f32> = (0..8).map(|_| lfo.next(4)).collect();
+        assert_eq!(values, [0.0, 0.5, 1.0, 0.5, 0.0, 0.5, 1.0, 0.5]);
+
+        let mut lfo = Lfo::new(1.0, LfoShape::Sine);
+        let values: Vec<f32> = (0..4).map(|_| lfo.next(4)).collect();
+        for (a, b) in values.iter().zip(&[0.0, 0.5, 1.0, 0.5]) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn negative_rate() {
+        let mut lfo = Lfo::new(-1.0, LfoShape::Triangle);
+        let values: Vec<f32> = (0..4).map(|_| lfo.next(4)).collect();
+        assert_eq!(values, [0.0, 0.5, 1.0, 0.5]);
+    }
+}
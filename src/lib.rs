@@ -3,18 +3,25 @@
 //! Audio Engine is a cross-platform crate for audio playback, build on top of cpal.
 //!
 //! ## Supported formats
-//! - ogg
-//! - wav
+//! - ogg, with the `ogg` feature
+//! - wav, with the `wav` feature
+//!
+//! Both features are enabled by default. They can be disabled, with `default-features = false`,
+//! for a smaller build with only the engine, the mixer and the built-in sources, like for the web.
+//!
+//! [`decode`] picks the decoder from the extension or the content of the data.
 //!
 //! ## Example
 //!
 //! ```no_run
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # #[cfg(feature = "wav")] {
 //! # let my_wav_sound = std::io::Cursor::new(vec![]);
 //! use audio_engine::{AudioEngine, WavDecoder};
 //! let audio_engine = AudioEngine::new()?;
 //! let mut sound = audio_engine.new_sound(WavDecoder::new(my_wav_sound)?)?;
 //! sound.play();
+//! # }
 //! # Ok(())
 //! # }
 //! ```
@@ -22,52 +29,175 @@
 use std::{
     hash::Hash,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
+use mixer::{EndCallback, Levels, MixerHandle, SoundState};
+
+mod simd;
+mod time;
 mod unshared;
 
+/// Reexport of `cpal`, used for audio input and output.
+pub use cpal;
+
+mod bitcrusher;
+mod constant;
 pub mod converter;
+mod decode;
+mod effect;
+mod error;
+mod filter;
+mod forward;
+mod lfo;
+#[cfg(feature = "pitch-shift")]
+mod pitch_shift;
+mod prepared;
+mod priority;
+mod reverse;
 mod sine;
+mod slice;
+#[cfg(feature = "time-stretch")]
+mod time_stretch;
+mod tremolo;
+mod vibrato;
 
 #[cfg(feature = "ogg")]
 mod ogg;
 #[cfg(feature = "wav")]
 mod wav;
 
+#[cfg(feature = "input")]
+mod input;
+
 mod engine;
-pub use engine::AudioEngine;
+#[cfg(not(target_arch = "wasm32"))]
+pub use engine::MirrorOutput;
+pub use engine::{AudioEngine, AudioEngineBuilder, HeadlessMixer, ResamplePolicy, StreamEvent};
 
 mod mixer;
-pub use mixer::Mixer;
+pub use mixer::{Mixer, PanLaw, VoiceStealing, MAX_VOLUME};
 
+pub use bitcrusher::{BitCrusher, BitCrusherEffect};
+pub use constant::{Constant, Silence};
+pub use decode::{decode, DecodeError, FormatHint};
+pub use effect::{Effect, EffectChain};
+pub use error::AudioEngineError;
+pub use filter::{BiquadFilter, BiquadFilterEffect, Delay, DelayEffect};
+pub use forward::ForwardReader;
+pub use lfo::LfoShape;
+#[cfg(feature = "pitch-shift")]
+pub use pitch_shift::PitchShift;
+pub use prepared::PreparedSound;
+pub use reverse::Reverse;
 pub use sine::SineWave;
+pub use slice::SliceSource;
+#[cfg(feature = "time-stretch")]
+pub use time_stretch::TimeStretch;
+pub use tremolo::{Tremolo, TremoloEffect};
+pub use vibrato::{Vibrato, VibratoEffect};
 
 #[cfg(feature = "ogg")]
 pub use ogg::OggDecoder;
 #[cfg(feature = "wav")]
 pub use wav::WavDecoder;
 
+#[cfg(feature = "input")]
+pub use input::{InputSource, InputStream};
+
 /// The number of samples processed per second for a single channel of audio.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SampleRate(pub u32);
+impl SampleRate {
+    /// The number of frames in the given duration, rounded to the nearest frame, saturating at
+    /// `u64::MAX`.
+    ///
+    /// A frame has one sample for each channel. Use
+    /// [`interleaved_samples_for`](Self::interleaved_samples_for) for the length of a buffer of
+    /// interleaved samples.
+    pub fn samples_for(self, duration: Duration) -> u64 {
+        time::duration_to_frames(duration, self.0)
+    }
 
-type SoundId = u64;
+    /// The number of interleaved samples in the given duration, with the given number of
+    /// channels, saturating at `u64::MAX`.
+    pub fn interleaved_samples_for(self, duration: Duration, channels: u16) -> u64 {
+        self.samples_for(duration).saturating_mul(channels as u64)
+    }
+
+    /// The duration of the given number of frames, rounded down to the nanosecond.
+    ///
+    /// Return zero if the sample rate is zero.
+    pub fn duration_of(self, frames: u64) -> Duration {
+        time::frames_to_duration(frames, self.0)
+    }
+}
+impl From<u32> for SampleRate {
+    fn from(sample_rate: u32) -> Self {
+        SampleRate(sample_rate)
+    }
+}
+
+/// A globally unique identifier of a sound in a [`Mixer`].
+pub type SoundId = u64;
 
 /// Represents a sound in the AudioEngine.
 ///
 /// If this is dropped, the sound will continue to play, but will be removed
-/// when it reachs its ends, even if it is set to loop.
+/// when it reachs its ends, even if it is set to loop. If there are multiple handles to the same
+/// sound, created by [`clone_handle`](Sound::clone_handle), this only happens when the last one is
+/// dropped.
+///
+/// The methods that control the sound don't block the audio thread: they are queued, and applied
+/// right before the next buffer of samples is mixed.
 pub struct Sound<G: Eq + Hash + Send + 'static = ()> {
-    mixer: Arc<Mutex<Mixer<G>>>,
+    mixer: MixerHandle<G>,
     id: SoundId,
+    state: Arc<SoundState>,
 }
 impl<G: Eq + Hash + Send + 'static> Sound<G> {
+    /// Create a new handle that controls the same sound.
+    ///
+    /// The sound is only marked to be removed after all of its handles are dropped.
+    pub fn clone_handle(&self) -> Sound<G> {
+        let id = self.id;
+        self.mixer.command(move |m| m.add_handle(id));
+        Sound {
+            mixer: self.mixer.clone(),
+            id,
+            state: self.state.clone(),
+        }
+    }
+
+    /// Create a [`WeakSound`], that controls this sound without keeping it alive.
+    pub fn downgrade(&self) -> WeakSound<G> {
+        WeakSound {
+            mixer: self.mixer.clone(),
+            id: self.id,
+            state: self.state.clone(),
+        }
+    }
+
     /// Starts or continue to play the sound.
     ///
     /// If the sound was paused or stop, it will start playing again. Otherwise,
     /// does nothing.
     pub fn play(&mut self) {
-        self.mixer.lock().unwrap().play(self.id);
+        let id = self.id;
+        self.mixer.command(move |m| m.play(id));
+    }
+
+    /// Start playing the sound after the given delay.
+    ///
+    /// The delay is counted in samples from the start of the next buffer that is mixed, so the
+    /// sound starts at a exact sample, independently of the buffer size. If the sound is already
+    /// playing, does nothing.
+    pub fn play_at(&mut self, delay: Duration) {
+        let id = self.id;
+        self.mixer.command(move |m| {
+            let delay = time::duration_to_frames(delay, m.sample_rate());
+            m.play_at(id, delay)
+        });
     }
 
     /// Pause the sound.
@@ -76,7 +206,8 @@ impl<G: Eq + Hash + Send + 'static> Sound<G> {
     /// will continue from where it was before pause. If the sound is not
     /// playing, does nothing.
     pub fn pause(&mut self) {
-        self.mixer.lock().unwrap().pause(self.id);
+        let id = self.id;
+        self.mixer.command(move |m| m.pause(id));
     }
 
     /// Stop the sound.
@@ -85,29 +216,516 @@ impl<G: Eq + Hash + Send + 'static> Sound<G> {
     /// called, this sound will start from the beginning. Even if the sound is not
     /// playing, it will reset the sound to the start.
     pub fn stop(&mut self) {
-        self.mixer.lock().unwrap().stop(self.id);
+        let id = self.id;
+        self.mixer.command(move |m| m.stop(id));
     }
 
     /// Reset the sound to the start.
     ///
     /// The behaviour is the same being the sound playing or not.
     pub fn reset(&mut self) {
-        self.mixer.lock().unwrap().reset(self.id);
+        let id = self.id;
+        self.mixer.command(move |m| m.reset(id));
+    }
+
+    /// Move the sound to the given position, measured from its start.
+    ///
+    /// The sound keeps playing or paused. Seeking past the end behaves like reaching the end: the
+    /// sound loops or stops, depending on [`set_loop`](Self::set_loop).
+    ///
+    /// The seek is done in the audio thread, before the next buffer is mixed. Sources that can't
+    /// move to a position directly, like the ones using the default [`SoundSource::seek`] or a
+    /// `OggDecoder`, decode all the samples before the position, which can take long enough to
+    /// cause a dropout when seeking far into a long sound. A [`SliceSource`] or a `WavDecoder`
+    /// seek in constant time.
+    pub fn seek(&mut self, position: Duration) {
+        let id = self.id;
+        self.mixer.command(move |m| m.seek(id, position));
     }
 
     /// Set the volume of the sound.
+    ///
+    /// The volume is clamped to the range `0.0..=`[`MAX_VOLUME`], and NaN is treated as 0.0.
     pub fn set_volume(&mut self, volume: f32) {
-        self.mixer.lock().unwrap().set_volume(self.id, volume);
+        let id = self.id;
+        let volume = clamp_param(volume, 0.0, MAX_VOLUME);
+        self.state.store_volume(volume);
+        self.mixer.command(move |m| m.set_volume(id, volume));
+    }
+
+    /// Automate the volume of the sound, without needing to call `set_volume` repeatedly.
+    ///
+    /// Each point is a volume and the time, since now, at which the sound reaches it. The volume
+    /// is linearly interpolated between the current volume and the first point, and between each
+    /// following point, and remains at the volume of the last point after it. The interpolation
+    /// is done while mixing, so it is frame accurate, and the time only advances while the sound
+    /// is playing.
+    ///
+    /// This replaces any previous automation, and is cancelled by calling `set_volume`.
+    ///
+    /// # Example
+    ///
+    /// Fade in during one second, and fade out after two seconds:
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), audio_engine::AudioEngineError> {
+    /// use audio_engine::{AudioEngine, SineWave};
+    /// use std::time::Duration;
+    ///
+    /// let audio_engine = AudioEngine::new()?;
+    /// let mut sound = audio_engine.new_sound(SineWave::new(44100, 500.0))?;
+    /// sound.set_volume(0.0);
+    /// sound.automate_volume(&[
+    ///     (Duration::from_secs(1), 1.0),
+    ///     (Duration::from_secs(3), 1.0),
+    ///     (Duration::from_secs(4), 0.0),
+    /// ]);
+    /// sound.play();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn automate_volume(&mut self, points: &[(Duration, f32)]) {
+        let id = self.id;
+        let mut points = points.to_vec();
+        // sorted here, so the mixer don't allocate to sort it
+        points.sort_by_key(|x| x.0);
+        self.mixer.command(move |m| m.automate_volume(id, points));
+    }
+
+    /// Fade in the sound, from silence to its current volume, during the given duration.
+    ///
+    /// The fade only advances while the sound is playing, so it can be called right before
+    /// [`play`](Self::play). See [`Mixer::fade_in`].
+    pub fn fade_in(&mut self, duration: Duration) {
+        let id = self.id;
+        let points = Vec::with_capacity(1);
+        self.mixer
+            .command(move |m| m.fade_in_with(id, duration, points));
+    }
+
+    /// Fade out the sound, from its current volume to silence, during the given duration.
+    ///
+    /// The sound keeps playing, with a volume of 0.0. Use
+    /// [`fade_out_and_stop`](Self::fade_out_and_stop) to also stop it.
+    pub fn fade_out(&mut self, duration: Duration) {
+        let id = self.id;
+        let points = vec![(duration, 0.0)];
+        self.mixer.command(move |m| m.automate_volume(id, points));
+    }
+
+    /// Fade out the sound, and stop it when the fade ends.
+    ///
+    /// After it stops, the volume is restored to the one before the fade. See
+    /// [`Mixer::fade_out_and_stop`].
+    pub fn fade_out_and_stop(&mut self, duration: Duration) {
+        let id = self.id;
+        let points = Vec::with_capacity(1);
+        self.mixer
+            .command(move |m| m.fade_out_and_stop_with(id, duration, points));
+    }
+
+    /// The total duration of the sound, if it is known. See [`SoundSource::duration`].
+    pub fn duration(&self) -> Option<Duration> {
+        self.state.duration()
+    }
+
+    /// How much of the sound was already played, since its start or its last loop.
+    ///
+    /// See [`Mixer::playback_position`].
+    pub fn position(&self) -> Duration {
+        let position = self.mixer.playback_position(&self.state);
+        position.unwrap_or_default()
+    }
+
+    /// The current volume of the sound.
+    pub fn volume(&self) -> f32 {
+        self.state.volume().unwrap_or(0.0)
+    }
+
+    /// Set the stereo position of the sound, from -1.0 (left) to 1.0 (right).
+    ///
+    /// The pan is clamped to this range, and NaN is treated as 0.0 (center). It is ignored if the
+    /// output is mono. See [`Mixer::set_pan`].
+    pub fn set_pan(&mut self, pan: f32) {
+        let id = self.id;
+        let pan = clamp_param(pan, -1.0, 1.0);
+        self.state.store_pan(pan);
+        self.mixer.command(move |m| m.set_pan(id, pan));
+    }
+
+    /// The current stereo position of the sound.
+    pub fn pan(&self) -> f32 {
+        self.state.pan().unwrap_or(0.0)
+    }
+
+    /// The peak level of each channel of the sound, in the most recently mixed buffer. See
+    /// [`Mixer::sound_peak_level`].
+    pub fn peak_level(&self) -> Vec<f32> {
+        self.mixer
+            .sound_levels(&self.state, Levels::peak)
+            .unwrap_or_default()
+    }
+
+    /// The RMS level of each channel of the sound, in the most recently mixed buffer. See
+    /// [`Mixer::sound_rms_level`].
+    pub fn rms_level(&self) -> Vec<f32> {
+        self.mixer
+            .sound_levels(&self.state, Levels::rms)
+            .unwrap_or_default()
+    }
+
+    /// Mute or unmute the sound. A muted sound keeps playing, but outputs silence. See
+    /// [`Mixer::set_mute`].
+    pub fn set_mute(&mut self, mute: bool) {
+        let id = self.id;
+        self.state.store_muted(mute);
+        self.mixer.command(move |m| m.set_mute(id, mute));
+    }
+
+    /// If the sound is muted.
+    pub fn is_muted(&self) -> bool {
+        self.state.is_muted().unwrap_or(false)
+    }
+
+    /// Solo or unsolo the sound. While any sound is soloed, the sounds that are not soloed keep
+    /// playing, but output silence. See [`Mixer::set_solo`].
+    pub fn set_solo(&mut self, solo: bool) {
+        let id = self.id;
+        self.state.store_solo(solo);
+        self.mixer.command(move |m| m.set_solo(id, solo));
+    }
+
+    /// If the sound is soloed.
+    pub fn is_solo(&self) -> bool {
+        self.state.is_solo().unwrap_or(false)
+    }
+
+    /// Set the pan law of the sound, that defines how the sound is attenuated in the other
+    /// channel as it is panned.
+    ///
+    /// By default, sounds use [`PanLaw::ConstantPower`].
+    pub fn set_pan_law(&mut self, law: PanLaw) {
+        let id = self.id;
+        self.mixer.command(move |m| m.set_pan_law(id, law));
+    }
+
+    /// Move the sound to another group, without recreating it.
+    ///
+    /// See [`Mixer::set_group`].
+    pub fn set_group(&mut self, group: G) {
+        self.mixer.set_group(self.id, &self.state, group);
+    }
+
+    /// Replace what the sound is playing, keeping its id, volume, group and all other settings.
+    ///
+    /// The new source is converted to the output format in the same way as in
+    /// [`AudioEngine::new_sound`], returning a error if the [`ResamplePolicy`] of the engine
+    /// denies it. If the sound is playing, it continues playing from the start of the new source.
+    /// See [`Mixer::replace_source`].
+    pub fn replace_source<T: SoundSource + Send + 'static>(
+        &mut self,
+        source: T,
+    ) -> Result<(), AudioEngineError> {
+        let id = self.id;
+        let source = self.mixer.convert_source(source)?;
+        self.mixer.command(move |m| m.replace_source(id, source));
+        Ok(())
+    }
+
+    /// Set a callback that is called each time the sound loops.
+    ///
+    /// The callback is called in the audio thread, so it should return quickly, for example by
+    /// sending a message through a channel. See [`Mixer::set_on_loop`].
+    pub fn on_loop(&mut self, callback: impl FnMut() + Send + 'static) {
+        let id = self.id;
+        let callback = Box::new(callback);
+        self.mixer
+            .command(move |m| m.set_boxed_on_loop(id, Some(callback)));
+    }
+
+    /// Remove the callback set by [`on_loop`](Self::on_loop).
+    pub fn remove_on_loop(&mut self) {
+        let id = self.id;
+        self.mixer.command(move |m| m.remove_on_loop(id));
+    }
+
+    /// Set a callback that is called each time the sound reaches its end.
+    ///
+    /// The callback is called in the audio thread, so it should return quickly, for example by
+    /// sending a message through a channel. See [`Mixer::set_on_end`].
+    pub fn on_end(&mut self, callback: impl FnMut() + Send + 'static) {
+        let id = self.id;
+        let callback: EndCallback = Arc::new(Mutex::new(callback));
+        self.mixer
+            .command(move |m| m.set_shared_on_end(id, Some(callback)));
+    }
+
+    /// Remove the callback set by [`on_end`](Self::on_end).
+    pub fn remove_on_end(&mut self) {
+        let id = self.id;
+        self.mixer.command(move |m| m.remove_on_end(id));
     }
 
     /// Set if the sound will repeat ever time it reachs its end.
     pub fn set_loop(&mut self, looping: bool) {
-        self.mixer.lock().unwrap().set_loop(self.id, looping);
+        let id = self.id;
+        self.mixer.command(move |m| m.set_loop(id, looping));
+    }
+
+    /// Loop only the region of the sound from `start` to `end`, measured from its start.
+    ///
+    /// While the sound is [looping](Self::set_loop), it plays from its start until `end`, and then
+    /// keeps looping back to `start`, like a music with an intro. Return a error if `start` is not
+    /// before `end`, once both are rounded to frames of the output. See
+    /// [`Mixer::set_loop_region`].
+    pub fn set_loop_region(
+        &mut self,
+        start: Duration,
+        end: Duration,
+    ) -> Result<(), AudioEngineError> {
+        self.mixer.set_loop_region(self.id, start, end)
+    }
+
+    /// Remove the region set by [`set_loop_region`](Self::set_loop_region), looping the whole
+    /// sound again.
+    pub fn remove_loop_region(&mut self) {
+        let id = self.id;
+        self.mixer.command(move |m| m.remove_loop_region(id));
+    }
+
+    /// Set the length of the crossfade between the end and the start of the sound, when it loops.
+    ///
+    /// This removes the audible seam of loops whose start and end don't match, like short ambient
+    /// loops. The end of the sound is detected by reading the sound ahead of the output by the
+    /// crossfade length. If `crossfade` is zero, the sound loops without a crossfade, which is the
+    /// default.
+    pub fn set_loop_crossfade(&mut self, crossfade: Duration) {
+        let id = self.id;
+        self.mixer.command(move |m| {
+            let frames = time::duration_to_frames(crossfade, m.sample_rate());
+            m.set_loop_crossfade(id, frames)
+        });
     }
 }
 impl<G: Eq + Hash + Send + 'static> Drop for Sound<G> {
     fn drop(&mut self) {
-        self.mixer.lock().unwrap().mark_to_remove(self.id, true);
+        let id = self.id;
+        self.mixer.command(move |m| m.remove_handle(id));
+    }
+}
+
+/// A handle to a sound that doesn't keep it alive, created by [`Sound::downgrade`].
+///
+/// Dropping a WeakSound does not affect the sound. If the sound was already removed, all methods
+/// do nothing.
+pub struct WeakSound<G: Eq + Hash + Send + 'static = ()> {
+    mixer: MixerHandle<G>,
+    id: SoundId,
+    state: Arc<SoundState>,
+}
+impl<G: Eq + Hash + Send + 'static> WeakSound<G> {
+    /// Return a [`Sound`] handle to this sound, or `None` if the sound was already removed.
+    ///
+    /// The new handle is only registered before the next buffer is mixed, so if the sound is
+    /// removed at the end of the current buffer, the returned handle does nothing.
+    pub fn upgrade(&self) -> Option<Sound<G>> {
+        if !self.state.is_alive() {
+            return None;
+        }
+        let id = self.id;
+        self.mixer.command(move |m| m.add_handle(id));
+        Some(Sound {
+            mixer: self.mixer.clone(),
+            id,
+            state: self.state.clone(),
+        })
+    }
+
+    /// Return true if the sound was not removed yet.
+    pub fn is_alive(&self) -> bool {
+        self.state.is_alive()
+    }
+
+    /// Starts or continue to play the sound. See [`Sound::play`].
+    pub fn play(&self) {
+        let id = self.id;
+        self.mixer.command(move |m| m.play(id));
+    }
+
+    /// Pause the sound. See [`Sound::pause`].
+    pub fn pause(&self) {
+        let id = self.id;
+        self.mixer.command(move |m| m.pause(id));
+    }
+
+    /// Stop the sound. See [`Sound::stop`].
+    pub fn stop(&self) {
+        let id = self.id;
+        self.mixer.command(move |m| m.stop(id));
+    }
+
+    /// Reset the sound to the start. See [`Sound::reset`].
+    pub fn reset(&self) {
+        let id = self.id;
+        self.mixer.command(move |m| m.reset(id));
+    }
+
+    /// Move the sound to the given position. See [`Sound::seek`].
+    pub fn seek(&self, position: Duration) {
+        let id = self.id;
+        self.mixer.command(move |m| m.seek(id, position));
+    }
+
+    /// Set the volume of the sound.
+    pub fn set_volume(&self, volume: f32) {
+        let id = self.id;
+        let volume = clamp_param(volume, 0.0, MAX_VOLUME);
+        self.state.store_volume(volume);
+        self.mixer.command(move |m| m.set_volume(id, volume));
+    }
+
+    /// Automate the volume of the sound. See [`Sound::automate_volume`].
+    pub fn automate_volume(&self, points: &[(Duration, f32)]) {
+        let id = self.id;
+        let mut points = points.to_vec();
+        // sorted here, so the mixer don't allocate to sort it
+        points.sort_by_key(|x| x.0);
+        self.mixer.command(move |m| m.automate_volume(id, points));
+    }
+
+    /// Fade in the sound. See [`Sound::fade_in`].
+    pub fn fade_in(&self, duration: Duration) {
+        let id = self.id;
+        let points = Vec::with_capacity(1);
+        self.mixer
+            .command(move |m| m.fade_in_with(id, duration, points));
+    }
+
+    /// Fade out the sound. See [`Sound::fade_out`].
+    pub fn fade_out(&self, duration: Duration) {
+        let id = self.id;
+        let points = vec![(duration, 0.0)];
+        self.mixer.command(move |m| m.automate_volume(id, points));
+    }
+
+    /// Fade out the sound, and stop it. See [`Sound::fade_out_and_stop`].
+    pub fn fade_out_and_stop(&self, duration: Duration) {
+        let id = self.id;
+        let points = Vec::with_capacity(1);
+        self.mixer
+            .command(move |m| m.fade_out_and_stop_with(id, duration, points));
+    }
+
+    /// The total duration of the sound, or `None` if it is unknown or the sound was already
+    /// removed. See [`Sound::duration`].
+    pub fn duration(&self) -> Option<Duration> {
+        self.state.duration()
+    }
+
+    /// How much of the sound was already played, or `None` if the sound was already removed. See
+    /// [`Sound::position`].
+    pub fn position(&self) -> Option<Duration> {
+        self.mixer.playback_position(&self.state)
+    }
+
+    /// The current volume of the sound, or `None` if the sound was already removed.
+    pub fn volume(&self) -> Option<f32> {
+        self.state.volume()
+    }
+
+    /// Set the stereo position of the sound. See [`Sound::set_pan`].
+    pub fn set_pan(&self, pan: f32) {
+        let id = self.id;
+        let pan = clamp_param(pan, -1.0, 1.0);
+        self.state.store_pan(pan);
+        self.mixer.command(move |m| m.set_pan(id, pan));
+    }
+
+    /// The current stereo position of the sound, or `None` if the sound was already removed.
+    pub fn pan(&self) -> Option<f32> {
+        self.state.pan()
+    }
+
+    /// The peak level of each channel of the sound, or `None` if the sound was already removed.
+    /// See [`Sound::peak_level`].
+    pub fn peak_level(&self) -> Option<Vec<f32>> {
+        self.mixer.sound_levels(&self.state, Levels::peak)
+    }
+
+    /// The RMS level of each channel of the sound, or `None` if the sound was already removed.
+    /// See [`Sound::rms_level`].
+    pub fn rms_level(&self) -> Option<Vec<f32>> {
+        self.mixer.sound_levels(&self.state, Levels::rms)
+    }
+
+    /// Mute or unmute the sound. See [`Sound::set_mute`].
+    pub fn set_mute(&self, mute: bool) {
+        let id = self.id;
+        self.state.store_muted(mute);
+        self.mixer.command(move |m| m.set_mute(id, mute));
+    }
+
+    /// If the sound is muted, or `None` if the sound was already removed.
+    pub fn is_muted(&self) -> Option<bool> {
+        self.state.is_muted()
+    }
+
+    /// Solo or unsolo the sound. See [`Sound::set_solo`].
+    pub fn set_solo(&self, solo: bool) {
+        let id = self.id;
+        self.state.store_solo(solo);
+        self.mixer.command(move |m| m.set_solo(id, solo));
+    }
+
+    /// If the sound is soloed, or `None` if the sound was already removed.
+    pub fn is_solo(&self) -> Option<bool> {
+        self.state.is_solo()
+    }
+
+    /// Set the pan law of the sound. See [`Sound::set_pan_law`].
+    pub fn set_pan_law(&self, law: PanLaw) {
+        let id = self.id;
+        self.mixer.command(move |m| m.set_pan_law(id, law));
+    }
+
+    /// Move the sound to another group. See [`Sound::set_group`].
+    pub fn set_group(&self, group: G) {
+        self.mixer.set_group(self.id, &self.state, group);
+    }
+
+    /// Set if the sound will repeat ever time it reachs its end.
+    pub fn set_loop(&self, looping: bool) {
+        let id = self.id;
+        self.mixer.command(move |m| m.set_loop(id, looping));
+    }
+
+    /// Loop only a region of the sound. See [`Sound::set_loop_region`].
+    pub fn set_loop_region(&self, start: Duration, end: Duration) -> Result<(), AudioEngineError> {
+        self.mixer.set_loop_region(self.id, start, end)
+    }
+
+    /// Loop the whole sound again. See [`Sound::remove_loop_region`].
+    pub fn remove_loop_region(&self) {
+        let id = self.id;
+        self.mixer.command(move |m| m.remove_loop_region(id));
+    }
+
+    /// Set the length of the loop crossfade. See [`Sound::set_loop_crossfade`].
+    pub fn set_loop_crossfade(&self, crossfade: Duration) {
+        let id = self.id;
+        self.mixer.command(move |m| {
+            let frames = time::duration_to_frames(crossfade, m.sample_rate());
+            m.set_loop_crossfade(id, frames)
+        });
+    }
+}
+impl<G: Eq + Hash + Send + 'static> Clone for WeakSound<G> {
+    fn clone(&self) -> Self {
+        Self {
+            mixer: self.mixer.clone(),
+            id: self.id,
+            state: self.state.clone(),
+        }
     }
 }
 
@@ -130,9 +748,129 @@ pub trait SoundSource {
     /// `buffer`, this indicate that the sound ended.
     ///
     /// The `buffer` length and the returned length should always be a multiple of
-    /// [`self.channels()`](SoundSource::channels).
+    /// [`self.channels()`](SoundSource::channels). The engine only passes whole frames to its
+    /// sources, even if the output buffer ends in a partial frame, which is filled with silence.
     fn write_samples(&mut self, buffer: &mut [i16]) -> usize;
+
+    /// Write the samples to `buffer`, as `f32` in the range `-1.0..=1.0`.
+    ///
+    /// This behaves like [`write_samples`](SoundSource::write_samples), and by default is
+    /// implemented by converting its samples. Sources that generate floating point samples should
+    /// override it, so their samples don't lose precision by being converted to `i16` before
+    /// reaching the output.
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        write_samples_f32_from_i16(self, buffer)
+    }
+
+    /// Return the total number of samples of the sound, counting all channels, if it is known.
+    ///
+    /// This is the number of samples written from the start of the sound until its end, and can
+    /// be used to preallocate a buffer for decoding the whole sound. It is only a hint: it may be
+    /// approximate for converted sources, and is `None` by default and for sources that never
+    /// end.
+    fn size_hint(&self) -> Option<u64> {
+        None
+    }
+
+    /// Return the total duration of the sound, if it is known.
+    ///
+    /// By default, this is computed from [`size_hint`](SoundSource::size_hint), so it is `None`
+    /// for sources that never end, or whose length is unknown.
+    fn duration(&self) -> Option<Duration> {
+        let frames = self.size_hint()? / self.channels() as u64;
+        Some(SampleRate(self.sample_rate()).duration_of(frames))
+    }
+
+    /// Move to the given position, in frames since the start of the sound.
+    ///
+    /// Seeking past the end of the sound moves it to its end, so it writes no more samples. Like
+    /// in [`reset`](SoundSource::reset), errors are logged.
+    ///
+    /// By default, the source is reset, and the samples before the position are written and
+    /// discarded. Sources that can move to a position directly should override it.
+    fn seek(&mut self, frame: u64) {
+        self.reset();
+        skip_frames(self, frame);
+    }
 }
+
+/// Return a error if the given source has zero channels or a zero sample rate, which can't be
+/// mixed or converted.
+pub(crate) fn check_source<S: SoundSource + ?Sized>(source: &S) -> Result<(), AudioEngineError> {
+    if source.channels() == 0 {
+        return Err(AudioEngineError::ZeroChannels);
+    }
+    if source.sample_rate() == 0 {
+        return Err(AudioEngineError::ZeroSampleRate);
+    }
+    Ok(())
+}
+
+/// Call `f` with a scratch buffer holding a whole number of frames, at least one, with the given
+/// number of channels.
+///
+/// The buffer is on the stack, unless a single frame doesn't fit in it.
+pub(crate) fn with_scratch<R>(channels: u16, f: impl FnOnce(&mut [i16]) -> R) -> R {
+    let channels = (channels as usize).max(1);
+    let mut chunk = [0i16; 1024];
+    if channels > chunk.len() {
+        return f(&mut vec![0; channels]);
+    }
+    let len = chunk.len() / channels * channels;
+    f(&mut chunk[..len])
+}
+
+/// Write and discard the next `frames` frames of `source`, stopping early if it ends.
+pub(crate) fn skip_frames<S: SoundSource + ?Sized>(source: &mut S, frames: u64) {
+    let channels = source.channels();
+    let mut remaining = frames.saturating_mul(channels as u64);
+    with_scratch(channels, |chunk| {
+        while remaining > 0 {
+            let len = remaining.min(chunk.len() as u64) as usize;
+            let written = source.write_samples(&mut chunk[..len]);
+            remaining -= written as u64;
+            if written < len {
+                break;
+            }
+        }
+    })
+}
+
+/// Clamp a parameter to the range `min..=max`, treating NaN as the value of the range closest to
+/// zero.
+///
+/// Used by setters, so a malformed value can't poison the mix.
+pub(crate) fn clamp_param(value: f32, min: f32, max: f32) -> f32 {
+    if value.is_nan() {
+        0.0f32.clamp(min, max)
+    } else {
+        value.clamp(min, max)
+    }
+}
+
+/// The default implementation of [`SoundSource::write_samples_f32`], converting the samples of
+/// [`SoundSource::write_samples`].
+pub(crate) fn write_samples_f32_from_i16<S: SoundSource + ?Sized>(
+    source: &mut S,
+    buffer: &mut [f32],
+) -> usize {
+    // Convert in chunks, to avoid allocating a `i16` buffer.
+    let mut len = 0;
+    with_scratch(source.channels(), |chunk| {
+        for out in buffer.chunks_mut(chunk.len()) {
+            let written = source.write_samples(&mut chunk[..out.len()]);
+            for (o, i) in out.iter_mut().zip(&chunk[..written]) {
+                *o = cpal::Sample::to_f32(i);
+            }
+            len += written;
+            if written < out.len() {
+                break;
+            }
+        }
+    });
+    len
+}
+
 impl<T: SoundSource + ?Sized> SoundSource for Box<T> {
     fn channels(&self) -> u16 {
         (**self).channels()
@@ -149,6 +887,22 @@ impl<T: SoundSource + ?Sized> SoundSource for Box<T> {
     fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
         (**self).write_samples(buffer)
     }
+
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        (**self).write_samples_f32(buffer)
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        (**self).size_hint()
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        (**self).duration()
+    }
+
+    fn seek(&mut self, frame: u64) {
+        (**self).seek(frame)
+    }
 }
 impl<T: SoundSource + ?Sized> SoundSource for Arc<Mutex<T>> {
     fn channels(&self) -> u16 {
@@ -166,4 +920,20 @@ impl<T: SoundSource + ?Sized> SoundSource for Arc<Mutex<T>> {
     fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
         (*self).lock().unwrap().write_samples(buffer)
     }
+
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        (*self).lock().unwrap().write_samples_f32(buffer)
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        (*self).lock().unwrap().size_hint()
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        (*self).lock().unwrap().duration()
+    }
+
+    fn seek(&mut self, frame: u64) {
+        (*self).lock().unwrap().seek(frame)
+    }
 }
@@ -1,8 +1,16 @@
-use crate::{converter, SampleRate, SoundId, SoundSource};
+use crate::{
+    clamp_param,
+    converter::{self, Upmix},
+    simd, time, AudioEngineError, Effect, ResamplePolicy, SampleRate, SoundId, SoundSource,
+};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     hash::Hash,
-    sync::atomic::{AtomicU64, Ordering},
+    sync::{
+        atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
 fn next_id() -> SoundId {
@@ -14,30 +22,854 @@ struct SoundInner<G = ()> {
     id: SoundId,
     data: Box<dyn SoundSource + Send>,
     volume: f32,
+    /// The stereo position, from -1.0 (left) to 1.0 (right).
+    pan: f32,
+    pan_law: PanLaw,
+    /// If true, the sound outputs silence, but keeps playing.
+    muted: bool,
+    /// If true, only soloed sounds are heard.
+    solo: bool,
     group: G,
     looping: bool,
+    /// The start and end of the region that is looped, in frames, if not the whole sound.
+    loop_region: Option<(u64, u64)>,
     drop: bool,
+    /// The value of `Mixer::play_counter` when this sound last started playing.
+    started: u64,
+    /// The number of `Sound` handles controlling this sound.
+    handles: usize,
+    /// If the last call to `Mixer::play` was refused, because there was no voice to steal.
+    refused: bool,
+    /// The number of frames of silence to output before the sound starts.
+    start_delay: u64,
+    /// The automation of the volume, if any.
+    automation: Option<Automation>,
+    /// The state of the loop crossfade, if it was ever set.
+    crossfade: Option<LoopCrossfade>,
+    /// Called each time the sound loops.
+    on_loop: Option<SoundCallback>,
+    /// Called each time the sound reaches its end.
+    on_end: Option<EndCallback>,
+    /// The number of frames read from `data` since its start, in the sample rate of the mixer.
+    /// Includes the frames read ahead by `crossfade`.
+    position: u64,
+    /// The levels of the sound in the last buffer that it was mixed in.
+    levels: Levels,
+    /// The value of `Mixer::mix_count` when `levels` was measured.
+    levels_mix: u64,
+    /// The state published to the handles of the sound.
+    state: Arc<SoundState>,
 }
 impl<G> SoundInner<G> {
     fn new(group: G, data: Box<dyn SoundSource + Send>) -> Self {
+        // the levels are allocated here, instead of in the first mixed buffer of the sound
+        let channels = data.channels() as usize;
+        let state = Arc::new(SoundState::new(data.duration(), channels));
         Self {
             id: next_id(),
             data,
             volume: 1.0,
+            pan: 0.0,
+            pan_law: PanLaw::default(),
+            muted: false,
+            solo: false,
             group,
             looping: false,
+            loop_region: None,
             drop: true,
+            started: 0,
+            handles: 0,
+            refused: false,
+            start_delay: 0,
+            automation: None,
+            crossfade: None,
+            on_loop: None,
+            on_end: None,
+            position: 0,
+            levels: Levels::new(channels),
+            levels_mix: 0,
+            state,
+        }
+    }
+
+    /// Set the volume, publishing it to the handles of the sound.
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        self.state.store_volume(volume);
+    }
+
+    /// Reset the source to its start.
+    fn reset(&mut self) {
+        self.data.reset();
+        self.position = 0;
+        if let Some(crossfade) = &mut self.crossfade {
+            crossfade.len = 0;
+        }
+    }
+
+    /// The number of frames of the sound already played.
+    fn played_frames(&self) -> u64 {
+        let ahead = match &self.crossfade {
+            Some(crossfade) => (crossfade.len / self.data.channels() as usize) as u64,
+            None => 0,
+        };
+        self.position.saturating_sub(ahead)
+    }
+}
+
+/// The peak and RMS level of each channel of a buffer.
+#[derive(Debug, Default)]
+pub(crate) struct Levels {
+    peak: Vec<f32>,
+    rms: Vec<f32>,
+}
+impl Levels {
+    /// Create the levels of a buffer with the given number of channels, all zero.
+    fn new(channels: usize) -> Self {
+        Self {
+            peak: vec![0.0; channels],
+            rms: vec![0.0; channels],
+        }
+    }
+
+    /// Measure the levels of the given samples, multiplied by `gain`.
+    fn measure<S: MixSample>(&mut self, buffer: &[S], channels: usize, gain: f32) {
+        // Only allocates if the number of channels increases.
+        self.peak.clear();
+        self.peak.resize(channels, 0.0);
+        self.rms.clear();
+        self.rms.resize(channels, 0.0);
+        for frame in buffer.chunks_exact(channels) {
+            for (c, x) in frame.iter().enumerate() {
+                let x = x.to_f32().abs() * gain;
+                self.peak[c] = self.peak[c].max(x);
+                self.rms[c] += x * x;
+            }
+        }
+        let frames = (buffer.len() / channels).max(1) as f32;
+        for x in &mut self.rms {
+            *x = (*x / frames).sqrt();
+        }
+    }
+
+    /// The peak level of each channel.
+    pub(crate) fn peak(&self) -> Vec<f32> {
+        self.peak.clone()
+    }
+
+    /// The RMS level of each channel.
+    pub(crate) fn rms(&self) -> Vec<f32> {
+        self.rms.clone()
+    }
+
+    /// Copy the levels of `other`. Only allocates if the number of channels increases.
+    fn copy_from(&mut self, other: &Levels) {
+        self.peak.clear();
+        self.peak.extend_from_slice(&other.peak);
+        self.rms.clear();
+        self.rms.extend_from_slice(&other.rms);
+    }
+}
+
+/// The state of a sound that the mixer publishes to the [`Sound`](crate::Sound) handles of the
+/// sound, so they can read it without accessing the mixer.
+///
+/// The getters return `None` after the sound is removed from the mixer.
+pub(crate) struct SoundState {
+    /// Cleared when the sound is removed from the mixer.
+    alive: AtomicBool,
+    /// If the sound was playing at the end of the last mixed buffer.
+    playing: AtomicBool,
+    /// The number of frames of the sound already played, in the sample rate of the mixer.
+    position: AtomicU64,
+    /// The bits of the volume and of the pan, that are `f32`.
+    volume: AtomicU32,
+    pan: AtomicU32,
+    muted: AtomicBool,
+    solo: AtomicBool,
+    /// The duration of the source in nanoseconds, or `u64::MAX` if it is unknown.
+    duration: AtomicU64,
+    /// A copy of the levels of the sound, that the audio thread only updates if it is not being
+    /// read.
+    levels: Mutex<Levels>,
+    /// The value of `Mixer::mix_count` when `levels` was measured.
+    levels_mix: AtomicU64,
+}
+impl SoundState {
+    fn new(duration: Option<Duration>, channels: usize) -> Self {
+        let state = Self {
+            alive: AtomicBool::new(true),
+            playing: AtomicBool::new(false),
+            position: AtomicU64::new(0),
+            volume: AtomicU32::new(1.0f32.to_bits()),
+            pan: AtomicU32::new(0.0f32.to_bits()),
+            muted: AtomicBool::new(false),
+            solo: AtomicBool::new(false),
+            duration: AtomicU64::new(u64::MAX),
+            levels: Mutex::new(Levels::new(channels)),
+            levels_mix: AtomicU64::new(0),
+        };
+        state.store_duration(duration);
+        state
+    }
+
+    /// Return true if the sound was not removed from the mixer yet.
+    pub(crate) fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed)
+    }
+
+    /// Return true if the sound is alive and was playing in the last mixed buffer.
+    pub(crate) fn is_playing(&self) -> bool {
+        self.is_alive() && self.playing.load(Ordering::Relaxed)
+    }
+
+    fn if_alive<T>(&self, value: T) -> Option<T> {
+        self.is_alive().then_some(value)
+    }
+
+    /// The duration of the source, or `None` if it is unknown.
+    pub(crate) fn duration(&self) -> Option<Duration> {
+        match self.duration.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            x => self.if_alive(Duration::from_nanos(x)),
+        }
+    }
+
+    /// The number of frames of the sound already played.
+    fn played_frames(&self) -> Option<u64> {
+        self.if_alive(self.position.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn volume(&self) -> Option<f32> {
+        self.if_alive(f32::from_bits(self.volume.load(Ordering::Relaxed)))
+    }
+
+    pub(crate) fn pan(&self) -> Option<f32> {
+        self.if_alive(f32::from_bits(self.pan.load(Ordering::Relaxed)))
+    }
+
+    pub(crate) fn is_muted(&self) -> Option<bool> {
+        self.if_alive(self.muted.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn is_solo(&self) -> Option<bool> {
+        self.if_alive(self.solo.load(Ordering::Relaxed))
+    }
+
+    /// The given level of each channel, if the sound was mixed in the buffer where
+    /// `Mixer::mix_count` reached `mix_count`, or `channels` zeros otherwise.
+    fn levels(
+        &self,
+        mix_count: u64,
+        channels: u16,
+        level: fn(&Levels) -> Vec<f32>,
+    ) -> Option<Vec<f32>> {
+        if !self.is_alive() {
+            return None;
+        }
+        let levels = self.levels.lock().unwrap();
+        if self.levels_mix.load(Ordering::Relaxed) == mix_count {
+            Some(level(&levels))
+        } else {
+            Some(vec![0.0; channels as usize])
+        }
+    }
+
+    // The setters are also called by the handles, with the same clamped values that the mixer
+    // will set, so the change can be read back before the command is applied.
+
+    pub(crate) fn store_volume(&self, volume: f32) {
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn store_pan(&self, pan: f32) {
+        self.pan.store(pan.to_bits(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn store_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub(crate) fn store_solo(&self, solo: bool) {
+        self.solo.store(solo, Ordering::Relaxed);
+    }
+
+    pub(crate) fn store_duration(&self, duration: Option<Duration>) {
+        let nanos = duration.map_or(u64::MAX, |x| x.as_nanos().min(u64::MAX as u128 - 1) as u64);
+        self.duration.store(nanos, Ordering::Relaxed);
+    }
+
+    /// Copy the levels measured in the buffer where `Mixer::mix_count` reached `mix_count`,
+    /// unless they are being read.
+    fn store_levels(&self, levels: &Levels, mix_count: u64) {
+        // don't wait for a reader in the audio thread
+        if let Ok(mut published) = self.levels.try_lock() {
+            published.copy_from(levels);
+            self.levels_mix.store(mix_count, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A sequence of points that the volume of a sound is linearly interpolated between.
+struct Automation {
+    /// The volume in each point, and the time of the point since the start of the automation.
+    /// Sorted by time.
+    points: Vec<(Duration, f32)>,
+    /// The volume at the start of the automation.
+    start: f32,
+    /// The number of frames already mixed since the start of the automation.
+    pos: u64,
+    /// If set, the sound is stopped when the automation ends, and its volume is restored to this
+    /// value.
+    stop: Option<f32>,
+}
+impl Automation {
+    /// The volume at the given frame, and the frame of the next point after it, if any.
+    fn value_at(&self, frame: u64, sample_rate: u32) -> (f32, Option<u64>) {
+        let mut prev = (0, self.start);
+        for &(at, volume) in &self.points {
+            let at = time::duration_to_frames(at, sample_rate);
+            if frame < at {
+                let x = (frame - prev.0) as f32 / (at - prev.0) as f32;
+                return (prev.1 + (volume - prev.1) * x, Some(at));
+            }
+            prev = (at, volume);
+        }
+        (prev.1, None)
+    }
+}
+
+/// The maximum volume of a sound or group. Larger volumes are clamped to this value.
+pub const MAX_VOLUME: f32 = 16.0;
+
+/// The policy used to choose which sound to stop when the maximum number of voices is reached.
+///
+/// See [`Mixer::set_max_voices`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VoiceStealing {
+    /// Stop the sound that started playing first.
+    #[default]
+    Oldest,
+    /// Stop the sound with the lowest volume, taking the group volume into account.
+    Quietest,
+}
+
+/// The curve used to split a panned sound between the left and right channels.
+///
+/// The gains are normalized so a centered sound keeps its volume, and no gain is above 1.0, so
+/// panning never makes a sound louder: the channel of the side the sound is panned to keeps its
+/// volume, and the other channel is attenuated following the curve of the law, down to silence.
+///
+/// See [`Mixer::set_pan_law`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanLaw {
+    /// The gains change linearly, like a pan law that attenuates the center by 6 dB.
+    Linear,
+    /// The gains follow a sine and cosine curve, like a pan law that keeps the power constant,
+    /// attenuating the center by 3 dB.
+    #[default]
+    ConstantPower,
+    /// A compromise between `Linear` and `ConstantPower`, like a pan law that attenuates the
+    /// center by 4.5 dB.
+    Compromise,
+}
+impl PanLaw {
+    /// The gains of the left and right channels for the given pan, from -1.0 to 1.0.
+    fn gains(self, pan: f32) -> (f32, f32) {
+        use std::f32::consts::FRAC_PI_2;
+        let law = |x: f32| match self {
+            PanLaw::Linear => x,
+            PanLaw::ConstantPower => (x * FRAC_PI_2).sin(),
+            PanLaw::Compromise => (x * (x * FRAC_PI_2).sin()).sqrt(),
+        };
+        let x = (pan + 1.0) / 2.0;
+        let center = law(0.5);
+        let gain = |x: f32| (law(x) / center).min(1.0);
+        (gain(1.0 - x), gain(x))
+    }
+}
+
+type OutputTap = Box<dyn FnMut(&[i16]) + Send>;
+
+/// A wav file that receives the output of the mixer.
+#[cfg(feature = "wav")]
+pub(crate) type Recording = hound::WavWriter<std::io::BufWriter<std::fs::File>>;
+
+type SoundCallback = Box<dyn FnMut() + Send>;
+
+/// A callback called when a sound ends. It is shared, so it can be called after the sound is
+/// removed.
+pub(crate) type EndCallback = Arc<Mutex<dyn FnMut() + Send>>;
+
+/// The duration of the fade-in of the output after [`Mixer::set_config`], that is called on each
+/// stream creation.
+const CONFIG_FADE_IN: Duration = Duration::from_millis(10);
+
+/// A operation queued to be applied to a [`Mixer`].
+///
+/// The command is boxed by the [`MixerHandle`], and sent back to it after being applied, so it is
+/// allocated and freed outside of the audio thread.
+trait Command<G: Eq + Hash + Send + 'static>: Send {
+    /// Apply the operation, if it was not applied yet.
+    fn apply(&mut self, mixer: &mut Mixer<G>);
+}
+impl<G, F> Command<G> for Option<F>
+where
+    G: Eq + Hash + Send + 'static,
+    F: FnOnce(&mut Mixer<G>) + Send,
+{
+    fn apply(&mut self, mixer: &mut Mixer<G>) {
+        if let Some(command) = self.take() {
+            command(mixer);
+        }
+    }
+}
+
+/// The number of commands that can be waiting to be applied by a [`Mixer`], and of values that
+/// can be waiting to be dropped by its [`MixerHandle`].
+const QUEUE_CAPACITY: usize = 1024;
+
+/// The number of sounds a [`Mixer`] has space for when created, so adding sounds doesn't
+/// allocate in the audio thread until this number is exceeded.
+const SOUNDS_CAPACITY: usize = 64;
+
+/// A value removed from a [`Mixer`] that has a [`MixerHandle`], that is sent back to the handle to
+/// be dropped, so no memory is freed in the audio thread.
+///
+/// The values are only held to be dropped. If the queue is full, they are dropped by the mixer.
+/// A removed sound is not boxed, as the box would be freed by the mixer.
+#[allow(dead_code, clippy::large_enum_variant)]
+enum Garbage<G: Eq + Hash + Send + 'static> {
+    Command(Box<dyn Command<G>>),
+    Sound(SoundInner<G>),
+    Source(Box<dyn SoundSource + Send>),
+    Points(Vec<(Duration, f32)>),
+    Callback(SoundCallback),
+    EndCallback(EndCallback),
+    OutputTap(OutputTap),
+    GroupBus(GroupBus),
+    Mirror(Arc<Mutex<Mirror>>),
+}
+
+/// The ends of the queues of a [`MixerHandle`] that are owned by its [`Mixer`].
+struct Link<G: Eq + Hash + Send + 'static> {
+    commands: rtrb::Consumer<Box<dyn Command<G>>>,
+    garbage: rtrb::Producer<Garbage<G>>,
+    published: Arc<Published>,
+}
+
+/// The state of a [`Mixer`] that it publishes to its [`MixerHandle`].
+struct Published {
+    channels: AtomicU16,
+    sample_rate: AtomicU32,
+    /// The value of `Mixer::mix_count` after the last mixed buffer.
+    mix_count: AtomicU64,
+}
+
+/// The key of a group in a [`Mixer`] controlled by a [`MixerHandle`].
+///
+/// The groups are interned by the handle, so they don't need to be shared with the audio thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct GroupKey(usize);
+
+/// The groups and sounds of a mixer, tracked by its [`MixerHandle`] to answer queries without
+/// accessing the mixer.
+struct Registry<G> {
+    groups: HashMap<G, GroupKey>,
+    /// The state and group of each sound added through the handle. The sounds removed from the
+    /// mixer are pruned when the sounds are counted, or a new one is added.
+    sounds: Vec<(Arc<SoundState>, GroupKey)>,
+    group_volumes: HashMap<GroupKey, f32>,
+    master_volume: f32,
+}
+impl<G: Eq + Hash> Registry<G> {
+    /// The key of the given group, interning it if it is new.
+    fn group(&mut self, group: G) -> GroupKey {
+        let len = self.groups.len();
+        *self.groups.entry(group).or_insert(GroupKey(len))
+    }
+
+    /// The sounds that were not removed from the mixer yet.
+    fn sounds(&mut self) -> impl Iterator<Item = &(Arc<SoundState>, GroupKey)> + '_ {
+        self.sounds.retain(|x| x.0.is_alive());
+        self.sounds.iter()
+    }
+}
+
+/// The ends of the queues of a [`MixerHandle`] that are owned by the handle.
+struct Queue {
+    commands: rtrb::Producer<Box<dyn Command<GroupKey>>>,
+    /// The commands that didn't fit in the queue, in order.
+    pending: VecDeque<Box<dyn Command<GroupKey>>>,
+    garbage: rtrb::Consumer<Garbage<GroupKey>>,
+}
+impl Queue {
+    /// Move the pending commands to the queue, while it has space.
+    fn flush(&mut self) {
+        while let Some(command) = self.pending.pop_front() {
+            if let Err(rtrb::PushError::Full(command)) = self.commands.push(command) {
+                self.pending.push_front(command);
+                break;
+            }
+        }
+    }
+}
+
+/// A shared handle to a Mixer, used to control it from other threads.
+///
+/// The mixer is owned by the thread that drives it. Operations are sent to it through a bounded
+/// queue, and applied before it mixes the next buffer, so the audio thread never waits for a lock.
+/// The values removed from the mixer are sent back, to be dropped by the handle. The state read
+/// by the getters is published by the mixer through atomics, or tracked by the handle itself.
+pub(crate) struct MixerHandle<G: Eq + Hash + Send + 'static> {
+    queue: Arc<Mutex<Queue>>,
+    registry: Arc<Mutex<Registry<G>>>,
+    published: Arc<Published>,
+    /// How the sources added through this handle are converted to the format of the mixer.
+    pub(crate) resample_policy: ResamplePolicy,
+    pub(crate) upmix: Upmix,
+}
+impl<G: Eq + Hash + Send + 'static> MixerHandle<G> {
+    /// Create a handle to the given mixer, that will be driven by another thread.
+    pub(crate) fn new(mixer: &mut Mixer<GroupKey>) -> Self {
+        let (commands, command_consumer) = rtrb::RingBuffer::new(QUEUE_CAPACITY);
+        let (garbage_producer, garbage) = rtrb::RingBuffer::new(QUEUE_CAPACITY);
+        let published = Arc::new(Published {
+            channels: AtomicU16::new(mixer.channels),
+            sample_rate: AtomicU32::new(mixer.sample_rate.0),
+            mix_count: AtomicU64::new(mixer.mix_count),
+        });
+        mixer.link = Some(Link {
+            commands: command_consumer,
+            garbage: garbage_producer,
+            published: published.clone(),
+        });
+        let queue = Queue {
+            commands,
+            pending: VecDeque::new(),
+            garbage,
+        };
+        let registry = Registry {
+            groups: HashMap::new(),
+            sounds: Vec::new(),
+            group_volumes: HashMap::new(),
+            master_volume: 1.0,
+        };
+        Self {
+            queue: Arc::new(Mutex::new(queue)),
+            registry: Arc::new(Mutex::new(registry)),
+            published,
+            resample_policy: ResamplePolicy::default(),
+            upmix: Upmix::default(),
+        }
+    }
+
+    /// Queue a command to be applied to the mixer before it outputs its next samples.
+    ///
+    /// If the queue is full, the command is kept by the handle, and sent by a later call.
+    pub(crate) fn command(&self, command: impl FnOnce(&mut Mixer<GroupKey>) + Send + 'static) {
+        let command: Box<dyn Command<GroupKey>> = Box::new(Some(command));
+        let mut queue = self.queue.lock().unwrap();
+        queue.pending.push_back(command);
+        queue.flush();
+        drop(queue);
+        self.collect_garbage();
+    }
+
+    /// Send the commands that didn't fit in the queue, and drop the values sent back by the
+    /// mixer.
+    ///
+    /// This is also called periodically by the thread that handles the output stream, so the
+    /// values are dropped even if no command is sent.
+    pub(crate) fn collect_garbage(&self) {
+        self.queue.lock().unwrap().flush();
+        loop {
+            // dropped without holding the lock, because the values may hold sound handles
+            let garbage = self.queue.lock().unwrap().garbage.pop();
+            match garbage {
+                Ok(garbage) => drop(garbage),
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// The number of channels of the mixer.
+    pub(crate) fn channels(&self) -> u16 {
+        self.published.channels.load(Ordering::Relaxed)
+    }
+
+    /// The sample rate of the mixer.
+    pub(crate) fn sample_rate(&self) -> u32 {
+        self.published.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// Box the given source, wrapping it in converters to match the format of the mixer, following
+    /// the resample policy and upmix of this handle.
+    pub(crate) fn convert_source<T: SoundSource + Send + 'static>(
+        &self,
+        source: T,
+    ) -> Result<Box<dyn SoundSource + Send>, AudioEngineError> {
+        crate::engine::convert_source(
+            source,
+            self.channels(),
+            self.sample_rate(),
+            self.resample_policy,
+            self.upmix,
+        )
+    }
+
+    /// The key of the given group.
+    pub(crate) fn group(&self, group: G) -> GroupKey {
+        self.registry.lock().unwrap().group(group)
+    }
+
+    /// Add a sound to the mixer, returning its id and state.
+    ///
+    /// If `handle` is true, the sound is added with one handle, otherwise it is marked to be
+    /// removed. The sound is created here, and moved to the mixer by a command, so it is not
+    /// allocated in the audio thread.
+    pub(crate) fn add_sound(
+        &self,
+        group: G,
+        source: Box<dyn SoundSource + Send>,
+        handle: bool,
+    ) -> (SoundId, Arc<SoundState>) {
+        let mut registry = self.registry.lock().unwrap();
+        let group = registry.group(group);
+        let mut sound = SoundInner::new(group, source);
+        if handle {
+            sound.handles = 1;
+            sound.drop = false;
+        }
+        let (id, state) = (sound.id, sound.state.clone());
+        registry.sounds.retain(|x| x.0.is_alive());
+        registry.sounds.push((state.clone(), group));
+        drop(registry);
+        self.command(move |m| m.insert_sound(sound));
+        (id, state)
+    }
+
+    /// Move the given sound to another group.
+    pub(crate) fn set_group(&self, id: SoundId, state: &Arc<SoundState>, group: G) {
+        let mut registry = self.registry.lock().unwrap();
+        let group = registry.group(group);
+        if let Some(sound) = registry
+            .sounds
+            .iter_mut()
+            .find(|x| Arc::ptr_eq(&x.0, state))
+        {
+            sound.1 = group;
+        }
+        drop(registry);
+        self.command(move |m| m.set_group(id, group));
+    }
+
+    /// The number of sounds in the mixer, including the sounds that are stopped.
+    pub(crate) fn sound_count(&self) -> usize {
+        self.registry.lock().unwrap().sounds().count()
+    }
+
+    /// The number of sounds that were playing in the last mixed buffer.
+    pub(crate) fn playing_count(&self) -> usize {
+        let mut registry = self.registry.lock().unwrap();
+        registry.sounds().filter(|x| x.0.is_playing()).count()
+    }
+
+    /// The number of sounds in the given group, including the sounds that are stopped.
+    pub(crate) fn group_sound_count(&self, group: &G) -> usize {
+        let mut registry = self.registry.lock().unwrap();
+        let group = match registry.groups.get(group) {
+            Some(&x) => x,
+            None => return 0,
+        };
+        registry.sounds().filter(|x| x.1 == group).count()
+    }
+
+    /// The number of sounds in the given group that were playing in the last mixed buffer.
+    pub(crate) fn group_playing_count(&self, group: &G) -> usize {
+        let mut registry = self.registry.lock().unwrap();
+        let group = match registry.groups.get(group) {
+            Some(&x) => x,
+            None => return 0,
+        };
+        registry
+            .sounds()
+            .filter(|x| x.1 == group && x.0.is_playing())
+            .count()
+    }
+
+    /// Set the volume of the given group. See [`Mixer::set_group_volume`].
+    pub(crate) fn set_group_volume(&self, group: G, volume: f32) {
+        let volume = clamp_param(volume, 0.0, MAX_VOLUME);
+        let mut registry = self.registry.lock().unwrap();
+        let group = registry.group(group);
+        registry.group_volumes.insert(group, volume);
+        drop(registry);
+        self.command(move |m| m.set_group_volume(group, volume));
+    }
+
+    /// The volume of the given group. See [`Mixer::group_volume`].
+    pub(crate) fn group_volume(&self, group: &G) -> f32 {
+        let registry = self.registry.lock().unwrap();
+        let volume = registry
+            .groups
+            .get(group)
+            .and_then(|x| registry.group_volumes.get(x));
+        *volume.unwrap_or(&1.0)
+    }
+
+    /// Set the master volume. See [`Mixer::set_master_volume`].
+    pub(crate) fn set_master_volume(&self, volume: f32) {
+        let volume = clamp_param(volume, 0.0, MAX_VOLUME);
+        self.registry.lock().unwrap().master_volume = volume;
+        self.command(move |m| m.set_master_volume(volume));
+    }
+
+    /// The master volume.
+    pub(crate) fn master_volume(&self) -> f32 {
+        self.registry.lock().unwrap().master_volume
+    }
+
+    /// How much of the given sound was already played. See [`Mixer::playback_position`].
+    pub(crate) fn playback_position(&self, state: &SoundState) -> Option<Duration> {
+        let frames = state.played_frames()?;
+        Some(SampleRate(self.sample_rate()).duration_of(frames))
+    }
+
+    /// The given level of each channel of the given sound, in the last mixed buffer. See
+    /// [`Mixer::sound_peak_level`].
+    pub(crate) fn sound_levels(
+        &self,
+        state: &SoundState,
+        level: fn(&Levels) -> Vec<f32>,
+    ) -> Option<Vec<f32>> {
+        let mix_count = self.published.mix_count.load(Ordering::Relaxed);
+        state.levels(mix_count, self.channels(), level)
+    }
+
+    /// Loop only a region of the given sound. See [`Mixer::set_loop_region`].
+    ///
+    /// The region is validated with the current sample rate of the mixer, so a error is returned
+    /// right away.
+    pub(crate) fn set_loop_region(
+        &self,
+        id: SoundId,
+        start: Duration,
+        end: Duration,
+    ) -> Result<(), AudioEngineError> {
+        let sample_rate = SampleRate(self.sample_rate());
+        if sample_rate.samples_for(start) >= sample_rate.samples_for(end) {
+            return Err(AudioEngineError::InvalidLoopRegion);
+        }
+        self.command(move |m| {
+            // only fails if the sample rate changed in between
+            let _ = m.set_loop_region(id, start, end);
+        });
+        Ok(())
+    }
+}
+impl<G: Eq + Hash + Send + 'static> Clone for MixerHandle<G> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+            registry: self.registry.clone(),
+            published: self.published.clone(),
+            resample_policy: self.resample_policy,
+            upmix: self.upmix,
+        }
+    }
+}
+
+/// A buffer of output samples of a [`Mixer`], that is consumed by another output stream.
+pub(crate) struct Mirror {
+    samples: VecDeque<i16>,
+    /// The maximum number of samples kept in `samples`.
+    max_len: usize,
+}
+impl Mirror {
+    /// Create a new Mirror, that keeps at most `max_len` samples.
+    ///
+    /// If the samples are not consumed fast enough, the oldest ones are dropped, so the latency
+    /// of the mirror stays bounded.
+    pub(crate) fn new(max_len: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(max_len),
+            max_len,
+        }
+    }
+
+    fn push(&mut self, samples: &[i16]) {
+        let samples = &samples[samples.len().saturating_sub(self.max_len)..];
+        let excess = (self.samples.len() + samples.len()).saturating_sub(self.max_len);
+        self.samples.drain(..excess);
+        self.samples.extend(samples);
+    }
+
+    /// Move the oldest samples to `buffer`, filling with silence if there are not enough samples.
+    pub(crate) fn pop(&mut self, buffer: &mut [i16]) {
+        let len = buffer.len().min(self.samples.len());
+        for (out, sample) in buffer.iter_mut().zip(self.samples.drain(..len)) {
+            *out = sample;
         }
+        buffer[len..].fill(0);
     }
 }
 
+/// The sub-mix of a group that has a [`Effect`].
+struct GroupBus {
+    effect: Box<dyn Effect + Send>,
+    /// The mix of the sounds of the group.
+    buffer: Vec<i16>,
+    /// Same as `buffer`, but for mixing `f32` samples. Also used to convert `buffer` to `f32`.
+    buffer_f32: Vec<f32>,
+}
+
 /// Keep track of each Sound, and mix they output together.
+///
+/// All time-based behavior, like [`play_at`](Self::play_at), volume automation, fades and
+/// looping, is counted in samples and only advances in [`write_samples`](SoundSource::write_samples),
+/// so the mixer can be driven deterministically, without a output stream, like in tests. See
+/// [`AudioEngine::headless`](crate::AudioEngine::headless).
 pub struct Mixer<G: Eq + Hash + Send + 'static = ()> {
     sounds: Vec<SoundInner<G>>,
     playing: usize,
     channels: u16,
     sample_rate: SampleRate,
     group_volumes: HashMap<G, f32>,
+    /// Multiplied into the volume of every sound, after its group volume.
+    master_volume: f32,
+    group_buses: HashMap<G, GroupBus>,
+    duckings: Vec<Ducking<G>>,
+    max_voices: usize,
+    group_max_voices: HashMap<G, usize>,
+    voice_stealing: VoiceStealing,
+    /// Incremented every time a sound starts playing.
+    play_counter: u64,
+    /// The length of the current fade-in of the output, in frames.
+    fade_in_len: u64,
+    /// The number of frames of the fade-in that were not mixed yet.
+    fade_in: u64,
+    /// Set when a mixed sample is clipped.
+    clipped: Arc<AtomicBool>,
+    /// The levels of the last outputed buffer.
+    levels: Arc<Mutex<Levels>>,
+    /// Incremented on each mixed buffer.
+    mix_count: u64,
+    /// The end callbacks of the sounds that ended in the buffers mixed since they were last
+    /// called. Its capacity is kept at least the number of sounds, so it never grows while mixing.
+    ended: Vec<EndCallback>,
+    output_tap: Option<OutputTap>,
+    /// The wav file that receives the output, shared with the engine so it is created and
+    /// finalized outside of the audio thread.
+    #[cfg(feature = "wav")]
+    recording: Arc<Mutex<Option<Recording>>>,
+    mirrors: Vec<Arc<Mutex<Mirror>>>,
+    /// The connection to the [`MixerHandle`] that controls this mixer, if any.
+    link: Option<Link<G>>,
+    /// A buffer to temporary hold the samples of each sound.
+    buffer: Vec<i16>,
+    /// Same as `buffer`, but for mixing `f32` samples.
+    buffer_f32: Vec<f32>,
 }
 
 impl<G: Eq + Hash + Send + 'static> Mixer<G> {
@@ -47,18 +879,79 @@ impl<G: Eq + Hash + Send + 'static> Mixer<G> {
     /// configuration can be changed by calling [`set_config`](Self::set_config).
     pub fn new(channels: u16, sample_rate: SampleRate) -> Self {
         Self {
-            sounds: vec![],
+            sounds: Vec::with_capacity(SOUNDS_CAPACITY),
             playing: 0,
             channels,
             sample_rate,
             group_volumes: HashMap::new(),
+            master_volume: 1.0,
+            group_buses: HashMap::new(),
+            duckings: Vec::new(),
+            max_voices: usize::MAX,
+            group_max_voices: HashMap::new(),
+            voice_stealing: VoiceStealing::default(),
+            play_counter: 0,
+            fade_in_len: 0,
+            fade_in: 0,
+            clipped: Arc::new(AtomicBool::new(false)),
+            levels: Arc::new(Mutex::new(Levels::default())),
+            mix_count: 0,
+            ended: Vec::with_capacity(SOUNDS_CAPACITY),
+            output_tap: None,
+            #[cfg(feature = "wav")]
+            recording: Arc::new(Mutex::new(None)),
+            mirrors: Vec::new(),
+            link: None,
+            buffer: Vec::new(),
+            buffer_f32: Vec::new(),
+        }
+    }
+
+    /// Apply all commands queued through a [`MixerHandle`].
+    fn process_commands(&mut self) {
+        while let Some(mut command) = self.link.as_mut().and_then(|x| x.commands.pop().ok()) {
+            command.apply(self);
+            self.dispose(Garbage::Command(command));
+        }
+    }
+
+    /// Send the given value back to the [`MixerHandle`] to be dropped, or drop it if there is no
+    /// handle.
+    fn dispose(&mut self, garbage: Garbage<G>) {
+        if let Some(link) = &mut self.link {
+            // if the queue is full, the value is dropped here
+            let _ = link.garbage.push(garbage);
+        }
+    }
+
+    /// Remove the sound at the given index, like `Vec::swap_remove`.
+    fn remove_sound(&mut self, i: usize) {
+        let sound = self.sounds.swap_remove(i);
+        sound.state.alive.store(false, Ordering::Relaxed);
+        self.dispose(Garbage::Sound(sound));
+    }
+
+    /// Publish the state of the sounds, after a buffer is mixed.
+    fn publish(&self) {
+        for (i, sound) in self.sounds.iter().enumerate() {
+            let state = &sound.state;
+            state.playing.store(i < self.playing, Ordering::Relaxed);
+            state
+                .position
+                .store(sound.played_frames(), Ordering::Relaxed);
+        }
+        if let Some(link) = &self.link {
+            let published = &link.published;
+            published.mix_count.store(self.mix_count, Ordering::Relaxed);
         }
     }
 
     /// Change the number of channels and the sample rate.
     ///
     /// This keep also keep all currently playing sounds, and convert them to the new config, if
-    /// necessary.
+    /// necessary. The output is faded in during a few milliseconds after this is called, even if
+    /// the config didn't change, to avoid a pop when a new output stream starts with sounds
+    /// already playing, and to hide the discontinuity of switching the output device.
     pub fn set_config(&mut self, channels: u16, sample_rate: SampleRate) {
         struct Nop;
         #[rustfmt::skip]
@@ -71,10 +964,27 @@ impl<G: Eq + Hash + Send + 'static> Mixer<G> {
 
         let not_chaged = self.channels == channels && self.sample_rate == sample_rate;
         if not_chaged {
+            self.fade_in_output(CONFIG_FADE_IN);
             return;
         }
         if !self.sounds.is_empty() {
+            let old_rate = self.sample_rate.0;
+            // Computed in u128, to not overflow for far positions and regions.
+            let rescale = |x: u64| {
+                let x = x as u128 * sample_rate.0 as u128 / old_rate as u128;
+                x.min(u64::MAX as u128) as u64
+            };
             for sound in self.sounds.iter_mut() {
+                // the samples read ahead are in the previous config
+                sound.position = sound.played_frames();
+                if let Some(crossfade) = &mut sound.crossfade {
+                    crossfade.len = 0;
+                }
+                sound.position = rescale(sound.position);
+                if let Some((start, end)) = &mut sound.loop_region {
+                    *start = rescale(*start);
+                    *end = rescale(*end).max(start.saturating_add(1));
+                }
                 // FIXME: if the config change multiple times, this will nest multiple converts,
                 // increasing processing and loosing quality.
                 // Maybe I should create something like a tree of converters, and always keep the
@@ -92,6 +1002,41 @@ impl<G: Eq + Hash + Send + 'static> Mixer<G> {
         }
         self.channels = channels;
         self.sample_rate = sample_rate;
+        if let Some(link) = &self.link {
+            let published = &link.published;
+            published.channels.store(channels, Ordering::Relaxed);
+            published
+                .sample_rate
+                .store(sample_rate.0, Ordering::Relaxed);
+        }
+        // taken after the new config is published, so a recording started with the previous
+        // config is always stopped
+        #[cfg(feature = "wav")]
+        if self.recording.lock().unwrap().take().is_some() {
+            log::warn!("the output config changed, stopping the recording");
+        }
+        self.fade_in_output(CONFIG_FADE_IN);
+    }
+
+    /// Fade in the output of the mixer, from silence, during the given duration.
+    fn fade_in_output(&mut self, duration: Duration) {
+        self.fade_in_len = self.sample_rate.samples_for(duration);
+        self.fade_in = self.fade_in_len;
+    }
+
+    /// Apply the current fade-in to the mixed `buffer`.
+    fn apply_fade_in<S: MixSample>(&mut self, buffer: &mut [S]) {
+        let channels = self.channels as usize;
+        for frame in buffer.chunks_mut(channels) {
+            if self.fade_in == 0 {
+                break;
+            }
+            let gain = 1.0 - self.fade_in as f32 / self.fade_in_len as f32;
+            for x in frame {
+                *x = x.scale(gain);
+            }
+            self.fade_in -= 1;
+        }
     }
 
     /// Add new sound to the Mixer.
@@ -100,20 +1045,115 @@ impl<G: Eq + Hash + Send + 'static> Mixer<G> {
     ///
     /// The added sound is started in stopped state, and [`play`](Self::play) must be called to start playing
     /// it. [`mark_to_remove`](Self::mark_to_remove) is true by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sound has zero channels or a sample rate of zero. See
+    /// [`try_add_sound`](Self::try_add_sound).
     pub fn add_sound(&mut self, group: G, sound: Box<dyn SoundSource + Send>) -> SoundId {
+        match self.try_add_sound(group, sound) {
+            Ok(id) => id,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Add new sound to the Mixer, like [`add_sound`](Self::add_sound), but return a error if the
+    /// sound has zero channels or a sample rate of zero, instead of panicking.
+    pub fn try_add_sound(
+        &mut self,
+        group: G,
+        sound: Box<dyn SoundSource + Send>,
+    ) -> Result<SoundId, AudioEngineError> {
+        crate::check_source(&*sound)?;
         let sound_inner = SoundInner::new(group, sound);
         let id = sound_inner.id;
-        self.sounds.push(sound_inner);
-        id
+        self.push_sound(sound_inner);
+        Ok(id)
+    }
+
+    /// Add a sound created by a [`MixerHandle`], wrapping its source in converters if the config
+    /// of the mixer changed since the source was converted.
+    fn insert_sound(&mut self, mut sound: SoundInner<G>) {
+        sound.data = self.convert_source(sound.data);
+        self.push_sound(sound);
+    }
+
+    fn push_sound(&mut self, sound: SoundInner<G>) {
+        self.sounds.push(sound);
+        // each sound ends at most once per buffer
+        self.ended.reserve(self.sounds.len());
+    }
+
+    /// Replace the source of the sound associated with the given id, keeping its id, volume, group
+    /// and all other settings.
+    ///
+    /// The new source is wrapped in converters if its number of channels or sample rate mismatch
+    /// the ones of the Mixer. If the sound is playing, it continues playing from the start of the
+    /// new source. If the new source has zero channels or a sample rate of zero, a error is logged
+    /// and the sound keeps its current source.
+    pub fn replace_source(&mut self, id: SoundId, source: Box<dyn SoundSource + Send>) {
+        if let Err(err) = crate::check_source(&*source) {
+            log::error!("failed to replace source: {}", err);
+            return;
+        }
+        let mut source = self.convert_source(source);
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            sound.state.store_duration(source.duration());
+            std::mem::swap(&mut sound.data, &mut source);
+            sound.position = 0;
+            // the samples read ahead are from the previous source
+            if let Some(crossfade) = &mut sound.crossfade {
+                crossfade.len = 0;
+            }
+        }
+        self.dispose(Garbage::Source(source));
+    }
+
+    /// Wrap the given source in converters if its number of channels or sample rate mismatch the
+    /// ones of the Mixer.
+    pub(crate) fn convert_source(
+        &self,
+        mut source: Box<dyn SoundSource + Send>,
+    ) -> Box<dyn SoundSource + Send> {
+        if source.channels() != self.channels {
+            source = Box::new(converter::ChannelConverter::new(source, self.channels));
+        }
+        if source.sample_rate() != self.sample_rate.0 {
+            source = Box::new(converter::SampleRateConverter::new(
+                source,
+                self.sample_rate.0,
+            ));
+        }
+        source
     }
 
     /// Start playing the sound associated with the given id.
     ///
     /// If the sound was paused or stop, it will start playing again.
     /// Otherwise, does nothing.
+    ///
+    /// If the [maximum number of voices](Self::set_max_voices) is reached, a playing sound is
+    /// stopped to make room for this one, following the [`VoiceStealing`] policy. If there is no
+    /// sound to stop, this sound is not played, and it is removed if it is [marked to be
+    /// removed](Self::mark_to_remove).
     pub fn play(&mut self, id: SoundId) {
+        if !self.make_room_for(id) {
+            if let Some(i) = self.sounds[self.playing..].iter().position(|x| x.id == id) {
+                let i = self.playing + i;
+                // the sound would never end, so it would never be removed
+                if self.sounds[i].drop {
+                    self.remove_sound(i);
+                } else {
+                    self.sounds[i].refused = true;
+                }
+            }
+            return;
+        }
         for i in (self.playing..self.sounds.len()).rev() {
             if self.sounds[i].id == id {
+                self.play_counter += 1;
+                self.sounds[i].started = self.play_counter;
+                self.sounds[i].refused = false;
                 self.sounds.swap(self.playing, i);
                 self.playing += 1;
                 break;
@@ -121,6 +1161,79 @@ impl<G: Eq + Hash + Send + 'static> Mixer<G> {
         }
     }
 
+    /// Start playing the sound associated with the given id after the given number of frames.
+    ///
+    /// The sound starts exactly `delay` frames after the start of the next buffer that is mixed,
+    /// outputing silence until then. If the sound is already playing, does nothing.
+    pub fn play_at(&mut self, id: SoundId, delay: u64) {
+        if !self.sounds[self.playing..].iter().any(|x| x.id == id) {
+            return;
+        }
+        self.play(id);
+        // the play may be refused by the maximum number of voices
+        if let Some(sound) = self.sounds[..self.playing].iter_mut().find(|x| x.id == id) {
+            sound.start_delay = delay;
+        }
+    }
+
+    /// Stop playing sounds, until the sound associated with the given id can be played without
+    /// exceeding the maximum number of voices.
+    ///
+    /// Return false if there is no sound that could be stopped.
+    fn make_room_for(&mut self, id: SoundId) -> bool {
+        loop {
+            let sound = match self.sounds[self.playing..].iter().find(|x| x.id == id) {
+                Some(x) => x,
+                // the sound does not exist, or is already playing
+                None => return true,
+            };
+            let group = &sound.group;
+            let group_max_voices = self
+                .group_max_voices
+                .get(group)
+                .copied()
+                .unwrap_or(usize::MAX);
+            let group_playing = self.sounds[..self.playing]
+                .iter()
+                .filter(|x| &x.group == group)
+                .count();
+
+            let victim = if group_playing >= group_max_voices {
+                self.steal_candidate(Some(group))
+            } else if self.playing >= self.max_voices {
+                self.steal_candidate(None)
+            } else {
+                return true;
+            };
+
+            match victim {
+                Some(victim) => {
+                    log::trace!("stealing voice of sound {}", victim);
+                    self.stop(victim)
+                }
+                None => return false,
+            }
+        }
+    }
+
+    /// Choose a playing sound to be stopped, following the voice stealing policy.
+    ///
+    /// If `group` is some, only sounds in that group are considered.
+    fn steal_candidate(&self, group: Option<&G>) -> Option<SoundId> {
+        let candidates = self.sounds[..self.playing]
+            .iter()
+            .filter(|x| group.is_none_or(|g| &x.group == g));
+        let victim = match self.voice_stealing {
+            VoiceStealing::Oldest => candidates.min_by_key(|x| x.started),
+            VoiceStealing::Quietest => candidates.min_by(|a, b| {
+                let volume =
+                    |x: &SoundInner<G>| x.volume * self.group_volumes.get(&x.group).unwrap_or(&1.0);
+                volume(a).total_cmp(&volume(b))
+            }),
+        };
+        victim.map(|x| x.id)
+    }
+
     /// Pause the sound associated with the given id.
     ///
     /// If the sound is playing, it will pause. If play is called,
@@ -144,16 +1257,18 @@ impl<G: Eq + Hash + Send + 'static> Mixer<G> {
     /// Even if the sound is not playing, it will reset the sound to the start. If the sound is
     /// [marked to be removed](Self::mark_to_remove), this sound will be removed from the Mixer.
     pub fn stop(&mut self, id: SoundId) {
-        for i in (0..self.sounds.len()).rev() {
+        for mut i in (0..self.sounds.len()).rev() {
             if self.sounds[i].id == id {
-                if self.sounds[i].drop {
-                    self.sounds.swap_remove(i);
-                } else {
-                    self.sounds[i].data.reset();
-                }
                 if i < self.playing {
                     self.playing -= 1;
                     self.sounds.swap(self.playing, i);
+                    i = self.playing;
+                }
+                if self.sounds[i].drop {
+                    self.remove_sound(i);
+                } else {
+                    self.sounds[i].reset();
+                    self.sounds[i].start_delay = 0;
                 }
                 break;
             }
@@ -166,333 +1281,2635 @@ impl<G: Eq + Hash + Send + 'static> Mixer<G> {
     pub fn reset(&mut self, id: SoundId) {
         for i in (0..self.sounds.len()).rev() {
             if self.sounds[i].id == id {
-                self.sounds[i].data.reset();
+                self.sounds[i].reset();
                 break;
             }
         }
     }
 
-    /// Set if the sound associated with the given id will loop.
+    /// Move the sound associated with the given id to the given position, measured from its start.
     ///
-    /// If true, ever time the sound reachs its end, it will reset, and continue to play in a loop.
-    ///
-    /// This also set [`mark_to_remove`](Self::mark_to_remove) to false.
-    pub fn set_loop(&mut self, id: SoundId, looping: bool) {
+    /// The sound keeps playing or paused. If the position is past the end of the sound, it ends,
+    /// or loops, in the next buffer. This may decode all samples before the position, see
+    /// [`SoundSource::seek`].
+    pub fn seek(&mut self, id: SoundId, position: Duration) {
+        let frame = self.sample_rate.samples_for(position);
         for i in (0..self.sounds.len()).rev() {
             if self.sounds[i].id == id {
-                self.sounds[i].looping = looping;
+                let sound = &mut self.sounds[i];
+                sound.data.seek(frame);
+                sound.position = frame;
+                if let Some(crossfade) = &mut sound.crossfade {
+                    crossfade.len = 0;
+                }
                 break;
             }
         }
     }
 
-    /// Set the volume of the sound associated with the given id.
+    /// Set the length of the crossfade between the end and the start of the sound associated with
+    /// the given id, when it loops.
     ///
-    /// The output samples of the SoundSource assicociated with the given id will be multiplied by
-    /// this volume.
-    pub fn set_volume(&mut self, id: SoundId, volume: f32) {
-        for i in (0..self.sounds.len()).rev() {
-            if self.sounds[i].id == id {
-                self.sounds[i].volume = volume;
-                break;
+    /// This removes the audible seam when the start and end of a looping sound don't match. To
+    /// crossfade the end of the sound before it is played, the sound is read ahead of the output by
+    /// `frames`. If `frames` is 0, the sound loops without a crossfade.
+    pub fn set_loop_crossfade(&mut self, id: SoundId, frames: u64) {
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            match &mut sound.crossfade {
+                Some(crossfade) => crossfade.frames = frames as usize,
+                None if frames == 0 => {}
+                None => {
+                    sound.crossfade = Some(LoopCrossfade {
+                        frames: frames as usize,
+                        buffer: Vec::new(),
+                        len: 0,
+                        head: Vec::new(),
+                    })
+                }
             }
         }
     }
 
-    /// Set the volume of the given group.
-    ///
-    /// The volume of all sounds associated with this group is multiplied by this volume.
-    pub fn set_group_volume(&mut self, group: G, volume: f32) {
-        self.group_volumes.insert(group, volume);
-    }
-
-    /// Mark if the sound will be removed after it reachs its end.
+    /// Set if the sound associated with the given id will loop.
     ///
-    /// If false, it will be possible to reset the sound and play it again after it has already
-    /// reached its end. Otherwise, the sound will be removed when it reachs its end, even if it is
-    /// marked to loop.
-    pub fn mark_to_remove(&mut self, id: SoundId, drop: bool) {
+    /// If true, ever time the sound reachs its end, it will reset, and continue to play in a loop.
+    /// A sound shorter than the output buffer loops multiple times in each buffer. A sound that
+    /// outputs nothing after a reset would hang the audio thread, so it ends instead, like a sound
+    /// that is not looping: it stops, and is removed if it is [marked to be
+    /// removed](Self::mark_to_remove).
+    pub fn set_loop(&mut self, id: SoundId, looping: bool) {
         for i in (0..self.sounds.len()).rev() {
             if self.sounds[i].id == id {
-                self.sounds[i].drop = drop;
+                self.sounds[i].looping = looping;
                 break;
             }
         }
     }
 
-    /// The number of sounds in the mixer.
+    /// Loop only a region of the sound associated with the given id, from `start` to `end`,
+    /// measured from its start.
     ///
-    /// This include the sounds that are currently stopped.
-    pub fn sound_count(&self) -> usize {
-        self.sounds.len()
-    }
-
-    /// The number of sounds being played currently.
+    /// While the sound is [looping](Self::set_loop), when it reaches `end`, or the end of the
+    /// source, it seeks back to `start` instead of its start. This can be used to play the intro
+    /// of a music only once. Every source can seek, but sources that don't override
+    /// [`SoundSource::seek`] are decoded again from their start on each loop. If the sound is past
+    /// `end` it seeks to `start` in the next buffer. This replaces any previously set region.
     ///
-    /// Does not include the sounds that are currently stopped.
-    pub fn playing_count(&self) -> usize {
-        self.playing
+    /// Return a error if `start` is not before `end`.
+    pub fn set_loop_region(
+        &mut self,
+        id: SoundId,
+        start: Duration,
+        end: Duration,
+    ) -> Result<(), AudioEngineError> {
+        let start = self.sample_rate.samples_for(start);
+        let end = self.sample_rate.samples_for(end);
+        if start >= end {
+            return Err(AudioEngineError::InvalidLoopRegion);
+        }
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            sound.loop_region = Some((start, end));
+        }
+        Ok(())
     }
-}
 
-impl<G: Eq + Hash + Send + 'static> SoundSource for Mixer<G> {
-    fn channels(&self) -> u16 {
-        self.channels
+    /// Remove the region set by [`set_loop_region`](Self::set_loop_region), looping the whole
+    /// sound again.
+    pub fn remove_loop_region(&mut self, id: SoundId) {
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            sound.loop_region = None;
+        }
     }
 
-    fn sample_rate(&self) -> u32 {
-        self.sample_rate.0
+    /// Set a callback that is called each time the sound associated with the given id loops.
+    ///
+    /// The callback is called in the audio thread, during
+    /// [`write_samples`](SoundSource::write_samples), when the source is reset to its start, so it
+    /// should return quickly and avoid allocations. Without a crossfade, this happens in the
+    /// buffer where the start of the sound is outputed. With a [loop
+    /// crossfade](Self::set_loop_crossfade), the source is read ahead, so this may happen up to a
+    /// buffer plus the length of the crossfade earlier. This replaces any previously set callback.
+    pub fn set_on_loop(&mut self, id: SoundId, callback: impl FnMut() + Send + 'static) {
+        self.set_boxed_on_loop(id, Some(Box::new(callback)));
     }
 
-    fn reset(&mut self) {}
+    /// Remove the callback set by [`set_on_loop`](Self::set_on_loop).
+    pub fn remove_on_loop(&mut self, id: SoundId) {
+        self.set_boxed_on_loop(id, None);
+    }
 
-    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
-        if self.playing == 0 {
-            for b in buffer.iter_mut() {
-                *b = 0;
-            }
-            return buffer.len();
+    /// Replace the loop callback of the given sound by a callback already boxed, so it is not
+    /// allocated in the audio thread.
+    pub(crate) fn set_boxed_on_loop(&mut self, id: SoundId, mut callback: Option<SoundCallback>) {
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            std::mem::swap(&mut sound.on_loop, &mut callback);
+        }
+        if let Some(callback) = callback {
+            self.dispose(Garbage::Callback(callback));
         }
+    }
 
-        let mut buf = vec![0; buffer.len()];
-        let mut s = 0;
-        while s < self.playing {
-            let mut len = 0;
-            loop {
-                len += self.sounds[s].data.write_samples(&mut buf[len..]);
-                if len < buffer.len() {
-                    self.sounds[s].data.reset();
-                    if self.sounds[s].looping {
-                        continue;
-                    }
-                }
-                break;
-            }
+    /// Set a callback that is called each time the sound associated with the given id reaches its
+    /// end.
+    ///
+    /// The callback is called in the audio thread, at the end of
+    /// [`write_samples`](SoundSource::write_samples), after the buffer where the last sample of
+    /// the sound is outputed is mixed. It is not called for a looping sound, unless its source is
+    /// empty, nor when the sound is stopped before its end. This replaces any previously set callback.
+    pub fn set_on_end(&mut self, id: SoundId, callback: impl FnMut() + Send + 'static) {
+        self.set_shared_on_end(id, Some(Arc::new(Mutex::new(callback))));
+    }
 
-            let group_volume = *self
-                .group_volumes
-                .get(&self.sounds[s].group)
-                .unwrap_or(&1.0);
-            let volume = self.sounds[s].volume * group_volume;
+    /// Remove the callback set by [`set_on_end`](Self::set_on_end).
+    pub fn remove_on_end(&mut self, id: SoundId) {
+        self.set_shared_on_end(id, None);
+    }
 
-            if (volume - 1.0).abs() < 1.0 / i16::max_value() as f32 {
-                for i in 0..len {
-                    buffer[i] = buffer[i].saturating_add(buf[i]);
-                }
-            } else {
-                for i in 0..len {
-                    buffer[i] = buffer[i].saturating_add((buf[i] as f32 * volume) as i16);
-                }
-            }
+    /// Replace the end callback of the given sound by a callback already allocated, so it is not
+    /// allocated in the audio thread.
+    pub(crate) fn set_shared_on_end(&mut self, id: SoundId, mut callback: Option<EndCallback>) {
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            std::mem::swap(&mut sound.on_end, &mut callback);
+        }
+        if let Some(callback) = callback {
+            self.dispose(Garbage::EndCallback(callback));
+        }
+    }
 
-            if len < buffer.len() {
-                if self.sounds[s].drop {
-                    let _ = self.sounds.swap_remove(s);
-                }
-                self.playing -= 1;
-                if self.playing > 0 && self.playing < self.sounds.len() {
-                    self.sounds.swap(s, self.playing);
-                }
-            } else {
-                s += 1;
-            }
+    /// Set the volume of the sound associated with the given id.
+    ///
+    /// The output samples of the SoundSource assicociated with the given id will be multiplied by
+    /// this volume. The volume is clamped to the range `0.0..=`[`MAX_VOLUME`], and NaN is treated
+    /// as 0.0.
+    pub fn set_volume(&mut self, id: SoundId, volume: f32) {
+        let volume = clamp_param(volume, 0.0, MAX_VOLUME);
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            sound.set_volume(volume);
+            let automation = sound.automation.take();
+            self.dispose_automation(automation);
         }
+    }
 
-        buffer.len()
+    /// Set the stereo position of the sound associated with the given id.
+    ///
+    /// A pan of -1.0 plays the sound only in the left channel, 0.0 in the center, and 1.0 only in
+    /// the right channel. The pan is clamped to this range, and NaN is treated as 0.0. The gain of
+    /// each channel is given by the [pan law](Self::set_pan_law) of the sound.
+    ///
+    /// The pan is ignored if the output is mono. With more than two channels, only the first two
+    /// channels are affected.
+    pub fn set_pan(&mut self, id: SoundId, pan: f32) {
+        let pan = clamp_param(pan, -1.0, 1.0);
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            sound.pan = pan;
+            sound.state.store_pan(pan);
+        }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::SoundSource;
+    /// The stereo position of the sound associated with the given id.
+    ///
+    /// Return `None` if there is no sound associated with the given id.
+    pub fn pan(&self, id: SoundId) -> Option<f32> {
+        self.sounds.iter().find(|x| x.id == id).map(|x| x.pan)
+    }
 
-    use super::Mixer;
+    /// Mute or unmute the sound associated with the given id.
+    ///
+    /// A muted sound outputs silence, but keeps playing, so it stays in sync with the other
+    /// sounds.
+    pub fn set_mute(&mut self, id: SoundId, mute: bool) {
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            sound.muted = mute;
+            sound.state.store_muted(mute);
+        }
+    }
 
-    struct DebugSource {
-        i: usize,
-        v: i16,
-        len: usize,
+    /// If the sound associated with the given id is muted.
+    ///
+    /// Return `None` if there is no sound associated with the given id.
+    pub fn is_muted(&self, id: SoundId) -> Option<bool> {
+        self.sounds.iter().find(|x| x.id == id).map(|x| x.muted)
     }
-    impl DebugSource {
-        fn new(v: i16, len: usize) -> Self {
-            Self { i: 0, v, len }
+
+    /// Solo or unsolo the sound associated with the given id.
+    ///
+    /// While any sound of the mixer is soloed, even if it is not playing, all sounds that are not
+    /// soloed output silence, but keep playing like muted sounds. A muted sound stays silent even
+    /// if soloed.
+    pub fn set_solo(&mut self, id: SoundId, solo: bool) {
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            sound.solo = solo;
+            sound.state.store_solo(solo);
         }
     }
-    impl SoundSource for DebugSource {
-        fn channels(&self) -> u16 {
-            1
+
+    /// If the sound associated with the given id is soloed.
+    ///
+    /// Return `None` if there is no sound associated with the given id.
+    pub fn is_solo(&self, id: SoundId) -> Option<bool> {
+        self.sounds.iter().find(|x| x.id == id).map(|x| x.solo)
+    }
+
+    /// Set the pan law of the sound associated with the given id.
+    ///
+    /// By default, sounds use [`PanLaw::ConstantPower`].
+    pub fn set_pan_law(&mut self, id: SoundId, law: PanLaw) {
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            sound.pan_law = law;
         }
+    }
 
-        fn sample_rate(&self) -> u32 {
-            1
+    /// Move the sound associated with the given id to another group.
+    ///
+    /// From the next mixed buffer, the sound is affected by the volume, ducking and effect of its
+    /// new group. If the sound is playing, it keeps playing even if the new group is already at
+    /// its [maximum number of voices](Self::set_group_max_voices), which is only checked when a
+    /// sound starts playing.
+    pub fn set_group(&mut self, id: SoundId, group: G) {
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            sound.group = group;
         }
+    }
 
-        fn reset(&mut self) {
-            self.i = 0;
+    /// The group of the sound associated with the given id.
+    pub fn group(&self, id: SoundId) -> Option<&G> {
+        self.sounds.iter().find(|x| x.id == id).map(|x| &x.group)
+    }
+
+    /// Automate the volume of the sound associated with the given id.
+    ///
+    /// Each point is a volume and the time, since now, at which the sound reaches it. The volume
+    /// is linearly interpolated between the current volume and the first point, and between each
+    /// following point, and remains at the volume of the last point after it. The time only
+    /// advances while the sound is playing.
+    ///
+    /// The volumes are clamped like in [`set_volume`](Self::set_volume). This replaces any previous
+    /// automation, and is cancelled by [`set_volume`](Self::set_volume).
+    pub fn automate_volume(&mut self, id: SoundId, mut points: Vec<(Duration, f32)>) {
+        for (_, volume) in &mut points {
+            *volume = clamp_param(*volume, 0.0, MAX_VOLUME);
+        }
+        // the handles sort the points before sending them, so this don't allocate
+        if !points.windows(2).all(|x| x[0].0 <= x[1].0) {
+            points.sort_by_key(|x| x.0);
         }
+        self.set_automation(id, points, |sound, points| Automation {
+            points,
+            start: sound.volume,
+            pos: 0,
+            stop: None,
+        });
+    }
 
-        fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
-            for (i, o) in buffer.iter_mut().enumerate() {
-                *o = self.v;
-                self.i += 1;
-                if self.i > self.len {
-                    return i;
-                }
+    /// Replace the automation of the given sound by the one returned by `automation`, that
+    /// receives the sound and `points`.
+    ///
+    /// If there is no such sound, `points` is disposed, so it is never freed in the audio thread.
+    fn set_automation(
+        &mut self,
+        id: SoundId,
+        points: Vec<(Duration, f32)>,
+        automation: impl FnOnce(&mut SoundInner<G>, Vec<(Duration, f32)>) -> Automation,
+    ) {
+        let previous = match self.sounds.iter_mut().find(|x| x.id == id) {
+            Some(sound) => {
+                let automation = automation(sound, points);
+                sound.automation.replace(automation)
             }
-            buffer.len()
+            None => Some(Automation {
+                points,
+                start: 0.0,
+                pos: 0,
+                stop: None,
+            }),
+        };
+        self.dispose_automation(previous);
+    }
+
+    fn dispose_automation(&mut self, automation: Option<Automation>) {
+        if let Some(automation) = automation {
+            self.dispose(Garbage::Points(automation.points));
         }
     }
 
-    #[test]
-    fn start_stopped() {
-        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+    /// Fade in the sound associated with the given id, from silence to its current volume, during
+    /// the given duration.
+    ///
+    /// This is a [automation](Self::automate_volume) of the volume, so the fade only advances
+    /// while the sound is playing, and is cancelled by [`set_volume`](Self::set_volume).
+    pub fn fade_in(&mut self, id: SoundId, duration: Duration) {
+        self.fade_in_with(id, duration, Vec::with_capacity(1));
+    }
 
-        // create new sound
-        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 5)));
+    /// Like [`fade_in`](Self::fade_in), but reusing `points` for the point of the automation, so it
+    /// is not allocated in the audio thread.
+    pub(crate) fn fade_in_with(
+        &mut self,
+        id: SoundId,
+        duration: Duration,
+        points: Vec<(Duration, f32)>,
+    ) {
+        self.set_automation(id, points, |sound, mut points| {
+            points.clear();
+            points.push((duration, sound.volume));
+            sound.set_volume(0.0);
+            Automation {
+                points,
+                start: 0.0,
+                pos: 0,
+                stop: None,
+            }
+        });
+    }
 
-        // sound start in stopped state
-        let mut buffer = [0; 10];
-        assert_eq!(mixer.write_samples(&mut buffer), 10);
-        assert_eq!(buffer, [0; 10]);
+    /// Fade out the sound associated with the given id, from its current volume to silence,
+    /// during the given duration.
+    ///
+    /// The sound keeps playing, with a volume of 0.0. See [`fade_in`](Self::fade_in).
+    pub fn fade_out(&mut self, id: SoundId, duration: Duration) {
+        self.automate_volume(id, vec![(duration, 0.0)]);
+    }
 
-        // play the sound
-        mixer.play(id);
-        assert_eq!(mixer.write_samples(&mut buffer), 10);
-        assert_eq!(buffer, [2, 2, 2, 2, 2, 0, 0, 0, 0, 0]);
+    /// Fade out the sound associated with the given id like in [`fade_out`](Self::fade_out), and
+    /// [stop](Self::stop) it when the fade ends.
+    ///
+    /// After it stops, the volume of the sound is restored to the one before the fade, so it can
+    /// be played again.
+    pub fn fade_out_and_stop(&mut self, id: SoundId, duration: Duration) {
+        self.fade_out_and_stop_with(id, duration, Vec::with_capacity(1));
+    }
 
-        // mark to remove is true, sound was removed
-        mixer.reset(id);
-        mixer.play(id);
-        assert_eq!(mixer.write_samples(&mut buffer), 10);
-        assert_eq!(buffer, [0; 10]);
+    /// Like [`fade_out_and_stop`](Self::fade_out_and_stop), but reusing `points` like in
+    /// [`fade_in_with`](Self::fade_in_with).
+    pub(crate) fn fade_out_and_stop_with(
+        &mut self,
+        id: SoundId,
+        duration: Duration,
+        points: Vec<(Duration, f32)>,
+    ) {
+        self.set_automation(id, points, |sound, mut points| {
+            points.clear();
+            points.push((duration, 0.0));
+            Automation {
+                points,
+                start: sound.volume,
+                pos: 0,
+                stop: Some(sound.volume),
+            }
+        });
+    }
 
-        // create new sound
-        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 5)));
+    /// The total duration of the sound associated with the given id, if it is known.
+    ///
+    /// Return `None` if the duration is unknown, or if there is no sound associated with the given
+    /// id. See [`SoundSource::duration`].
+    pub fn duration(&self, id: SoundId) -> Option<Duration> {
+        let sound = self.sounds.iter().find(|x| x.id == id)?;
+        sound.data.duration()
+    }
 
-        mixer.stop(id);
+    /// How much of the sound associated with the given id was already played, since its start or
+    /// its last loop.
+    ///
+    /// This is measured in the frames output by the mixer, so it is the position in real time
+    /// even if the sound is resampled. Return `None` if there is no sound associated with the
+    /// given id.
+    pub fn playback_position(&self, id: SoundId) -> Option<Duration> {
+        let sound = self.sounds.iter().find(|x| x.id == id)?;
+        Some(self.sample_rate.duration_of(sound.played_frames()))
     }
 
-    #[test]
-    fn mark_to_remove_true() {
-        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+    /// The volume of the sound associated with the given id.
+    ///
+    /// Return `None` if there is no sound associated with the given id.
+    pub fn volume(&self, id: SoundId) -> Option<f32> {
+        self.sounds.iter().find(|x| x.id == id).map(|x| x.volume)
+    }
 
-        // create new sound
-        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 5)));
+    /// Set the volume of the given group.
+    ///
+    /// The volume of all sounds associated with this group is multiplied by this volume. The
+    /// volume is clamped like in [`set_volume`](Self::set_volume).
+    pub fn set_group_volume(&mut self, group: G, volume: f32) {
+        let volume = clamp_param(volume, 0.0, MAX_VOLUME);
+        self.group_volumes.insert(group, volume);
+    }
 
-        // stop the sound will remove the sound if marked to remove is true
-        mixer.stop(id);
+    /// The volume of the given group.
+    ///
+    /// If the volume of the group was never set, this is 1.0.
+    pub fn group_volume(&self, group: &G) -> f32 {
+        *self.group_volumes.get(group).unwrap_or(&1.0)
+    }
 
-        // mark to remove is true, sound was removed
-        mixer.reset(id);
-        mixer.play(id);
-        let mut buffer = [0; 10];
-        assert_eq!(mixer.write_samples(&mut buffer), 10);
-        assert_eq!(buffer, [0; 10]);
+    /// Set the master volume.
+    ///
+    /// The volume of all sounds is multiplied by this volume, after their group volume. The volume
+    /// is clamped like in [`set_volume`](Self::set_volume).
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = clamp_param(volume, 0.0, MAX_VOLUME);
     }
 
-    #[test]
-    fn mark_to_remove_false() {
-        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+    /// The master volume. This is 1.0 by default.
+    pub fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
 
-        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 5)));
-        mixer.mark_to_remove(id, false);
+    /// Set a effect that processes the sub-mix of the given group.
+    ///
+    /// The sounds of the group are mixed in a separated buffer, after applying their volume and
+    /// the group volume, which is processed by the effect and then added to the output. This
+    /// replaces any previous effect of the group.
+    pub fn set_group_effect(&mut self, group: G, effect: Box<dyn Effect + Send>) {
+        let previous = self.group_buses.insert(
+            group,
+            GroupBus {
+                effect,
+                buffer: Vec::new(),
+                buffer_f32: Vec::new(),
+            },
+        );
+        if let Some(bus) = previous {
+            self.dispose(Garbage::GroupBus(bus));
+        }
+    }
 
-        // stop the sound will not remove the sound if marked to remove is false
-        mixer.stop(id);
+    /// Remove the effect set by [`set_group_effect`](Self::set_group_effect).
+    pub fn remove_group_effect(&mut self, group: &G) {
+        if let Some(bus) = self.group_buses.remove(group) {
+            self.dispose(Garbage::GroupBus(bus));
+        }
+    }
 
-        // sound start in stopped state
-        let mut buffer = [0; 10];
-        assert_eq!(mixer.write_samples(&mut buffer), 10);
-        assert_eq!(buffer, [0; 10]);
+    /// Set the maximum number of sounds that can be playing at the same time.
+    ///
+    /// When a sound starts playing while this number is reached, another playing sound is stopped,
+    /// following the policy set by [`set_voice_stealing`](Self::set_voice_stealing). Sounds that
+    /// are already playing when the maximum is lowered are not stopped.
+    ///
+    /// By default there is no limit.
+    pub fn set_max_voices(&mut self, max_voices: usize) {
+        self.max_voices = max_voices;
+    }
 
-        // play the sound
-        mixer.play(id);
-        assert_eq!(mixer.playing_count(), 1);
-        assert_eq!(mixer.write_samples(&mut buffer), 10);
-        assert_eq!(buffer, [2, 2, 2, 2, 2, 0, 0, 0, 0, 0]);
+    /// Set the maximum number of sounds of the given group that can be playing at the same time.
+    ///
+    /// This works like [`set_max_voices`](Self::set_max_voices), but only sounds in the given group
+    /// are counted and stopped.
+    pub fn set_group_max_voices(&mut self, group: G, max_voices: usize) {
+        self.group_max_voices.insert(group, max_voices);
+    }
 
-        // mark to remove is false
-        mixer.play(id);
-        buffer = [0; 10];
-        assert_eq!(mixer.playing_count(), 1);
-        assert_eq!(mixer.write_samples(&mut buffer), 10);
-        assert_eq!(buffer, [2, 2, 2, 2, 2, 0, 0, 0, 0, 0]);
+    /// Set the policy used to choose which sound to stop when the maximum number of voices is
+    /// reached.
+    ///
+    /// The default is [`VoiceStealing::Oldest`].
+    pub fn set_voice_stealing(&mut self, policy: VoiceStealing) {
+        self.voice_stealing = policy;
     }
 
-    #[test]
-    fn volume() {
-        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+    /// Set a callback that receives each buffer of samples outputed by the mixer.
+    ///
+    /// The callback is called at the end of each call to
+    /// [`write_samples`](SoundSource::write_samples), in the audio thread, so it should return
+    /// quickly and avoid allocations. This replaces any previously set callback.
+    pub fn set_output_tap(&mut self, tap: impl FnMut(&[i16]) + Send + 'static) {
+        self.set_boxed_output_tap(Some(Box::new(tap)));
+    }
 
-        let id0 = mixer.add_sound((), Box::new(DebugSource::new(10, 2)));
-        let id1 = mixer.add_sound((), Box::new(DebugSource::new(10, 4)));
-        let id2 = mixer.add_sound((), Box::new(DebugSource::new(10, 6)));
+    /// Remove the callback set by [`set_output_tap`](Self::set_output_tap).
+    pub fn remove_output_tap(&mut self) {
+        self.set_boxed_output_tap(None);
+    }
 
-        mixer.set_volume(id0, 0.2);
-        mixer.set_volume(id1, 0.4);
-        mixer.set_volume(id2, 0.8);
+    /// Replace the output tap by a callback already boxed, so it is not allocated in the audio
+    /// thread.
+    pub(crate) fn set_boxed_output_tap(&mut self, tap: Option<OutputTap>) {
+        if let Some(tap) = std::mem::replace(&mut self.output_tap, tap) {
+            self.dispose(Garbage::OutputTap(tap));
+        }
+    }
 
-        mixer.play(id0);
-        mixer.play(id1);
-        mixer.play(id2);
+    /// Return true if any outputed sample was clipped since the last call to this method.
+    ///
+    /// A sample is clipped when the sum of the sounds exceeds the maximum sample value, which
+    /// distorts the output. This can be used to show a clip indicator, or to lower the volume.
+    /// This is checked at the end of each call to [`write_samples`](SoundSource::write_samples),
+    /// and can be read without accessing the mixer through [`AudioEngine`](crate::AudioEngine).
+    pub fn clipped_since_last_check(&self) -> bool {
+        self.clipped.swap(false, Ordering::Relaxed)
+    }
 
-        let mut buffer = [0; 10];
-        assert_eq!(mixer.write_samples(&mut buffer), 10);
-        assert_eq!(buffer, [14, 14, 12, 12, 8, 8, 0, 0, 0, 0]);
+    /// The flag returned by [`clipped_since_last_check`](Self::clipped_since_last_check).
+    pub(crate) fn clipped_flag(&self) -> Arc<AtomicBool> {
+        self.clipped.clone()
     }
 
-    #[test]
-    fn group_volume() {
-        #[derive(Eq, Hash, PartialEq)]
-        enum Group {
+    /// The peak level of each channel of the most recently outputed buffer.
+    ///
+    /// The level is the absolute value of the samples, where 1.0 is the maximum value of the
+    /// output, and is measured after all volumes and group effects are applied. Before the first
+    /// buffer, this is empty.
+    pub fn peak_level(&self) -> Vec<f32> {
+        self.levels.lock().unwrap().peak()
+    }
+
+    /// The RMS (root mean square) level of each channel of the most recently outputed buffer.
+    ///
+    /// See [`peak_level`](Self::peak_level).
+    pub fn rms_level(&self) -> Vec<f32> {
+        self.levels.lock().unwrap().rms()
+    }
+
+    /// The peak level of each channel of the sound associated with the given id, in the most
+    /// recently mixed buffer.
+    ///
+    /// The level is measured after the volume of the sound, its group and the master volume are
+    /// applied, using the volume at the end of the buffer, but before any group effect. If the
+    /// sound was not mixed in the last buffer, like when paused, the levels are 0.0. Return `None`
+    /// if there is no sound associated with the given id.
+    pub fn sound_peak_level(&self, id: SoundId) -> Option<Vec<f32>> {
+        let sound = self.sounds.iter().find(|x| x.id == id)?;
+        Some(self.sound_levels(sound, Levels::peak))
+    }
+
+    /// The RMS level of each channel of the sound associated with the given id, in the most
+    /// recently mixed buffer.
+    ///
+    /// See [`sound_peak_level`](Self::sound_peak_level).
+    pub fn sound_rms_level(&self, id: SoundId) -> Option<Vec<f32>> {
+        let sound = self.sounds.iter().find(|x| x.id == id)?;
+        Some(self.sound_levels(sound, Levels::rms))
+    }
+
+    fn sound_levels(&self, sound: &SoundInner<G>, level: fn(&Levels) -> Vec<f32>) -> Vec<f32> {
+        if sound.levels_mix == self.mix_count {
+            level(&sound.levels)
+        } else {
+            vec![0.0; self.channels as usize]
+        }
+    }
+
+    /// The levels of the output, to be read without accessing the mixer.
+    pub(crate) fn levels(&self) -> Arc<Mutex<Levels>> {
+        self.levels.clone()
+    }
+
+    /// Measure the levels of the output `buffer`.
+    fn measure_levels<S: MixSample>(&self, buffer: &[S]) {
+        // don't wait for a reader in the audio thread
+        if let Ok(mut levels) = self.levels.try_lock() {
+            levels.measure(buffer, self.channels as usize, 1.0);
+        }
+    }
+
+    /// Set the clipped flag if any sample of `buffer` was clipped.
+    fn detect_clipping<S: MixSample>(&self, buffer: &[S]) {
+        if buffer.iter().any(|x| x.is_clipped()) {
+            self.clipped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The slot of the wav file the output of the mixer is written to, if any.
+    ///
+    /// The file must have the same number of channels and sample rate as the mixer. The recording
+    /// is dropped, finalizing the file, if the config of the mixer changes.
+    #[cfg(feature = "wav")]
+    pub(crate) fn recording(&self) -> Arc<Mutex<Option<Recording>>> {
+        self.recording.clone()
+    }
+
+    /// If the output samples are sent to anything, to avoid converting them otherwise.
+    fn has_output_receiver(&self) -> bool {
+        #[cfg(feature = "wav")]
+        if self.recording.try_lock().is_ok_and(|x| x.is_some()) {
+            return true;
+        }
+        self.output_tap.is_some() || !self.mirrors.is_empty()
+    }
+
+    /// Copy the output of the mixer to the given buffer, to be played by another stream.
+    ///
+    /// The mirror is removed once the mixer holds the only reference to it.
+    pub(crate) fn add_mirror(&mut self, mirror: Arc<Mutex<Mirror>>) {
+        self.mirrors.push(mirror);
+    }
+
+    /// Call the output tap, feed the mirrors and write the recording with the given output samples.
+    fn send_output(&mut self, buffer: &[i16]) {
+        if let Some(tap) = &mut self.output_tap {
+            tap(buffer);
+        }
+        // don't wait for the recording to be started or stopped in the audio thread
+        #[cfg(feature = "wav")]
+        if let Ok(mut slot) = self.recording.try_lock() {
+            if let Some(recording) = &mut *slot {
+                let result = buffer.iter().try_for_each(|&x| recording.write_sample(x));
+                if let Err(err) = result {
+                    log::error!("failed to write the recording, stopping it: {}", err);
+                    *slot = None;
+                }
+            }
+        }
+        let mut i = 0;
+        while i < self.mirrors.len() {
+            if Arc::strong_count(&self.mirrors[i]) > 1 {
+                i += 1;
+            } else {
+                let mirror = self.mirrors.swap_remove(i);
+                self.dispose(Garbage::Mirror(mirror));
+            }
+        }
+        for mirror in &self.mirrors {
+            mirror.lock().unwrap().push(buffer);
+        }
+    }
+
+    /// Mark if the sound will be removed after it reachs its end.
+    ///
+    /// If false, it will be possible to reset the sound and play it again after it has already
+    /// reached its end. Otherwise, the sound will be removed when it reachs its end, even if it is
+    /// marked to loop.
+    pub fn mark_to_remove(&mut self, id: SoundId, drop: bool) {
+        for i in (0..self.sounds.len()).rev() {
+            if self.sounds[i].id == id {
+                self.sounds[i].drop = drop;
+                break;
+            }
+        }
+    }
+
+    /// Increment the number of `Sound` handles of the given sound.
+    ///
+    /// While there is any handle, the sound is not marked to be removed.
+    pub(crate) fn add_handle(&mut self, id: SoundId) {
+        if let Some(sound) = self.sounds.iter_mut().find(|x| x.id == id) {
+            sound.handles += 1;
+            sound.drop = false;
+        }
+    }
+
+    /// Decrement the number of `Sound` handles of the given sound, marking it to be removed when
+    /// there is none left.
+    ///
+    /// If the last play of the sound was refused, it is removed right away.
+    pub(crate) fn remove_handle(&mut self, id: SoundId) {
+        if let Some(i) = self.sounds.iter().position(|x| x.id == id) {
+            let sound = &mut self.sounds[i];
+            sound.handles -= 1;
+            if sound.handles == 0 {
+                sound.drop = true;
+                if sound.refused {
+                    self.remove_sound(i);
+                }
+            }
+        }
+    }
+
+    /// Return true if there is a sound with the given id in the mixer.
+    pub fn contains(&self, id: SoundId) -> bool {
+        self.sounds.iter().any(|x| x.id == id)
+    }
+
+    /// The number of sounds in the mixer.
+    ///
+    /// This include the sounds that are currently stopped.
+    pub fn sound_count(&self) -> usize {
+        self.sounds.len()
+    }
+
+    /// The number of sounds being played currently.
+    ///
+    /// Does not include the sounds that are currently stopped.
+    pub fn playing_count(&self) -> usize {
+        self.playing
+    }
+
+    /// The number of sounds in the mixer that are associated with the given group.
+    ///
+    /// This include the sounds that are currently stopped.
+    pub fn group_sound_count(&self, group: &G) -> usize {
+        self.sounds.iter().filter(|x| &x.group == group).count()
+    }
+
+    /// The number of sounds associated with the given group that are being played currently.
+    pub fn group_playing_count(&self, group: &G) -> usize {
+        self.sounds[..self.playing]
+            .iter()
+            .filter(|x| &x.group == group)
+            .count()
+    }
+
+    /// The ids of all sounds in the mixer that are associated with the given group.
+    ///
+    /// This include the sounds that are currently stopped.
+    pub fn sound_ids_in_group(&self, group: &G) -> Vec<SoundId> {
+        self.sounds
+            .iter()
+            .filter(|x| &x.group == group)
+            .map(|x| x.id)
+            .collect()
+    }
+
+    /// Lower the volume of the `target` group while any sound of the `trigger` group is playing.
+    ///
+    /// The volume of `target` is multiplied by `1.0 - amount`, where `amount` goes from 0.0 (no
+    /// ducking) to 1.0 (silence). The volume is lowered linearly during `attack` after a sound of
+    /// `trigger` starts playing, and raised back during `release` after all sounds of `trigger`
+    /// stop. This replaces any previous ducking between the same groups.
+    pub fn set_ducking(
+        &mut self,
+        trigger: G,
+        target: G,
+        amount: f32,
+        attack: Duration,
+        release: Duration,
+    ) {
+        let amount = clamp_param(amount, 0.0, 1.0);
+        if let Some(ducking) = self
+            .duckings
+            .iter_mut()
+            .find(|x| x.trigger == trigger && x.target == target)
+        {
+            ducking.amount = amount;
+            ducking.attack = attack;
+            ducking.release = release;
+            return;
+        }
+        self.duckings.push(Ducking {
+            trigger,
+            target,
+            amount,
+            attack,
+            release,
+            start: 0.0,
+            end: 0.0,
+        });
+    }
+
+    /// Remove the ducking set by [`set_ducking`](Self::set_ducking) between the given groups.
+    pub fn remove_ducking(&mut self, trigger: &G, target: &G) {
+        self.duckings
+            .retain(|x| &x.trigger != trigger || &x.target != target);
+    }
+
+    /// Advance the ducking of each group by the given number of frames.
+    fn update_duckings(&mut self, frames: u64) {
+        let playing = &self.sounds[..self.playing];
+        for ducking in &mut self.duckings {
+            let active = playing.iter().any(|x| x.group == ducking.trigger);
+            let (time, direction) = if active {
+                (ducking.attack, 1.0)
+            } else {
+                (ducking.release, -1.0)
+            };
+            let len = time.as_secs_f32() * self.sample_rate.0 as f32;
+            let step = if len > 0.0 { frames as f32 / len } else { 1.0 };
+            ducking.start = ducking.end;
+            ducking.end = (ducking.end + step * direction).clamp(0.0, 1.0);
+        }
+    }
+
+    /// The multiplier applied to the volume of the given group due to ducking, in the start and
+    /// in the end of the current buffer.
+    fn ducking_gain(&self, group: &G) -> (f32, f32) {
+        let mut gain = (1.0, 1.0);
+        for ducking in self.duckings.iter().filter(|x| &x.target == group) {
+            gain.0 *= 1.0 - ducking.amount * ducking.start;
+            gain.1 *= 1.0 - ducking.amount * ducking.end;
+        }
+        gain
+    }
+
+    /// Mix the output of all playing sounds into `buffer`.
+    fn mix<S: MixSample>(&mut self, buffer: &mut [S]) {
+        for b in buffer.iter_mut() {
+            *b = S::default();
+        }
+
+        let channels = self.channels as usize;
+        let frames = (buffer.len() / channels) as u64;
+        self.mix_count += 1;
+
+        self.update_duckings(frames);
+
+        if self.playing == 0 && self.group_buses.is_empty() {
+            return;
+        }
+
+        // the sounds of groups with a effect are mixed in the buffer of their group
+        let mut buses = std::mem::take(&mut self.group_buses);
+        for bus in buses.values_mut() {
+            let bus = S::bus(bus);
+            if bus.len() < buffer.len() {
+                bus.resize(buffer.len(), S::default());
+            }
+            bus[..buffer.len()].fill(S::default());
+        }
+
+        let mut scratch = std::mem::take(S::scratch(self));
+        // Only allocates if the buffer is larger than any previous one.
+        if scratch.len() < buffer.len() {
+            scratch.resize(buffer.len(), S::default());
+        }
+        let buf = &mut scratch[..buffer.len()];
+        let any_solo = self.sounds.iter().any(|x| x.solo);
+
+        let mut s = 0;
+        while s < self.playing {
+            // the sound may only start in the middle of the buffer
+            let delay = self.sounds[s].start_delay;
+            if delay >= frames {
+                self.sounds[s].start_delay -= frames;
+                s += 1;
+                continue;
+            }
+            self.sounds[s].start_delay = 0;
+            let offset = delay as usize * channels;
+            let out = match buses.get_mut(&self.sounds[s].group) {
+                Some(bus) => &mut S::bus(bus)[offset..buffer.len()],
+                None => &mut buffer[offset..],
+            };
+            let buf = &mut buf[..out.len()];
+
+            let sound = &mut self.sounds[s];
+            let mut len = 0;
+            let region = sound.loop_region.filter(|_| sound.looping);
+            if let Some(crossfade) = &mut sound.crossfade {
+                let on_loop = &mut sound.on_loop;
+                let position = &mut sound.position;
+                let looping = sound.looping;
+                len = crossfade.write(&mut *sound.data, buf, looping, region, position, || {
+                    if let Some(on_loop) = on_loop {
+                        on_loop();
+                    }
+                });
+                if len < out.len() {
+                    sound.reset();
+                }
+            } else {
+                let mut reset = false;
+                loop {
+                    let mut end = buf.len();
+                    if let Some((_, region_end)) = region {
+                        let frames = region_end.saturating_sub(sound.position) as usize;
+                        end = end.min(len.saturating_add(frames.saturating_mul(channels)));
+                    }
+                    let n = S::write(&mut *sound.data, &mut buf[len..end]);
+                    len += n;
+                    sound.position = sound.position.saturating_add((n / channels) as u64);
+                    // a source that is empty after a reset would loop forever
+                    if len < out.len() && !(reset && n == 0) {
+                        reset = true;
+                        if let Some((start, _)) = region {
+                            sound.data.seek(start);
+                            sound.position = start;
+                        } else {
+                            sound.data.reset();
+                            sound.position = 0;
+                        }
+                        if sound.looping {
+                            if let Some(on_loop) = &mut sound.on_loop {
+                                on_loop();
+                            }
+                            continue;
+                        }
+                    }
+                    break;
+                }
+                if len < out.len() && region.is_some() {
+                    // the region is empty, so the sound ends
+                    sound.reset();
+                }
+            }
+
+            if channels >= 2 && sound.pan != 0.0 {
+                let (left, right) = sound.pan_law.gains(sound.pan);
+                for frame in buf[..len].chunks_exact_mut(channels) {
+                    frame[0] = frame[0].scale(left);
+                    frame[1] = frame[1].scale(right);
+                }
+            }
+
+            let sound = &self.sounds[s];
+            let group = &sound.group;
+            // silenced sounds are still read, so they keep in sync with the others
+            let silenced = sound.muted || (any_solo && !sound.solo);
+            let group_volume = *self.group_volumes.get(group).unwrap_or(&1.0)
+                * self.master_volume
+                * if silenced { 0.0 } else { 1.0 };
+            let volume = sound.volume * group_volume;
+            let (duck_start, duck_end) = self.ducking_gain(group);
+
+            let mut stop = false;
+            if let Some(automation) = &mut self.sounds[s].automation {
+                let sample_rate = self.sample_rate.0;
+                let duck = |frame: usize| {
+                    let x = (delay as usize + frame) as f32 / frames as f32;
+                    duck_start + (duck_end - duck_start) * x
+                };
+
+                // ramp the volume between each point of the automation in the buffer
+                let len_frames = len / channels;
+                let mut a = 0;
+                while a < len_frames {
+                    let (from, next) = automation.value_at(automation.pos + a as u64, sample_rate);
+                    let b = next.map_or(len_frames, |t| {
+                        ((t - automation.pos) as usize).min(len_frames)
+                    });
+                    let (to, _) = automation.value_at(automation.pos + b as u64, sample_rate);
+                    let start = from * group_volume * duck(a);
+                    let step = (to * group_volume * duck(b) - start) / (b - a) as f32;
+                    let range = a * channels..b * channels;
+                    S::mix_ramp(&mut out[range.clone()], &buf[range], channels, start, step);
+                    a = b;
+                }
+
+                automation.pos += len_frames as u64;
+                let (volume, next) = automation.value_at(automation.pos, sample_rate);
+                let restore = automation.stop;
+                self.sounds[s].set_volume(volume);
+                if next.is_none() {
+                    if let Some(volume) = restore {
+                        self.sounds[s].set_volume(volume);
+                        stop = true;
+                    }
+                    let automation = self.sounds[s].automation.take();
+                    self.dispose_automation(automation);
+                }
+            } else if duck_start != duck_end {
+                // ramp the volume through the buffer, to avoid clicks
+                let step = volume * (duck_end - duck_start) / frames as f32;
+                let start = volume * duck_start + step * delay as f32;
+                S::mix_ramp(&mut out[..len], &buf[..len], channels, start, step);
+            } else if (volume * duck_end - 1.0).abs() < 1.0 / i16::MAX as f32 {
+                S::mix(&mut out[..len], &buf[..len]);
+            } else {
+                S::mix_volume(&mut out[..len], &buf[..len], volume * duck_end);
+            }
+
+            let sound = &mut self.sounds[s];
+            let gain = sound.volume * group_volume * duck_end;
+            sound.levels.measure(&buf[..len], channels, gain);
+            sound.levels_mix = self.mix_count;
+            sound.state.store_levels(&sound.levels, self.mix_count);
+
+            if stop && len == out.len() {
+                // the sound was faded out, but not ended, so it restarts on the next play
+                self.sounds[s].reset();
+            }
+            if len < out.len() {
+                if let Some(on_end) = &self.sounds[s].on_end {
+                    self.ended.push(on_end.clone());
+                }
+            }
+            if len < out.len() || stop {
+                if self.sounds[s].drop {
+                    self.remove_sound(s);
+                }
+                self.playing -= 1;
+                if self.playing > 0 && self.playing < self.sounds.len() {
+                    self.sounds.swap(s, self.playing);
+                }
+            } else {
+                s += 1;
+            }
+        }
+
+        for bus in buses.values_mut() {
+            S::apply_effect(bus, buffer.len(), self.channels, self.sample_rate.0);
+            S::mix(buffer, &S::bus(bus)[..buffer.len()]);
+        }
+        self.group_buses = buses;
+
+        *S::scratch(self) = scratch;
+    }
+}
+
+/// Crossfades the end of a looping sound with its start.
+///
+/// To know where the source ends before its end is mixed, the source is read ahead of the output
+/// by the length of the crossfade.
+struct LoopCrossfade {
+    /// The length of the crossfade, in frames. If 0, the sound loops without a crossfade.
+    frames: usize,
+    /// Samples already read from the source, but not yet written.
+    buffer: Vec<f32>,
+    /// The number of valid samples in `buffer`.
+    len: usize,
+    /// The first samples of the source, read on each loop to be crossfaded with its end.
+    head: Vec<f32>,
+}
+impl LoopCrossfade {
+    /// Write the samples of `source` to `out`, returning how many samples were written.
+    ///
+    /// If `looping` is true, the source is reset on its end, calling `on_loop`, and its end is
+    /// crossfaded with its start. Otherwise this returns less than the length of `out` after the
+    /// end of the source. If `region` is some, only the frames from its start to its end are
+    /// looped, seeking to its start instead of resetting the source.
+    ///
+    /// `position` is the number of frames read from the source since its start, and is updated on
+    /// each read and reset.
+    fn write<S: MixSample>(
+        &mut self,
+        source: &mut (dyn SoundSource + Send),
+        out: &mut [S],
+        looping: bool,
+        region: Option<(u64, u64)>,
+        position: &mut u64,
+        mut on_loop: impl FnMut(),
+    ) -> usize {
+        let channels = source.channels() as usize;
+        let ahead = self.frames * channels;
+        let target = out.len() + ahead;
+        // Only allocates if the buffer is larger than any previous one.
+        if self.buffer.len() < target {
+            self.buffer.resize(target, 0.0);
+        }
+        if self.head.len() < ahead {
+            self.head.resize(ahead, 0.0);
+        }
+
+        while self.len < target {
+            let mut end = target;
+            if let Some((_, region_end)) = region {
+                let frames = region_end.saturating_sub(*position) as usize;
+                end = end.min(self.len.saturating_add(frames.saturating_mul(channels)));
+            }
+            let requested = end - self.len;
+            let read = source.write_samples_f32(&mut self.buffer[self.len..end]);
+            self.len += read;
+            *position = position.saturating_add((read / channels) as u64);
+            if self.len == target || !looping {
+                break;
+            }
+
+            let mut fade = self.len.min(ahead) / channels;
+            let start = match region {
+                Some((start, end)) => {
+                    source.seek(start);
+                    fade = fade.min((end - start) as usize);
+                    start
+                }
+                None => {
+                    source.reset();
+                    0
+                }
+            };
+            let head = &mut self.head[..fade * channels];
+            let head_len = source.write_samples_f32(head);
+            *position = start + (head_len / channels) as u64;
+            if requested > 0 && read == 0 && head_len == 0 {
+                // the source is empty
+                break;
+            }
+            on_loop();
+
+            let tail = &mut self.buffer[self.len - fade * channels..self.len];
+            let frames = tail
+                .chunks_mut(channels)
+                .zip(head[..head_len].chunks(channels));
+            for (i, (tail, head)) in frames.enumerate() {
+                let t = (i + 1) as f32 / (fade + 1) as f32;
+                for (a, b) in tail.iter_mut().zip(head) {
+                    *a = *a * (1.0 - t) + *b * t;
+                }
+            }
+        }
+
+        let len = out.len().min(self.len);
+        for (o, x) in out.iter_mut().zip(&self.buffer[..len]) {
+            *o = S::from_f32(*x);
+        }
+        self.buffer.copy_within(len..self.len, 0);
+        self.len -= len;
+        len
+    }
+}
+
+/// A rule that lowers the volume of a group while another group is playing.
+struct Ducking<G> {
+    trigger: G,
+    target: G,
+    amount: f32,
+    attack: Duration,
+    release: Duration,
+    /// How much the target is ducked in the start of the current buffer, from 0.0 to 1.0.
+    start: f32,
+    /// How much the target is ducked in the end of the current buffer, from 0.0 to 1.0.
+    end: f32,
+}
+
+/// A sample type that the [`Mixer`] can mix.
+trait MixSample: Copy + Default + 'static {
+    /// Return the scratch buffer of the mixer for this sample type.
+    fn scratch<G: Eq + Hash + Send + 'static>(mixer: &mut Mixer<G>) -> &mut Vec<Self>;
+    /// Return the buffer of the given bus for this sample type.
+    fn bus(bus: &mut GroupBus) -> &mut Vec<Self>;
+    /// Process the first `len` samples of the buffer of the bus with its effect.
+    fn apply_effect(bus: &mut GroupBus, len: usize, channels: u16, sample_rate: u32);
+    /// Write the samples of `source` to `buffer`.
+    fn write(source: &mut (dyn SoundSource + Send), buffer: &mut [Self]) -> usize;
+    /// Convert a sample in the range `-1.0..=1.0` to this type.
+    fn from_f32(x: f32) -> Self;
+    /// Convert this sample to the range `-1.0..=1.0`.
+    fn to_f32(self) -> f32;
+    /// Multiply this sample by `gain`.
+    fn scale(self, gain: f32) -> Self;
+    /// Return true if this sample is at or past the maximum value of the output.
+    fn is_clipped(self) -> bool;
+    /// Add each sample of `src` to `dst`.
+    fn mix(dst: &mut [Self], src: &[Self]);
+    /// Add each sample of `src`, multiplied by `volume`, to `dst`.
+    fn mix_volume(dst: &mut [Self], src: &[Self], volume: f32);
+    /// Add each sample of `src` to `dst`, multiplied by a volume that starts at `start` and is
+    /// incremented by `step` on each frame.
+    fn mix_ramp(dst: &mut [Self], src: &[Self], channels: usize, start: f32, step: f32);
+}
+impl MixSample for i16 {
+    fn scratch<G: Eq + Hash + Send + 'static>(mixer: &mut Mixer<G>) -> &mut Vec<Self> {
+        &mut mixer.buffer
+    }
+    fn bus(bus: &mut GroupBus) -> &mut Vec<Self> {
+        &mut bus.buffer
+    }
+    fn apply_effect(bus: &mut GroupBus, len: usize, channels: u16, sample_rate: u32) {
+        let samples = &mut bus.buffer[..len];
+        let converted = &mut bus.buffer_f32;
+        converted.clear();
+        converted.extend(samples.iter().map(cpal::Sample::to_f32));
+        bus.effect.process(converted, channels, sample_rate);
+        for (x, y) in samples.iter_mut().zip(converted.iter()) {
+            *x = cpal::Sample::from(y);
+        }
+    }
+    fn write(source: &mut (dyn SoundSource + Send), buffer: &mut [Self]) -> usize {
+        source.write_samples(buffer)
+    }
+    fn from_f32(x: f32) -> Self {
+        cpal::Sample::from(&x)
+    }
+    fn to_f32(self) -> f32 {
+        cpal::Sample::to_f32(&self)
+    }
+    fn scale(self, gain: f32) -> Self {
+        (self as f32 * gain) as i16
+    }
+    fn is_clipped(self) -> bool {
+        self == i16::MAX || self == i16::MIN
+    }
+    fn mix(dst: &mut [Self], src: &[Self]) {
+        simd::mix(dst, src)
+    }
+    fn mix_volume(dst: &mut [Self], src: &[Self], volume: f32) {
+        simd::mix_volume(dst, src, volume)
+    }
+    fn mix_ramp(dst: &mut [Self], src: &[Self], channels: usize, start: f32, step: f32) {
+        let frames = dst.chunks_mut(channels).zip(src.chunks(channels));
+        for (i, (d, s)) in frames.enumerate() {
+            let volume = start + step * i as f32;
+            for (d, s) in d.iter_mut().zip(s) {
+                *d = d.saturating_add((*s as f32 * volume) as i16);
+            }
+        }
+    }
+}
+impl MixSample for f32 {
+    fn scratch<G: Eq + Hash + Send + 'static>(mixer: &mut Mixer<G>) -> &mut Vec<Self> {
+        &mut mixer.buffer_f32
+    }
+    fn bus(bus: &mut GroupBus) -> &mut Vec<Self> {
+        &mut bus.buffer_f32
+    }
+    fn apply_effect(bus: &mut GroupBus, len: usize, channels: u16, sample_rate: u32) {
+        let samples = &mut bus.buffer_f32[..len];
+        bus.effect.process(samples, channels, sample_rate);
+    }
+    fn write(source: &mut (dyn SoundSource + Send), buffer: &mut [Self]) -> usize {
+        source.write_samples_f32(buffer)
+    }
+    fn from_f32(x: f32) -> Self {
+        x
+    }
+    fn to_f32(self) -> f32 {
+        self
+    }
+    fn scale(self, gain: f32) -> Self {
+        self * gain
+    }
+    fn is_clipped(self) -> bool {
+        self.abs() > 1.0
+    }
+    fn mix(dst: &mut [Self], src: &[Self]) {
+        for (d, s) in dst.iter_mut().zip(src) {
+            *d += *s;
+        }
+    }
+    fn mix_volume(dst: &mut [Self], src: &[Self], volume: f32) {
+        for (d, s) in dst.iter_mut().zip(src) {
+            *d += *s * volume;
+        }
+    }
+    fn mix_ramp(dst: &mut [Self], src: &[Self], channels: usize, start: f32, step: f32) {
+        let frames = dst.chunks_mut(channels).zip(src.chunks(channels));
+        for (i, (d, s)) in frames.enumerate() {
+            let volume = start + step * i as f32;
+            for (d, s) in d.iter_mut().zip(s) {
+                *d += *s * volume;
+            }
+        }
+    }
+}
+
+impl<G: Eq + Hash + Send + 'static> SoundSource for Mixer<G> {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate.0
+    }
+
+    fn reset(&mut self) {}
+
+    /// Mix the sounds to `buffer`.
+    ///
+    /// If the length of `buffer` is not a multiple of the number of channels, the trailing partial
+    /// frame is filled with silence, and the sources only receive whole frames.
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let len = self.render(buffer);
+        self.call_end_callbacks();
+        len
+    }
+
+    /// Mix the sounds as `f32`, without clipping, so floating point sources don't lose precision.
+    ///
+    /// A trailing partial frame is handled like in [`write_samples`](Self::write_samples).
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        let len = self.render_f32(buffer);
+        self.call_end_callbacks();
+        len
+    }
+}
+
+impl<G: Eq + Hash + Send + 'static> Mixer<G> {
+    /// Like [`write_samples`](SoundSource::write_samples), but without calling the end callbacks.
+    fn render(&mut self, buffer: &mut [i16]) -> usize {
+        let len = buffer.len();
+        let (buffer, rest) = split_frames(buffer, self.channels);
+        rest.fill(0);
+        self.process_commands();
+        self.mix(buffer);
+        self.apply_fade_in(buffer);
+        self.detect_clipping(buffer);
+        self.measure_levels(buffer);
+        self.send_output(buffer);
+        self.publish();
+        len
+    }
+
+    /// Like [`render`](Self::render), but for
+    /// [`write_samples_f32`](SoundSource::write_samples_f32).
+    fn render_f32(&mut self, buffer: &mut [f32]) -> usize {
+        let len = buffer.len();
+        let (buffer, rest) = split_frames(buffer, self.channels);
+        rest.fill(0.0);
+        self.process_commands();
+        self.mix(buffer);
+        self.apply_fade_in(buffer);
+        self.detect_clipping(buffer);
+        self.measure_levels(buffer);
+        if self.has_output_receiver() {
+            let mut tapped = std::mem::take(&mut self.buffer);
+            if tapped.len() < buffer.len() {
+                tapped.resize(buffer.len(), 0);
+            }
+            for (t, b) in tapped.iter_mut().zip(buffer.iter()) {
+                *t = cpal::Sample::to_i16(b);
+            }
+            self.send_output(&tapped[..buffer.len()]);
+            self.buffer = tapped;
+        }
+        self.publish();
+        len
+    }
+
+    /// Call the end callbacks of the sounds that ended in the last buffer.
+    fn call_end_callbacks(&mut self) {
+        let mut ended = std::mem::take(&mut self.ended);
+        for callback in ended.drain(..) {
+            (callback.lock().unwrap())();
+            self.dispose(Garbage::EndCallback(callback));
+        }
+        self.ended = ended;
+    }
+}
+
+/// Split `buffer` in its whole frames, and the samples of a trailing partial frame.
+fn split_frames<S>(buffer: &mut [S], channels: u16) -> (&mut [S], &mut [S]) {
+    let len = buffer.len() / channels as usize * channels as usize;
+    buffer.split_at_mut(len)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{atomic::Ordering, Arc},
+        time::Duration,
+    };
+
+    use crate::SoundSource;
+
+    use super::{Mixer, MixerHandle, VoiceStealing, QUEUE_CAPACITY};
+
+    struct DebugSource {
+        i: usize,
+        v: i16,
+        len: usize,
+    }
+    impl DebugSource {
+        fn new(v: i16, len: usize) -> Self {
+            Self { i: 0, v, len }
+        }
+    }
+    impl SoundSource for DebugSource {
+        fn channels(&self) -> u16 {
+            1
+        }
+
+        fn sample_rate(&self) -> u32 {
+            1
+        }
+
+        fn reset(&mut self) {
+            self.i = 0;
+        }
+
+        fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+            for (i, o) in buffer.iter_mut().enumerate() {
+                *o = self.v;
+                self.i += 1;
+                if self.i > self.len {
+                    return i;
+                }
+            }
+            buffer.len()
+        }
+    }
+
+    #[test]
+    fn start_stopped() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+
+        // create new sound
+        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 5)));
+
+        // sound start in stopped state
+        let mut buffer = [0; 10];
+        assert_eq!(mixer.write_samples(&mut buffer), 10);
+        assert_eq!(buffer, [0; 10]);
+
+        // play the sound
+        mixer.play(id);
+        assert_eq!(mixer.write_samples(&mut buffer), 10);
+        assert_eq!(buffer, [2, 2, 2, 2, 2, 0, 0, 0, 0, 0]);
+
+        // mark to remove is true, sound was removed
+        mixer.reset(id);
+        mixer.play(id);
+        assert_eq!(mixer.write_samples(&mut buffer), 10);
+        assert_eq!(buffer, [0; 10]);
+
+        // create new sound
+        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 5)));
+
+        mixer.stop(id);
+    }
+
+    #[test]
+    fn write_samples_overwrites() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let id = mixer.add_sound((), Box::new(DebugSource::new(4, 3)));
+        mixer.play(id);
+
+        let mut buffer = [1; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [4, 4, 4, 0]);
+    }
+
+    #[test]
+    fn mark_to_remove_true() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+
+        // create new sound
+        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 5)));
+
+        // stop the sound will remove the sound if marked to remove is true
+        mixer.stop(id);
+
+        // mark to remove is true, sound was removed
+        mixer.reset(id);
+        mixer.play(id);
+        let mut buffer = [0; 10];
+        assert_eq!(mixer.write_samples(&mut buffer), 10);
+        assert_eq!(buffer, [0; 10]);
+    }
+
+    #[test]
+    fn mark_to_remove_false() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+
+        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 5)));
+        mixer.mark_to_remove(id, false);
+
+        // stop the sound will not remove the sound if marked to remove is false
+        mixer.stop(id);
+
+        // sound start in stopped state
+        let mut buffer = [0; 10];
+        assert_eq!(mixer.write_samples(&mut buffer), 10);
+        assert_eq!(buffer, [0; 10]);
+
+        // play the sound
+        mixer.play(id);
+        assert_eq!(mixer.playing_count(), 1);
+        assert_eq!(mixer.write_samples(&mut buffer), 10);
+        assert_eq!(buffer, [2, 2, 2, 2, 2, 0, 0, 0, 0, 0]);
+
+        // mark to remove is false
+        mixer.play(id);
+        buffer = [0; 10];
+        assert_eq!(mixer.playing_count(), 1);
+        assert_eq!(mixer.write_samples(&mut buffer), 10);
+        assert_eq!(buffer, [2, 2, 2, 2, 2, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn stop_removed_while_all_playing() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+
+        let a = mixer.add_sound((), Box::new(DebugSource::new(2, 5)));
+        let b = mixer.add_sound((), Box::new(DebugSource::new(3, 5)));
+        let c = mixer.add_sound((), Box::new(DebugSource::new(4, 5)));
+        mixer.play(a);
+        mixer.play(b);
+        mixer.play(c);
+
+        // the stopped sounds are the last playing one, and the last one in the mixer
+        mixer.stop(a);
+        assert_eq!(mixer.sound_count(), 2);
+        assert_eq!(mixer.playing_count(), 2);
+        mixer.stop(b);
+        mixer.stop(c);
+        assert_eq!(mixer.sound_count(), 0);
+        assert_eq!(mixer.playing_count(), 0);
+
+        let mut buffer = [0; 4];
+        assert_eq!(mixer.write_samples(&mut buffer), 4);
+        assert_eq!(buffer, [0; 4]);
+    }
+
+    #[test]
+    fn volume() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+
+        let id0 = mixer.add_sound((), Box::new(DebugSource::new(10, 2)));
+        let id1 = mixer.add_sound((), Box::new(DebugSource::new(10, 4)));
+        let id2 = mixer.add_sound((), Box::new(DebugSource::new(10, 6)));
+
+        assert_eq!(mixer.volume(id0), Some(1.0));
+
+        mixer.set_volume(id0, 0.2);
+        mixer.set_volume(id1, 0.4);
+        mixer.set_volume(id2, 0.8);
+
+        assert_eq!(mixer.volume(id0), Some(0.2));
+        assert_eq!(mixer.volume(id2), Some(0.8));
+
+        mixer.play(id0);
+        mixer.play(id1);
+        mixer.play(id2);
+
+        let mut buffer = [0; 10];
+        assert_eq!(mixer.write_samples(&mut buffer), 10);
+        assert_eq!(buffer, [14, 14, 12, 12, 8, 8, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn group_volume() {
+        #[derive(Eq, Hash, PartialEq)]
+        enum Group {
+            A,
+            B,
+        }
+
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+
+        let a0 = mixer.add_sound(Group::A, Box::new(DebugSource::new(10, 2)));
+        let a1 = mixer.add_sound(Group::A, Box::new(DebugSource::new(10, 4)));
+        let a2 = mixer.add_sound(Group::A, Box::new(DebugSource::new(10, 6)));
+
+        let b0 = mixer.add_sound(Group::B, Box::new(DebugSource::new(10, 8)));
+        let b1 = mixer.add_sound(Group::B, Box::new(DebugSource::new(10, 10)));
+        let b2 = mixer.add_sound(Group::B, Box::new(DebugSource::new(10, 12)));
+
+        mixer.set_volume(a0, 0.2);
+        mixer.set_volume(a1, 0.4);
+        mixer.set_volume(a2, 0.8);
+
+        mixer.set_volume(b0, 0.2);
+        mixer.set_volume(b1, 0.4);
+        mixer.set_volume(b2, 0.8);
+
+        assert_eq!(mixer.group_volume(&Group::A), 1.0);
+
+        mixer.set_group_volume(Group::A, 2.0);
+        mixer.set_group_volume(Group::B, 4.0);
+
+        assert_eq!(mixer.group_volume(&Group::A), 2.0);
+        assert_eq!(mixer.group_volume(&Group::B), 4.0);
+
+        assert_eq!(mixer.sound_count(), 6);
+        assert_eq!(mixer.playing_count(), 0);
+
+        mixer.play(a0);
+        mixer.play(a1);
+        mixer.play(a2);
+
+        mixer.play(b0);
+        mixer.play(b1);
+        mixer.play(b2);
+
+        assert_eq!(mixer.sound_count(), 6);
+        assert_eq!(mixer.playing_count(), 6);
+
+        let mut buffer = [0; 10];
+        assert_eq!(mixer.write_samples(&mut buffer), 10);
+        // 28 + 56
+        assert_eq!(buffer, [84, 84, 80, 80, 72, 72, 56, 56, 48, 48]);
+        buffer = [0; 10];
+        assert_eq!(mixer.write_samples(&mut buffer), 10);
+        // 28 + 56
+        assert_eq!(buffer, [32, 32, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert_eq!(mixer.sound_count(), 0);
+        assert_eq!(mixer.playing_count(), 0);
+    }
+
+    #[test]
+    fn master_volume() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        assert_eq!(mixer.master_volume(), 1.0);
+
+        let a = mixer.add_sound((), Box::new(DebugSource::new(10, 4)));
+        let b = mixer.add_sound((), Box::new(DebugSource::new(20, 2)));
+        mixer.set_volume(b, 0.5);
+        mixer.set_group_volume((), 2.0);
+        mixer.set_master_volume(0.5);
+        assert_eq!(mixer.master_volume(), 0.5);
+
+        mixer.play(a);
+        mixer.play(b);
+
+        let mut buffer = [0; 6];
+        assert_eq!(mixer.write_samples(&mut buffer), 6);
+        // (10 * 2 + 20 * 0.5 * 2) * 0.5
+        assert_eq!(buffer, [20, 20, 10, 10, 0, 0]);
+
+        mixer.set_master_volume(f32::NAN);
+        assert_eq!(mixer.master_volume(), 0.0);
+    }
+
+    #[test]
+    fn ducking() {
+        #[derive(Eq, Hash, PartialEq)]
+        enum Group {
+            A,
+            B,
+        }
+
+        let mut mixer = Mixer::new(1, crate::SampleRate(4));
+        let a = mixer.add_sound(Group::A, Box::new(DebugSource::new(0, 4)));
+        let b = mixer.add_sound(Group::B, Box::new(DebugSource::new(100, 100)));
+        mixer.set_ducking(
+            Group::A,
+            Group::B,
+            0.5,
+            Duration::from_secs(1),
+            Duration::ZERO,
+        );
+
+        mixer.play(b);
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [100; 4]);
+
+        // the volume of B is lowered during the attack
+        mixer.play(a);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [100, 87, 75, 62]);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [50; 4]);
+
+        // and raised back in the next buffer after A stops
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [50, 62, 75, 87]);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [100; 4]);
+
+        mixer.play(a);
+        mixer.remove_ducking(&Group::A, &Group::B);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [100; 4]);
+    }
+
+    #[test]
+    fn automate_volume() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(4));
+        let id = mixer.add_sound((), Box::new(DebugSource::new(100, 100)));
+        mixer.play(id);
+
+        mixer.automate_volume(id, vec![(Duration::from_secs(1), 0.0)]);
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [100, 75, 50, 25]);
+        assert_eq!(mixer.volume(id), Some(0.0));
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0; 4]);
+
+        // points in the middle of the buffer
+        mixer.automate_volume(
+            id,
+            vec![
+                (Duration::from_millis(1500), 0.0),
+                (Duration::from_millis(500), 0.5),
+                (Duration::from_secs(1), 0.0),
+            ],
+        );
+        let mut buffer = [0; 8];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0, 25, 50, 25, 0, 0, 0, 0]);
+
+        // set_volume cancels the automation
+        mixer.automate_volume(id, vec![(Duration::from_secs(1), 1.0)]);
+        mixer.set_volume(id, 0.5);
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [50; 4]);
+    }
+
+    #[test]
+    fn fades() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(4));
+        let id = mixer.add_sound((), Box::new(DebugSource::new(100, 100)));
+        mixer.add_handle(id);
+        mixer.set_volume(id, 0.5);
+
+        // the fade only advances while playing
+        mixer.fade_in(id, Duration::from_secs(1));
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        mixer.play(id);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0, 12, 25, 37]);
+        assert_eq!(mixer.volume(id), Some(0.5));
+
+        mixer.fade_out(id, Duration::from_millis(500));
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [50, 25, 0, 0]);
+        assert_eq!(mixer.volume(id), Some(0.0));
+
+        // the sound is stopped, and plays again from its start with the previous volume
+        mixer.set_volume(id, 1.0);
+        mixer.fade_out_and_stop(id, Duration::from_millis(500));
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [100, 50, 0, 0]);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0; 4]);
+        assert_eq!(mixer.volume(id), Some(1.0));
+        mixer.play(id);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [100; 4]);
+    }
+
+    #[test]
+    fn invalid_volume() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let id = mixer.add_sound((), Box::new(DebugSource::new(10000, 100)));
+        mixer.play(id);
+
+        mixer.set_volume(id, f32::NAN);
+        assert_eq!(mixer.volume(id), Some(0.0));
+        mixer.set_volume(id, -1.0);
+        assert_eq!(mixer.volume(id), Some(0.0));
+        mixer.set_volume(id, 1e30);
+        assert_eq!(mixer.volume(id), Some(super::MAX_VOLUME));
+
+        mixer.set_group_volume((), f32::NAN);
+        assert_eq!(mixer.group_volume(&()), 0.0);
+        mixer.set_group_volume((), f32::INFINITY);
+        assert_eq!(mixer.group_volume(&()), super::MAX_VOLUME);
+
+        // the output saturates instead of overflowing
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [i16::MAX; 4]);
+    }
+
+    #[test]
+    fn loop_crossfade() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let samples = vec![10, 20, 30, 40, 50, 60, 70, 80];
+        let id = mixer.add_sound((), Box::new(crate::SliceSource::new(samples, 1, 1)));
+        mixer.set_loop(id, true);
+        mixer.set_loop_crossfade(id, 2);
+        mixer.play(id);
+
+        // the last 2 frames are crossfaded with the first 2 frames
+        let mut buffer = [0; 14];
+        mixer.write_samples(&mut buffer);
+        let expected = [10, 20, 30, 40, 50, 60, 50, 40, 30, 40, 50, 60, 50, 40];
+        for (a, b) in buffer.iter().zip(&expected) {
+            assert!((a - b).abs() <= 1, "{:?} != {:?}", buffer, expected);
+        }
+
+        // without a crossfade, the sound loops normally
+        mixer.set_loop_crossfade(id, 0);
+        mixer.reset(id);
+        let mut buffer = [0; 10];
+        mixer.write_samples(&mut buffer);
+        let expected = [10, 20, 30, 40, 50, 60, 70, 80, 10, 20];
+        for (a, b) in buffer.iter().zip(&expected) {
+            assert!((a - b).abs() <= 1, "{:?} != {:?}", buffer, expected);
+        }
+    }
+
+    #[test]
+    fn on_loop() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        for crossfade in [0, 2] {
+            let mut mixer = Mixer::new(1, crate::SampleRate(1));
+            let samples = vec![10, 20, 30, 40, 50, 60, 70, 80];
+            let id = mixer.add_sound((), Box::new(crate::SliceSource::new(samples, 1, 1)));
+            let loops = Arc::new(AtomicUsize::new(0));
+            let counter = loops.clone();
+            mixer.set_on_loop(id, move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+            mixer.set_loop(id, true);
+            mixer.set_loop_crossfade(id, crossfade);
+            mixer.play(id);
+
+            let mut buffer = [0; 10];
+            mixer.write_samples(&mut buffer);
+            assert_eq!(loops.load(Ordering::Relaxed), 1);
+
+            mixer.set_loop(id, false);
+            mixer.write_samples(&mut buffer);
+            assert_eq!(loops.load(Ordering::Relaxed), 1);
+        }
+    }
+
+    #[test]
+    fn mute_and_solo() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let a = mixer.add_sound((), Box::new(DebugSource::new(1, 8)));
+        let b = mixer.add_sound((), Box::new(DebugSource::new(10, 8)));
+        let c = mixer.add_sound((), Box::new(DebugSource::new(100, 8)));
+        for id in [a, b, c] {
+            mixer.play(id);
+        }
+
+        mixer.set_solo(b, true);
+        assert_eq!(mixer.is_solo(b), Some(true));
+        let mut buffer = [0; 2];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [10, 10]);
+        // the silenced sounds keep playing
+        for id in [a, b, c] {
+            assert_eq!(mixer.playback_position(id), Some(Duration::from_secs(2)));
+        }
+
+        mixer.set_mute(b, true);
+        assert_eq!(mixer.is_muted(b), Some(true));
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0, 0]);
+
+        mixer.set_solo(b, false);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [101, 101]);
+
+        mixer.set_mute(b, false);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [111, 111]);
+    }
+
+    #[test]
+    fn loop_region() {
+        for crossfade in [false, true] {
+            let mut mixer = Mixer::new(1, crate::SampleRate(1));
+            let samples = vec![1, 2, 3, 4, 5, 6, 7, 8];
+            let id = mixer.add_sound((), Box::new(crate::SliceSource::new(samples, 1, 1)));
+            if crossfade {
+                // use the crossfade path, without fading
+                mixer.set_loop_crossfade(id, 1);
+                mixer.set_loop_crossfade(id, 0);
+            }
+            let region = |a, b| (Duration::from_secs(a), Duration::from_secs(b));
+            let (start, end) = region(3, 2);
+            assert!(mixer.set_loop_region(id, start, end).is_err());
+            let (start, end) = region(2, 5);
+            mixer.set_loop_region(id, start, end).unwrap();
+            mixer.set_loop(id, true);
+            mixer.play(id);
+
+            let mut buffer = [0; 6];
+            mixer.write_samples(&mut buffer);
+            assert_eq!(buffer, [1, 2, 3, 4, 5, 3]);
+            mixer.write_samples(&mut buffer);
+            assert_eq!(buffer, [4, 5, 3, 4, 5, 3]);
+
+            // past the end of the region, so it seeks back to its start
+            mixer.seek(id, Duration::from_secs(6));
+            mixer.write_samples(&mut buffer);
+            assert_eq!(buffer, [3, 4, 5, 3, 4, 5]);
+
+            // without looping, the sound continues past the region until the end of the source
+            mixer.set_loop(id, false);
+            mixer.write_samples(&mut buffer);
+            assert_eq!(buffer, [6, 7, 8, 0, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn loop_region_max() {
+        let mut mixer = Mixer::new(2, crate::SampleRate(10));
+        let samples: Vec<i16> = (1..=8).collect();
+        let source = crate::SliceSource::new(samples, 2, 10);
+        let id = mixer.add_sound((), Box::new(source));
+        mixer.set_loop(id, true);
+        mixer.play(id);
+        mixer
+            .set_loop_region(id, Duration::from_millis(200), Duration::MAX)
+            .unwrap();
+        mixer.seek(id, Duration::MAX);
+
+        // the end of the region and the position are rescaled without overflowing
+        mixer.set_config(2, crate::SampleRate(20));
+        let mut buffer = [0; 8];
+        mixer.write_samples(&mut buffer);
+        mixer.set_config(2, crate::SampleRate(10));
+        mixer.write_samples(&mut buffer);
+        assert_eq!(mixer.playing_count(), 1);
+    }
+
+    #[test]
+    fn levels() {
+        let close = |a: Option<Vec<f32>>, b: &[f32]| {
+            let a = a.unwrap();
+            assert!(a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3));
+        };
+
+        let mut mixer = Mixer::new(2, crate::SampleRate(1));
+        assert!(mixer.peak_level().is_empty());
+
+        let samples = vec![16384, 0, -16384, 0, 16384, 8192, -16384, 8192];
+        let a = mixer.add_sound((), Box::new(crate::SliceSource::new(samples, 2, 1)));
+        let b = mixer.add_sound((), Box::new(crate::Constant::new(8192, 2, 1)));
+        mixer.set_volume(b, 0.5);
+        mixer.play(a);
+
+        let mut buffer = [0; 8];
+        mixer.write_samples(&mut buffer);
+        close(Some(mixer.peak_level()), &[0.5, 0.25]);
+        close(Some(mixer.rms_level()), &[0.5, 0.25 / 2f32.sqrt()]);
+        close(mixer.sound_peak_level(a), &[0.5, 0.25]);
+        // not playing
+        close(mixer.sound_peak_level(b), &[0.0, 0.0]);
+
+        mixer.play(b);
+        mixer.write_samples_f32(&mut [0.0; 8]);
+        close(Some(mixer.peak_level()), &[0.125, 0.125]);
+        close(mixer.sound_rms_level(b), &[0.125, 0.125]);
+        assert_eq!(mixer.sound_peak_level(a), None);
+    }
+
+    #[test]
+    fn on_end() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let id = mixer.add_sound((), Box::new(DebugSource::new(10, 4)));
+        mixer.mark_to_remove(id, false);
+        let ends = Arc::new(AtomicUsize::new(0));
+        let counter = ends.clone();
+        mixer.set_on_end(id, move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        mixer.play(id);
+
+        let mut buffer = [0; 3];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(ends.load(Ordering::Relaxed), 0);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(ends.load(Ordering::Relaxed), 1);
+        // the sound already ended, so it is not called again
+        mixer.write_samples(&mut buffer);
+        assert_eq!(ends.load(Ordering::Relaxed), 1);
+
+        // but it is called on each end
+        mixer.play(id);
+        mixer.write_samples(&mut [0; 6]);
+        assert_eq!(ends.load(Ordering::Relaxed), 2);
+
+        // a stopped sound didn't reach its end
+        mixer.play(id);
+        mixer.write_samples(&mut buffer);
+        mixer.stop(id);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(ends.load(Ordering::Relaxed), 2);
+
+        // a new callback replaces the previous one
+        mixer.set_on_end(id, {
+            let counter = ends.clone();
+            move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        mixer.play(id);
+        mixer.write_samples(&mut [0; 6]);
+        assert_eq!(ends.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn invalid_source() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let source = crate::Constant::new(0, 0, 1);
+        assert!(mixer.try_add_sound((), Box::new(source)).is_err());
+
+        let id = mixer.add_sound((), Box::new(DebugSource::new(3, 10)));
+        mixer.replace_source(id, Box::new(crate::Silence::new(1, 0)));
+        mixer.play(id);
+        let mut buffer = [0; 2];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [3, 3]);
+    }
+
+    #[test]
+    fn seek() {
+        let samples: Vec<i16> = (0..10).collect();
+        let mut mixer = Mixer::new(1, crate::SampleRate(10));
+        let id = mixer.add_sound((), Box::new(crate::SliceSource::new(samples, 1, 10)));
+        mixer.set_loop(id, true);
+
+        // a paused sound stays paused
+        mixer.seek(id, Duration::from_millis(500));
+        let mut buffer = [0; 3];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0, 0, 0]);
+
+        mixer.play(id);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [5, 6, 7]);
+
+        // seeking past the end loops
+        mixer.seek(id, Duration::from_secs(2));
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0, 1, 2]);
+
+        // or ends the sound
+        mixer.set_loop(id, false);
+        mixer.seek(id, Duration::from_secs(2));
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0, 0, 0]);
+    }
+
+    #[test]
+    fn seek_max() {
+        let samples: Vec<i16> = (0..20).collect();
+        let mut mixer = Mixer::new(2, crate::SampleRate(10));
+        let slice = crate::SliceSource::new(samples.clone(), 2, 5);
+        let sources: Vec<Box<dyn SoundSource + Send>> = vec![
+            // the default seek, that skips frames
+            Box::new(crate::Reverse::new(crate::SliceSource::new(samples, 2, 10))),
+            Box::new(crate::converter::SampleRateConverter::new(slice, 10)),
+            Box::new(crate::Constant::new(1, 2, 10)),
+            Box::new(crate::SineWave::new(10, 1.0)),
+        ];
+        let ids: Vec<_> = sources
+            .into_iter()
+            .map(|source| mixer.add_sound((), source))
+            .collect();
+        for &id in &ids {
+            mixer.play(id);
+            mixer.seek(id, Duration::MAX);
+        }
+
+        // the finite sources end, and the endless ones keep playing
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(mixer.playing_count(), 2);
+        let end = crate::SampleRate(10).duration_of(u64::MAX);
+        assert_eq!(mixer.playback_position(ids[2]), Some(end));
+    }
+
+    #[test]
+    fn playback_position() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(10));
+        let source = crate::SliceSource::new(vec![1; 20], 1, 5);
+        let id = mixer.add_sound((), mixer.convert_source(Box::new(source)));
+        mixer.set_loop(id, true);
+        mixer.play(id);
+        assert_eq!(mixer.playback_position(id), Some(Duration::ZERO));
+
+        // the source is resampled, but the position is in real time
+        let mut buffer = [0; 15];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(
+            mixer.playback_position(id),
+            Some(Duration::from_millis(1500))
+        );
+
+        // after a loop, the position restarts
+        let mut buffer = [0; 30];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(
+            mixer.playback_position(id),
+            Some(Duration::from_millis(500))
+        );
+
+        mixer.seek(id, Duration::from_secs(1));
+        assert_eq!(mixer.playback_position(id), Some(Duration::from_secs(1)));
+
+        // the samples read ahead for the crossfade are not counted
+        mixer.set_loop_crossfade(id, 5);
+        mixer.write_samples(&mut buffer[..10]);
+        assert_eq!(mixer.playback_position(id), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn default_seek() {
+        // DebugSource uses the default implementation, which resets and skips
+        let mut source = DebugSource::new(3, 10);
+        source.seek(4);
+        assert_eq!(source.write_samples(&mut [0; 10]), 6);
+    }
+
+    #[test]
+    fn partial_frame() {
+        let mut mixer = Mixer::new(2, crate::SampleRate(1));
+        let id = mixer.add_sound((), Box::new(DebugSource::new(3, 10)));
+        mixer.play(id);
+        let mut buffer = [1; 5];
+        assert_eq!(mixer.write_samples(&mut buffer), 5);
+        assert_eq!(buffer, [3, 3, 3, 3, 0]);
+
+        let mut buffer = [1.0; 3];
+        assert_eq!(mixer.write_samples_f32(&mut buffer), 3);
+        assert_eq!(buffer[2], 0.0);
+    }
+
+    #[test]
+    fn loop_zero_length() {
+        // a looping sound that outputs nothing must not hang the mixer
+        for crossfade in [0, 2] {
+            let mut mixer = Mixer::new(1, crate::SampleRate(1));
+            let id = mixer.add_sound((), Box::new(DebugSource::new(3, 0)));
+            mixer.set_loop(id, true);
+            mixer.set_loop_crossfade(id, crossfade);
+            mixer.play(id);
+
+            let mut buffer = [1; 4];
+            mixer.write_samples(&mut buffer);
+            assert_eq!(buffer, [0; 4]);
+            // instead, it ends
+            assert_eq!(mixer.playing_count(), 0);
+            assert!(!mixer.contains(id));
+        }
+
+        // a source shorter than the buffer loops many times in a single call
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let id = mixer.add_sound((), Box::new(DebugSource::new(3, 1)));
+        mixer.set_loop(id, true);
+        mixer.play(id);
+        let mut buffer = [0; 64];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [3; 64]);
+    }
+
+    #[test]
+    fn set_config_fade_in() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1000));
+        let id = mixer.add_sound((), Box::new(crate::Constant::new(1000, 1, 1000)));
+        mixer.play(id);
+
+        // the output fades in during 10 ms after the config changes
+        mixer.set_config(2, crate::SampleRate(1000));
+        let mut buffer = [0; 24];
+        mixer.write_samples(&mut buffer);
+        let expected = (0..12).flat_map(|x| [x.min(10) * 100; 2]);
+        for (a, b) in buffer.iter().zip(expected) {
+            assert!((a - b).abs() <= 1, "{:?}", buffer);
+        }
+
+        // also when a new stream starts with the same config
+        mixer.set_config(2, crate::SampleRate(1000));
+        mixer.write_samples(&mut buffer);
+        let expected = (0..12).flat_map(|x| [x.min(10) * 100; 2]);
+        for (a, b) in buffer.iter().zip(expected) {
+            assert!((a - b).abs() <= 1, "{:?}", buffer);
+        }
+    }
+
+    #[test]
+    fn pan() {
+        let mut mixer = Mixer::new(2, crate::SampleRate(1));
+        let id = mixer.add_sound((), Box::new(crate::Constant::new(1000, 2, 1)));
+        mixer.play(id);
+
+        let mut buffer = [0; 2];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [1000, 1000]);
+
+        mixer.set_pan(id, -1.0);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [1000, 0]);
+
+        mixer.set_pan(id, 0.5);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [541, 1000]);
+
+        mixer.set_pan_law(id, super::PanLaw::Linear);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [500, 1000]);
+
+        mixer.set_pan_law(id, super::PanLaw::Compromise);
+        mixer.set_pan(id, 2.0);
+        assert_eq!(mixer.pan(id), Some(1.0));
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0, 1000]);
+
+        mixer.set_pan(id, f32::NAN);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [1000, 1000]);
+
+        // the pan is ignored in mono
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let id = mixer.add_sound((), Box::new(crate::Constant::new(1000, 1, 1)));
+        mixer.play(id);
+        mixer.set_pan(id, 1.0);
+        let mut buffer = [0; 2];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [1000, 1000]);
+    }
+
+    #[test]
+    fn group_sounds() {
+        #[derive(Eq, Hash, PartialEq)]
+        enum Group {
             A,
             B,
+            C,
         }
 
         let mut mixer = Mixer::new(1, crate::SampleRate(1));
 
         let a0 = mixer.add_sound(Group::A, Box::new(DebugSource::new(10, 2)));
-        let a1 = mixer.add_sound(Group::A, Box::new(DebugSource::new(10, 4)));
-        let a2 = mixer.add_sound(Group::A, Box::new(DebugSource::new(10, 6)));
+        let b0 = mixer.add_sound(Group::B, Box::new(DebugSource::new(10, 4)));
+        let a1 = mixer.add_sound(Group::A, Box::new(DebugSource::new(10, 6)));
 
-        let b0 = mixer.add_sound(Group::B, Box::new(DebugSource::new(10, 8)));
-        let b1 = mixer.add_sound(Group::B, Box::new(DebugSource::new(10, 10)));
-        let b2 = mixer.add_sound(Group::B, Box::new(DebugSource::new(10, 12)));
+        mixer.play(a1);
 
-        mixer.set_volume(a0, 0.2);
-        mixer.set_volume(a1, 0.4);
-        mixer.set_volume(a2, 0.8);
+        assert_eq!(mixer.group_sound_count(&Group::A), 2);
+        assert_eq!(mixer.group_sound_count(&Group::B), 1);
+        assert_eq!(mixer.group_sound_count(&Group::C), 0);
+        assert_eq!(mixer.group_playing_count(&Group::A), 1);
+        assert_eq!(mixer.group_playing_count(&Group::B), 0);
+
+        let mut ids = mixer.sound_ids_in_group(&Group::A);
+        ids.sort_unstable();
+        assert_eq!(ids, [a0, a1]);
+        assert_eq!(mixer.sound_ids_in_group(&Group::B), [b0]);
+        assert!(mixer.sound_ids_in_group(&Group::C).is_empty());
+
+        // moving a sound applies the volume of its new group
+        mixer.set_group_volume(Group::C, 0.5);
+        mixer.set_group(a1, Group::C);
+        assert!(mixer.group(a1) == Some(&Group::C));
+        assert_eq!(mixer.group_sound_count(&Group::A), 1);
+        assert_eq!(mixer.group_playing_count(&Group::C), 1);
+        let mut buffer = [0; 2];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [5, 5]);
+    }
 
-        mixer.set_volume(b0, 0.2);
-        mixer.set_volume(b1, 0.4);
-        mixer.set_volume(b2, 0.8);
+    #[test]
+    fn max_voices() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        mixer.set_max_voices(2);
 
-        mixer.set_group_volume(Group::A, 2.0);
-        mixer.set_group_volume(Group::B, 4.0);
+        let id0 = mixer.add_sound((), Box::new(DebugSource::new(1, 20)));
+        let id1 = mixer.add_sound((), Box::new(DebugSource::new(2, 20)));
+        let id2 = mixer.add_sound((), Box::new(DebugSource::new(4, 20)));
+        mixer.mark_to_remove(id0, false);
 
-        assert_eq!(mixer.sound_count(), 6);
-        assert_eq!(mixer.playing_count(), 0);
+        mixer.play(id0);
+        mixer.play(id1);
+        assert_eq!(mixer.playing_count(), 2);
 
-        mixer.play(a0);
-        mixer.play(a1);
-        mixer.play(a2);
+        // the oldest sound is stopped
+        mixer.play(id2);
+        assert_eq!(mixer.playing_count(), 2);
+        assert_eq!(mixer.sound_count(), 3);
+
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [6; 4]);
+
+        // id1 is now the oldest, and is removed because it is marked to remove
+        mixer.play(id0);
+        assert_eq!(mixer.playing_count(), 2);
+        assert_eq!(mixer.sound_count(), 2);
+
+        buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [5; 4]);
+    }
+
+    #[test]
+    fn max_voices_refused() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        mixer.set_max_voices(0);
+
+        // a sound marked to remove is removed when it can't play
+        let id0 = mixer.add_sound((), Box::new(DebugSource::new(1, 20)));
+        mixer.play(id0);
+        assert_eq!(mixer.sound_count(), 0);
+
+        // and a sound with handles, when its last handle is dropped
+        let id1 = mixer.add_sound((), Box::new(DebugSource::new(2, 20)));
+        mixer.add_handle(id1);
+        mixer.play(id1);
+        assert_eq!(mixer.sound_count(), 1);
+        mixer.remove_handle(id1);
+        assert_eq!(mixer.sound_count(), 0);
+
+        // unless it was played later
+        let id2 = mixer.add_sound((), Box::new(DebugSource::new(4, 20)));
+        mixer.add_handle(id2);
+        mixer.play(id2);
+        mixer.set_max_voices(1);
+        mixer.play(id2);
+        mixer.remove_handle(id2);
+        assert_eq!(mixer.sound_count(), 1);
+        assert_eq!(mixer.playing_count(), 1);
+    }
+
+    #[test]
+    fn max_voices_quietest() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        mixer.set_max_voices(2);
+        mixer.set_voice_stealing(VoiceStealing::Quietest);
+
+        let id0 = mixer.add_sound((), Box::new(DebugSource::new(10, 20)));
+        let id1 = mixer.add_sound((), Box::new(DebugSource::new(10, 20)));
+        let id2 = mixer.add_sound((), Box::new(DebugSource::new(10, 20)));
+        mixer.set_volume(id0, 0.8);
+        mixer.set_volume(id1, 0.2);
+
+        mixer.play(id0);
+        mixer.play(id1);
+        mixer.play(id2);
+
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [18; 4]);
+    }
+
+    #[test]
+    fn group_max_voices() {
+        #[derive(Eq, Hash, PartialEq)]
+        enum Group {
+            A,
+            B,
+        }
+
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        mixer.set_group_max_voices(Group::A, 1);
+
+        let a0 = mixer.add_sound(Group::A, Box::new(DebugSource::new(1, 20)));
+        let a1 = mixer.add_sound(Group::A, Box::new(DebugSource::new(2, 20)));
+        let b0 = mixer.add_sound(Group::B, Box::new(DebugSource::new(4, 20)));
+        let b1 = mixer.add_sound(Group::B, Box::new(DebugSource::new(8, 20)));
 
         mixer.play(b0);
+        mixer.play(a0);
         mixer.play(b1);
-        mixer.play(b2);
+        mixer.play(a1);
+        assert_eq!(mixer.playing_count(), 3);
 
-        assert_eq!(mixer.sound_count(), 6);
-        assert_eq!(mixer.playing_count(), 6);
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [14; 4]);
+    }
 
-        let mut buffer = [0; 10];
-        assert_eq!(mixer.write_samples(&mut buffer), 10);
-        // 28 + 56
-        assert_eq!(buffer, [84, 84, 80, 80, 72, 72, 56, 56, 48, 48]);
-        buffer = [0; 10];
-        assert_eq!(mixer.write_samples(&mut buffer), 10);
-        // 28 + 56
-        assert_eq!(buffer, [32, 32, 0, 0, 0, 0, 0, 0, 0, 0]);
+    #[test]
+    fn set_config() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
 
-        assert_eq!(mixer.sound_count(), 0);
+        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 6)));
+        mixer.play(id);
+
+        let mut buffer = [0; 2];
+        assert_eq!(mixer.write_samples(&mut buffer), 2);
+        assert_eq!(buffer, [2, 2]);
+
+        // the sound continue from where it was, converted to the new config
+        mixer.set_config(2, crate::SampleRate(2));
+        assert_eq!(mixer.channels(), 2);
+        assert_eq!(mixer.sample_rate(), 2);
+
+        let mut buffer = [0; 20];
+        assert_eq!(mixer.write_samples(&mut buffer), 20);
+        assert_eq!(buffer[..16], [2; 16]);
+        assert_eq!(buffer[16..], [0; 4]);
         assert_eq!(mixer.playing_count(), 0);
     }
+
+    #[test]
+    fn write_samples_f32() {
+        struct FloatSource;
+        impl SoundSource for FloatSource {
+            fn channels(&self) -> u16 {
+                1
+            }
+            fn sample_rate(&self) -> u32 {
+                1
+            }
+            fn reset(&mut self) {}
+            fn write_samples(&mut self, _: &mut [i16]) -> usize {
+                unreachable!()
+            }
+            fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+                buffer.fill(1e-6);
+                buffer.len()
+            }
+        }
+
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+
+        // i16 sources are converted, and are not clipped when summed
+        let a = mixer.add_sound((), Box::new(DebugSource::new(i16::MAX, 5)));
+        let b = mixer.add_sound((), Box::new(DebugSource::new(i16::MAX, 3)));
+        mixer.play(a);
+        mixer.play(b);
+        let mut buffer = [0.0; 6];
+        assert_eq!(mixer.write_samples_f32(&mut buffer), 6);
+        assert_eq!(buffer, [2.0, 2.0, 2.0, 1.0, 1.0, 0.0]);
+
+        // f32 sources keep their precision
+        let c = mixer.add_sound((), Box::new(FloatSource));
+        mixer.set_volume(c, 0.5);
+        mixer.play(c);
+        assert_eq!(mixer.write_samples_f32(&mut buffer), 6);
+        assert_eq!(buffer, [0.5e-6; 6]);
+    }
+
+    #[test]
+    fn output_tap() {
+        use std::sync::{Arc, Mutex};
+
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let output = Arc::new(Mutex::new(Vec::new()));
+        mixer.set_output_tap({
+            let output = output.clone();
+            move |buffer| output.lock().unwrap().extend_from_slice(buffer)
+        });
+
+        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 3)));
+
+        let mut buffer = [0; 2];
+        mixer.write_samples(&mut buffer);
+        mixer.play(id);
+        mixer.write_samples(&mut buffer);
+        mixer.write_samples(&mut buffer);
+
+        assert_eq!(*output.lock().unwrap(), [0, 0, 2, 2, 2, 0]);
+
+        mixer.remove_output_tap();
+        mixer.write_samples(&mut buffer);
+        assert_eq!(output.lock().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn group_effect() {
+        /// Halve the samples, and add a offset to the first one.
+        struct HalfEffect;
+        impl crate::Effect for HalfEffect {
+            fn process(&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32) {
+                assert_eq!((channels, sample_rate), (1, 1));
+                for x in buffer.iter_mut() {
+                    *x *= 0.5;
+                }
+                buffer[0] += 0.5;
+            }
+        }
+
+        let mut mixer = Mixer::<u8>::new(1, crate::SampleRate(1));
+        mixer.set_group_effect(1, Box::new(HalfEffect));
+        let a = mixer.add_sound(0, Box::new(DebugSource::new(1000, 10)));
+        let b = mixer.add_sound(1, Box::new(DebugSource::new(2000, 10)));
+
+        // the effect is applied even when no sound is playing
+        let mut buffer = [0; 3];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [16383, 0, 0]);
+
+        // only the sounds of the group are processed by the effect
+        mixer.play(a);
+        mixer.play(b);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [17383 + 1000, 2000, 2000]);
+
+        mixer.remove_group_effect(&1);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [3000; 3]);
+    }
+
+    #[test]
+    fn mirror() {
+        use super::Mirror;
+        use std::sync::{Arc, Mutex};
+
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let mirror = Arc::new(Mutex::new(Mirror::new(4)));
+        mixer.add_mirror(mirror.clone());
+
+        let id = mixer.add_sound((), Box::new(DebugSource::new(2, 10)));
+        mixer.play(id);
+        let mut buffer = [0; 3];
+        mixer.write_samples(&mut buffer);
+
+        let mut out = [0; 2];
+        mirror.lock().unwrap().pop(&mut out);
+        assert_eq!(out, [2, 2]);
+
+        // the oldest samples are dropped when the mirror is full
+        mixer.write_samples(&mut buffer);
+        mixer.set_volume(id, 2.0);
+        mixer.write_samples(&mut buffer);
+        let mut out = [9; 6];
+        mirror.lock().unwrap().pop(&mut out);
+        assert_eq!(out, [2, 4, 4, 4, 0, 0]);
+
+        // the mirror is removed once it is dropped
+        drop(mirror);
+        mixer.write_samples(&mut buffer);
+        assert!(mixer.mirrors.is_empty());
+    }
+
+    #[test]
+    fn commands() {
+        use std::sync::atomic::AtomicUsize;
+
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let handle = MixerHandle::<()>::new(&mut mixer);
+        let (id, state) = handle.add_sound((), Box::new(DebugSource::new(2, 10)), true);
+
+        // commands are applied before mixing
+        handle.command(move |m| m.play(id));
+        assert_eq!(mixer.sound_count(), 0);
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [2; 4]);
+        assert!(state.is_playing());
+
+        // the commands that don't fit in the queue are sent later
+        let applied = Arc::new(AtomicUsize::new(0));
+        for _ in 0..QUEUE_CAPACITY + 10 {
+            let applied = applied.clone();
+            handle.command(move |_| {
+                applied.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        mixer.write_samples(&mut buffer);
+        assert_eq!(applied.load(Ordering::Relaxed), QUEUE_CAPACITY);
+        handle.collect_garbage();
+        mixer.write_samples(&mut buffer);
+        assert_eq!(applied.load(Ordering::Relaxed), QUEUE_CAPACITY + 10);
+
+        // and the applied commands and removed sounds are sent back to be dropped by the handle
+        handle.command(move |m| {
+            m.remove_handle(id);
+            m.play(id);
+        });
+        mixer.write_samples(&mut [0; 20]);
+        assert!(!state.is_alive());
+        assert_eq!(handle.queue.lock().unwrap().garbage.slots(), 2);
+        handle.collect_garbage();
+        assert!(handle.queue.lock().unwrap().garbage.is_empty());
+    }
+
+    #[test]
+    fn sound_handles() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let handle = MixerHandle::<()>::new(&mut mixer);
+        let (id, state) = handle.add_sound((), Box::new(DebugSource::new(2, 2)), true);
+        let mut sound = crate::Sound {
+            mixer: handle.clone(),
+            id,
+            state,
+        };
+        let other = sound.clone_handle();
+
+        // the sound is kept while there is a handle
+        drop(sound.clone_handle());
+        sound.play();
+        drop(sound);
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [2, 2, 0, 0]);
+        assert_eq!(handle.sound_count(), 1);
+
+        // and removed after the last one is dropped
+        let mut sound = other;
+        sound.play();
+        drop(sound);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [2, 2, 0, 0]);
+        assert_eq!(handle.sound_count(), 0);
+        assert_eq!(mixer.sound_count(), 0);
+    }
+
+    #[test]
+    fn play_at() {
+        let mut mixer = Mixer::new(2, crate::SampleRate(1));
+        let id = mixer.add_sound(
+            (),
+            Box::new(crate::converter::ChannelConverter::new(
+                DebugSource::new(2, 3),
+                2,
+            )),
+        );
+        mixer.mark_to_remove(id, false);
+
+        // start in the middle of the buffer
+        mixer.play_at(id, 2);
+        let mut buffer = [0; 8];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0, 0, 0, 0, 2, 2, 2, 2]);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [2, 2, 0, 0, 0, 0, 0, 0]);
+
+        // start in a later buffer
+        mixer.play_at(id, 5);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0; 8]);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [0, 0, 2, 2, 2, 2, 2, 2]);
+
+        // a refused play don't delay the next one
+        mixer.stop(id);
+        mixer.set_max_voices(0);
+        mixer.play_at(id, 5);
+        mixer.set_max_voices(1);
+        mixer.play(id);
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [2, 2, 2, 2, 2, 2, 0, 0]);
+    }
+
+    #[test]
+    fn weak_sound() {
+        let mut mixer = Mixer::new(1, crate::SampleRate(1));
+        let handle = MixerHandle::<()>::new(&mut mixer);
+        let (id, state) = handle.add_sound((), Box::new(DebugSource::new(2, 2)), true);
+        let sound = crate::Sound {
+            mixer: handle.clone(),
+            id,
+            state,
+        };
+        let weak = sound.downgrade();
+
+        // a weak handle can control the sound, but don't keep it alive
+        weak.set_volume(0.5);
+        assert_eq!(weak.volume(), Some(0.5));
+        drop(sound);
+        weak.play();
+        let mut buffer = [0; 4];
+        mixer.write_samples(&mut buffer);
+        assert_eq!(buffer, [1, 1, 0, 0]);
+        assert!(!weak.is_alive());
+        assert!(weak.upgrade().is_none());
+
+        // after the sound is removed, its methods do nothing
+        weak.play();
+        assert_eq!(weak.volume(), None);
+    }
 }
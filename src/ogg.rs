@@ -1,84 +1,382 @@
-use lewton::inside_ogg::OggStreamReader;
+use lewton::{
+    audio::{read_audio_packet_generic, PreviousWindowRight},
+    header::{read_header_comment, read_header_ident, read_header_setup, IdentHeader, SetupHeader},
+    inside_ogg::read_headers,
+    samples::{Sample, Samples},
+    OggReadError, VorbisError,
+};
+use ogg::PacketReader;
 use std::{
-    io::{Read, Seek, SeekFrom},
-    vec::IntoIter,
+    convert::TryInto,
+    io::{self, Cursor, Read, Seek, SeekFrom},
 };
 
-use crate::SoundSource;
+use crate::{ForwardReader, SoundSource};
 
 /// A SourceSource, from ogg encoded sound data.
+///
+/// The decoded samples are kept in a buffer that is reused between packets, but lewton still
+/// allocates memory while decoding each packet, so unlike the other sources of this crate, this
+/// one allocates in the audio thread.
 pub struct OggDecoder<T: Seek + Read + Send + 'static> {
-    reader: Option<OggStreamReader<T>>,
-    buffer: IntoIter<i16>,
+    reader: PacketReader<T>,
+    ident_hdr: IdentHeader,
+    setup_hdr: SetupHeader,
+    stream_serial: u32,
+    pwr: PreviousWindowRight,
+    /// The absolute granule position of the last decoded sample, used to find the length of the
+    /// last packet.
+    cur_absgp: Option<u64>,
+    /// The position in bytes of the first audio page, where `reset` seeks to.
+    audio_start: u64,
+    /// If the end of the first logical stream was reached in a chained ogg file, in which case the
+    /// headers of the first stream need to be read again on `reset`.
+    chained: bool,
+    /// The interleaved samples of the last decoded packet.
+    samples: Vec<i16>,
+    /// The index of the next sample of `samples` to be written.
+    pos: usize,
+    /// If a error happened while seeking, and the decoder can no longer output samples.
     done: bool,
+    /// The number of frames of the first logical stream, if it could be found.
+    frames: Option<u64>,
 }
 impl<T: Seek + Read + Send + 'static> OggDecoder<T> {
     /// Create a new OggDecoder from the given .ogg data.
-    pub fn new(data: T) -> Result<Self, lewton::VorbisError> {
-        let mut reader = OggStreamReader::new(data)?;
-        // The first packed is always empty
-        let _ = reader.read_dec_packet_itl()?;
+    pub fn new(data: T) -> Result<Self, VorbisError> {
+        let mut reader = PacketReader::new(data);
+        let ((ident_hdr, _, setup_hdr), stream_serial) = read_headers(&mut reader)?;
+        let audio_start = reader
+            .seek_bytes(SeekFrom::Current(0))
+            .map_err(OggReadError::ReadError)?;
+
+        let mut data = reader.into_inner();
+        let frames = last_granule(&mut data, stream_serial);
+        // seek through the PacketReader, so it doesn't expect the start of the stream
+        let mut reader = PacketReader::new(data);
+        reader
+            .seek_bytes(SeekFrom::Start(audio_start))
+            .map_err(OggReadError::ReadError)?;
+
         Ok(Self {
-            buffer: reader
-                .read_dec_packet_itl()?
-                .unwrap_or_default()
-                .into_iter(),
-            reader: Some(reader),
+            reader,
+            ident_hdr,
+            setup_hdr,
+            stream_serial,
+            pwr: PreviousWindowRight::new(),
+            cur_absgp: None,
+            audio_start,
+            chained: false,
+            samples: Vec::new(),
+            pos: 0,
             done: false,
+            frames,
         })
     }
 
-    fn reader(&self) -> &OggStreamReader<T> {
-        self.reader.as_ref().unwrap()
+    /// Seek back to the first audio packet of the first logical stream.
+    fn seek_start(&mut self) -> Result<(), VorbisError> {
+        if self.chained {
+            self.reader
+                .seek_bytes(SeekFrom::Start(0))
+                .map_err(OggReadError::ReadError)?;
+            let ((ident_hdr, _, setup_hdr), stream_serial) = read_headers(&mut self.reader)?;
+            self.ident_hdr = ident_hdr;
+            self.setup_hdr = setup_hdr;
+            self.stream_serial = stream_serial;
+            self.chained = false;
+        } else {
+            self.reader
+                .seek_bytes(SeekFrom::Start(self.audio_start))
+                .map_err(OggReadError::ReadError)?;
+        }
+        self.pwr = PreviousWindowRight::new();
+        self.cur_absgp = None;
+        Ok(())
+    }
+
+    /// Read and decode the next audio packet to `samples`, returning `false` if the end of the
+    /// stream was reached.
+    ///
+    /// This is the same as `OggStreamReader::read_dec_packet_itl`, from lewton, but reusing the
+    /// buffer of the interleaved samples.
+    fn read_packet(&mut self) -> Result<bool, VorbisError> {
+        loop {
+            let pck = match self.reader.read_packet()? {
+                Some(x) => x,
+                None => return Ok(false),
+            };
+
+            if pck.stream_serial() != self.stream_serial {
+                if pck.first_in_stream() {
+                    // This is a chained ogg file, so the headers of the next stream need to be
+                    // read.
+                    let ident_hdr = read_header_ident(&pck.data)?;
+                    let pck = self.reader.read_packet_expected()?;
+                    read_header_comment(&pck.data)?;
+                    let pck = self.reader.read_packet_expected()?;
+                    let setup_hdr = read_header_setup(
+                        &pck.data,
+                        ident_hdr.audio_channels,
+                        (ident_hdr.blocksize_0, ident_hdr.blocksize_1),
+                    )?;
+                    self.ident_hdr = ident_hdr;
+                    self.setup_hdr = setup_hdr;
+                    self.stream_serial = pck.stream_serial();
+                    self.pwr = PreviousWindowRight::new();
+                    self.cur_absgp = None;
+                    self.chained = true;
+                }
+                // Ignore packets of other streams
+                continue;
+            }
+
+            let mut decoded: Vec<Vec<f32>> = read_audio_packet_generic(
+                &self.ident_hdr,
+                &self.setup_hdr,
+                &pck.data,
+                &mut self.pwr,
+            )?;
+
+            // The last packet must be truncated, so its ending matches the absgp of the page.
+            if let (Some(absgp), true) = (self.cur_absgp, pck.last_in_stream()) {
+                let len = pck.absgp_page().saturating_sub(absgp) as usize;
+                Samples::truncate(&mut decoded, len);
+            }
+            if pck.last_in_page() {
+                self.cur_absgp = Some(pck.absgp_page());
+            } else if let Some(absgp) = &mut self.cur_absgp {
+                *absgp += decoded.num_samples() as u64;
+            }
+
+            let frames = decoded.num_samples();
+            self.samples.clear();
+            self.samples.extend(
+                (0..frames).flat_map(|i| decoded.iter().map(move |x| i16::from_float(x[i]))),
+            );
+            self.pos = 0;
+            return Ok(true);
+        }
+    }
+}
+/// Find the granule position of the last page of the data, which in Vorbis is the number of
+/// frames in the stream.
+///
+/// Return `None` if the data can't seek to its end, or if the last page is not of the given
+/// stream, like in a chained ogg file.
+fn last_granule<T: Read + Seek>(data: &mut T, stream_serial: u32) -> Option<u64> {
+    // the maximum size of a page
+    const MAX_PAGE: u64 = 27 + 255 + 255 * 255;
+
+    let len = data.seek(SeekFrom::End(0)).ok()?;
+    data.seek(SeekFrom::Start(len.saturating_sub(MAX_PAGE)))
+        .ok()?;
+    let mut tail = Vec::new();
+    data.read_to_end(&mut tail).ok()?;
+
+    // "OggS" may also appear inside a packet, so the page must end exactly at the end of the data
+    let is_last_page = |i: usize| {
+        let header = tail.get(i..i + 27)?;
+        let segments = tail.get(i + 27..i + 27 + header[26] as usize)?;
+        let end = i + 27 + segments.len() + segments.iter().map(|&x| x as usize).sum::<usize>();
+        (header[4] == 0 && end == tail.len()).then_some(header)
+    };
+    let header = (0..tail.len().saturating_sub(3))
+        .rev()
+        .filter(|&i| tail[i..].starts_with(b"OggS"))
+        .find_map(is_last_page)?;
+
+    let granule = u64::from_le_bytes(header[6..14].try_into().unwrap());
+    let serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+    // a granule of -1 means that no packet ends in the page
+    (serial == stream_serial && granule != u64::MAX).then_some(granule)
+}
+
+impl<B: AsRef<[u8]> + Send + 'static> OggDecoder<Cursor<B>> {
+    /// Create a new OggDecoder from .ogg data in memory.
+    ///
+    /// This accepts any bytes, like the `&'static [u8; N]` returned by `include_bytes!`, a
+    /// `Vec<u8>` or a `Arc<[u8]>`, without needing to wrap them in a `Cursor`.
+    pub fn from_bytes(bytes: B) -> Result<Self, VorbisError> {
+        Self::new(Cursor::new(bytes))
+    }
+}
+impl<R: Read + Send + 'static> OggDecoder<ForwardReader<R>> {
+    /// Create a new OggDecoder from a `Read`-only source, like a socket or a pipe.
+    ///
+    /// The data is decoded as it is read, without buffering it all. Because the source cannot
+    /// seek back, the decoder cannot be reset: on `reset` an error is logged and the decoder
+    /// finishes. Use [`with_reopen`](Self::with_reopen) for sources that can be opened again.
+    pub fn from_reader(reader: R) -> Result<Self, VorbisError> {
+        Self::new(ForwardReader::new(reader))
     }
 
-    fn reader_mut(&mut self) -> &mut OggStreamReader<T> {
-        self.reader.as_mut().unwrap()
+    /// Create a new OggDecoder from a `Read`-only source, that is opened by calling `reopen`.
+    ///
+    /// On `reset`, `reopen` is called again, and the stream is decoded from the start.
+    pub fn with_reopen(
+        reopen: impl FnMut() -> io::Result<R> + Send + 'static,
+    ) -> Result<Self, VorbisError> {
+        let reader = ForwardReader::with_reopen(reopen).map_err(OggReadError::ReadError)?;
+        Self::new(reader)
     }
 }
 impl<T: Seek + Read + Send + 'static> SoundSource for OggDecoder<T> {
     fn channels(&self) -> u16 {
-        self.reader().ident_hdr.audio_channels as u16
+        self.ident_hdr.audio_channels as u16
     }
 
     fn sample_rate(&self) -> u32 {
-        self.reader().ident_hdr.audio_sample_rate
+        self.ident_hdr.audio_sample_rate
+    }
+
+    /// The length of the first logical stream, if the data can seek to its end.
+    fn size_hint(&self) -> Option<u64> {
+        Some(self.frames? * self.ident_hdr.audio_channels as u64)
     }
 
     fn reset(&mut self) {
-        let reader = self.reader.take();
-        let mut source = reader.unwrap().into_inner().into_inner();
-        source.seek(SeekFrom::Start(0)).unwrap();
-        let reader = OggStreamReader::new(source).unwrap();
-        self.reader = Some(reader);
+        self.samples.clear();
+        self.pos = 0;
         self.done = false;
-        // The first packed is always empty
-        let _ = self.reader_mut().read_dec_packet_itl().unwrap();
-        self.buffer = self
-            .reader_mut()
-            .read_dec_packet_itl()
-            .unwrap()
-            .unwrap_or_default()
-            .into_iter();
+        if let Err(err) = self.seek_start() {
+            log::error!("error while seeking ogg: {}", err);
+            self.done = true;
+        }
     }
-    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
-        let mut i = 0;
 
-        'main: while i < buffer.len() {
-            if let Some(next) = self.buffer.next() {
-                buffer[i] = next;
-                i += 1;
-            } else {
-                while let Some(pck) = self.reader_mut().read_dec_packet_itl().unwrap() {
-                    if !pck.is_empty() {
-                        self.buffer = pck.into_iter();
-                        continue 'main;
+    fn seek(&mut self, frame: u64) {
+        self.reset();
+        // Vorbis can only be decoded from the start of a packet, so the packets before the
+        // position are decoded and discarded whole, without copying their samples.
+        let channels = self.channels() as u64;
+        let mut remaining = frame.saturating_mul(channels);
+        while remaining > 0 && !self.done {
+            match self.read_packet() {
+                Ok(true) => {
+                    if (self.samples.len() as u64) > remaining {
+                        self.pos = remaining as usize;
+                        return;
                     }
+                    self.pos = self.samples.len();
+                    remaining -= self.samples.len() as u64;
+                }
+                Ok(false) => return,
+                Err(err) => {
+                    log::error!("error while decoding ogg: {}", err);
+                    self.done = true;
+                }
+            }
+        }
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        if self.done {
+            return 0;
+        }
+
+        let mut i = 0;
+        while i < buffer.len() {
+            if self.pos < self.samples.len() {
+                let len = (self.samples.len() - self.pos).min(buffer.len() - i);
+                buffer[i..i + len].copy_from_slice(&self.samples[self.pos..self.pos + len]);
+                i += len;
+                self.pos += len;
+                continue;
+            }
+            // The first packet of a stream is always empty, so this may read more than one packet
+            match self.read_packet() {
+                Ok(true) => {}
+                Ok(false) => return i,
+                Err(err) => {
+                    log::error!("error while decoding ogg: {}", err);
+                    // Returning the current number of decoded samples before the error,
+                    // indicating that the SoundSource finished.
+                    return i;
                 }
-                return i;
             }
         }
 
         buffer.len()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::SoundSource;
+
+    use super::OggDecoder;
+
+    #[test]
+    fn reset() {
+        let data = &include_bytes!("../examples/pipe.ogg")[..];
+        let mut decoder = OggDecoder::from_bytes(data).unwrap();
+
+        let mut first = vec![0; 100_000];
+        let len = decoder.write_samples(&mut first);
+        assert_eq!(len, first.len());
+
+        decoder.reset();
+        let mut second = vec![0; 100_000];
+        assert_eq!(decoder.write_samples(&mut second), len);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn seek() {
+        let data = &include_bytes!("../examples/pipe.ogg")[..];
+        let mut decoder = OggDecoder::from_bytes(data).unwrap();
+        let mut expected = vec![0; 100_000];
+        decoder.write_samples(&mut expected);
+
+        // a position in the middle of a packet
+        let channels = decoder.channels() as usize;
+        decoder.seek(20_001);
+        let mut buffer = vec![0; 1000];
+        assert_eq!(decoder.write_samples(&mut buffer), buffer.len());
+        let start = 20_001 * channels;
+        assert_eq!(buffer[..], expected[start..start + 1000]);
+
+        decoder.seek(u32::MAX as u64);
+        assert_eq!(decoder.write_samples(&mut buffer), 0);
+        decoder.seek(u64::MAX);
+        assert_eq!(decoder.write_samples(&mut buffer), 0);
+    }
+
+    #[test]
+    fn duration() {
+        let data = &include_bytes!("../examples/pipe.ogg")[..];
+        let mut decoder = OggDecoder::from_bytes(data).unwrap();
+        let hint = decoder.size_hint().unwrap();
+        let mut buffer = vec![0; hint as usize + 1000];
+        assert_eq!(decoder.write_samples(&mut buffer) as u64, hint);
+        assert!(decoder.duration().is_some());
+
+        // the end of a Read-only source is unknown
+        let decoder = OggDecoder::from_reader(data).unwrap();
+        assert_eq!(decoder.duration(), None);
+    }
+
+    #[test]
+    fn from_reader() {
+        let data = &include_bytes!("../examples/pipe.ogg")[..];
+        let mut decoder = OggDecoder::from_bytes(data).unwrap();
+        let mut expected = vec![0; 100_000];
+        let len = decoder.write_samples(&mut expected);
+
+        let mut decoder = OggDecoder::from_reader(data).unwrap();
+        let mut buffer = vec![0; 100_000];
+        assert_eq!(decoder.write_samples(&mut buffer), len);
+        assert_eq!(buffer, expected);
+
+        // the source can't seek back, so the decoder finishes
+        decoder.reset();
+        assert_eq!(decoder.write_samples(&mut buffer), 0);
+
+        let mut decoder = OggDecoder::with_reopen(move || Ok(data)).unwrap();
+        assert_eq!(decoder.write_samples(&mut buffer), len);
+        decoder.reset();
+        assert_eq!(decoder.write_samples(&mut buffer), len);
+        assert_eq!(buffer, expected);
+    }
+}
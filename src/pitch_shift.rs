@@ -0,0 +1,162 @@
+use crate::{clamp_param, SoundSource, TimeStretch};
+
+/// A SoundSource that changes the pitch of another source, without changing its speed.
+///
+/// The source is stretched by a [`TimeStretch`] and then resampled back to its original
+/// duration, using linear interpolation, which changes its pitch.
+pub struct PitchShift<T: SoundSource> {
+    inner: TimeStretch<T>,
+    semitones: f32,
+    /// The number of frames of `inner` consumed by each output frame.
+    ratio: f64,
+    /// The current and the next frame of `inner`, that are interpolated.
+    curr: Vec<i16>,
+    next: Vec<i16>,
+    /// The position between `curr` and `next` of the next output frame.
+    t: f64,
+    /// The samples read from `inner`.
+    input: Vec<i16>,
+    /// The number of valid samples in `input`, and how many of them were already used.
+    input_len: usize,
+    input_pos: usize,
+}
+impl<T: SoundSource> PitchShift<T> {
+    /// Create a new PitchShift, that transposes the source by the given number of semitones.
+    ///
+    /// See [`set_semitones`](Self::set_semitones).
+    pub fn new(inner: T, semitones: f32) -> Self {
+        let channels = inner.channels() as usize;
+        let mut this = Self {
+            inner: TimeStretch::new(inner, 1.0),
+            semitones: 0.0,
+            ratio: 1.0,
+            curr: vec![0; channels],
+            next: vec![0; channels],
+            // the first two frames of `inner` are read before the first output frame
+            t: 2.0,
+            input: vec![0; 1024 * channels],
+            input_len: 0,
+            input_pos: 0,
+        };
+        this.set_semitones(semitones);
+        this
+    }
+
+    /// Set by how many semitones the source is transposed, clamped to the range `-24.0..=24.0`.
+    ///
+    /// Positive values raise the pitch, and negative values lower it. A value of 12.0 raises the
+    /// source by one octave.
+    pub fn set_semitones(&mut self, semitones: f32) {
+        self.semitones = clamp_param(semitones, -24.0, 24.0);
+        self.ratio = 2f64.powf(self.semitones as f64 / 12.0);
+        self.inner.set_rate(1.0 / self.ratio as f32);
+    }
+
+    /// By how many semitones the source is transposed.
+    pub fn semitones(&self) -> f32 {
+        self.semitones
+    }
+
+    /// Move to the next frame of `inner`. Return false if `inner` reached its end.
+    fn advance(&mut self) -> bool {
+        if self.input_pos == self.input_len {
+            self.input_len = self.inner.write_samples(&mut self.input);
+            self.input_pos = 0;
+            if self.input_len == 0 {
+                return false;
+            }
+        }
+        let channels = self.curr.len();
+        std::mem::swap(&mut self.curr, &mut self.next);
+        let frame = &self.input[self.input_pos..self.input_pos + channels];
+        self.next.copy_from_slice(frame);
+        self.input_pos += channels;
+        true
+    }
+}
+impl<T: SoundSource> SoundSource for PitchShift<T> {
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        let channels = self.inner.channels() as u64;
+        let len = self.inner.size_hint()? as f64 / self.ratio;
+        Some(len as u64 / channels * channels)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.t = 2.0;
+        self.input_len = 0;
+        self.input_pos = 0;
+    }
+
+    fn seek(&mut self, frame: u64) {
+        self.inner.seek((frame as f64 * self.ratio) as u64);
+        self.t = 2.0;
+        self.input_len = 0;
+        self.input_pos = 0;
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let channels = self.curr.len();
+        let mut len = 0;
+        for frame in buffer.chunks_exact_mut(channels) {
+            while self.t >= 1.0 {
+                if !self.advance() {
+                    return len;
+                }
+                self.t -= 1.0;
+            }
+            let t = self.t as f32;
+            for ((out, curr), next) in frame.iter_mut().zip(&self.curr).zip(&self.next) {
+                // interpolate by t, curr and next sample
+                *out = (*curr as f32 * (1.0 - t) + *next as f32 * t) as i16;
+            }
+            self.t += self.ratio;
+            len += channels;
+        }
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Constant, SineWave, SoundSource};
+
+    use super::PitchShift;
+
+    #[test]
+    fn pitch_shift() {
+        // count the number of times the wave crosses zero
+        let crossings = |x: &[i16]| x.windows(2).filter(|x| (x[0] < 0) != (x[1] < 0)).count();
+
+        let mut shift = PitchShift::new(SineWave::new(8000, 400.0), 12.0);
+        let mut buffer = vec![0; 8000];
+        shift.write_samples(&mut buffer);
+        let count = crossings(&buffer);
+        assert!((1580..=1620).contains(&count), "{}", count);
+
+        shift.set_semitones(-12.0);
+        shift.write_samples(&mut buffer);
+        let count = crossings(&buffer);
+        assert!((390..=410).contains(&count), "{}", count);
+    }
+
+    #[test]
+    fn keep_duration() {
+        for semitones in [-12.0, 7.0] {
+            let mut source = Constant::new(1000, 2, 8000);
+            source.len = Some(8000);
+            let mut shift = PitchShift::new(source, semitones);
+            let mut buffer = vec![0; 4 * 8000 * 2];
+            let len = shift.write_samples(&mut buffer);
+            assert!(len.abs_diff(8000 * 2) <= 160 * 2, "{}", len);
+        }
+    }
+}
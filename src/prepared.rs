@@ -0,0 +1,199 @@
+use std::{
+    hash::Hash,
+    sync::mpsc::{self, Receiver, TryRecvError},
+};
+
+use crate::{mixer::MixerHandle, AudioEngineError, Sound, SoundSource};
+
+/// A sound whose first samples are being decoded in a worker thread, created by
+/// [`AudioEngine::prepare`](crate::AudioEngine::prepare).
+///
+/// Decoding the first samples of a source can be slow, for example when the first packet of a
+/// compressed file is decoded, which would delay the first buffer in which the sound plays. Once
+/// the sound [is ready](Self::is_ready), turning it into a [`Sound`] and playing it is instant.
+pub struct PreparedSound<G: Eq + Hash + Send + 'static = ()> {
+    mixer: MixerHandle<G>,
+    group: G,
+    receiver: Receiver<Box<dyn SoundSource + Send>>,
+    /// The prebuffered source, after it was received from the worker thread.
+    source: Option<Box<dyn SoundSource + Send>>,
+}
+impl<G: Eq + Hash + Send + 'static> PreparedSound<G> {
+    /// Start decoding the first `frames` frames of `source` in a new thread.
+    pub(crate) fn new(
+        mixer: MixerHandle<G>,
+        group: G,
+        source: Box<dyn SoundSource + Send>,
+        frames: usize,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let source: Box<dyn SoundSource + Send> = Box::new(Prebuffer::new(source, frames));
+            let _ = sender.send(source);
+        });
+        Self {
+            mixer,
+            group,
+            receiver,
+            source: None,
+        }
+    }
+
+    /// Return true if the first samples of the sound were already decoded.
+    pub fn is_ready(&mut self) -> bool {
+        if self.source.is_none() {
+            match self.receiver.try_recv() {
+                Ok(source) => self.source = Some(source),
+                Err(TryRecvError::Empty) => return false,
+                // the worker panicked, which is reported by `into_sound`
+                Err(TryRecvError::Disconnected) => return true,
+            }
+        }
+        true
+    }
+
+    /// Add the sound to the engine, in the stopped state.
+    ///
+    /// If the sound is not [ready](Self::is_ready) yet, this blocks until it is. Return a error if
+    /// the source panicked while being decoded, or if the output config changed since the sound
+    /// was prepared, and the [`ResamplePolicy`](crate::ResamplePolicy) of the engine denies
+    /// converting it again.
+    pub fn into_sound(mut self) -> Result<Sound<G>, AudioEngineError> {
+        let mut source = match self.source.take() {
+            Some(x) => x,
+            None => self
+                .receiver
+                .recv()
+                .map_err(|_| AudioEngineError::SourcePanicked)?,
+        };
+        // the output config may have changed since the source was converted
+        if source.channels() != self.mixer.channels()
+            || source.sample_rate() != self.mixer.sample_rate()
+        {
+            source = self.mixer.convert_source(source)?;
+        }
+        let (id, state) = self.mixer.add_sound(self.group, source, true);
+        Ok(Sound {
+            mixer: self.mixer,
+            id,
+            state,
+        })
+    }
+
+    /// Add the sound to the engine, and start playing it.
+    ///
+    /// See [`into_sound`](Self::into_sound).
+    pub fn play(self) -> Result<Sound<G>, AudioEngineError> {
+        let mut sound = self.into_sound()?;
+        sound.play();
+        Ok(sound)
+    }
+}
+
+/// A SoundSource that outputs samples decoded ahead of time, before continuing to read from its
+/// inner source.
+struct Prebuffer<T: SoundSource> {
+    inner: T,
+    buffer: Vec<i16>,
+    /// The number of samples of `buffer` already written.
+    pos: usize,
+}
+impl<T: SoundSource> Prebuffer<T> {
+    /// Create a new Prebuffer, decoding the first `frames` frames of `inner`.
+    fn new(mut inner: T, frames: usize) -> Self {
+        let mut buffer = vec![0; frames * inner.channels() as usize];
+        let len = inner.write_samples(&mut buffer);
+        buffer.truncate(len);
+        Self {
+            inner,
+            buffer,
+            pos: 0,
+        }
+    }
+}
+impl<T: SoundSource> SoundSource for Prebuffer<T> {
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        self.inner.size_hint()
+    }
+
+    fn reset(&mut self) {
+        // the samples of `buffer` will be decoded again
+        self.inner.reset();
+        self.buffer.clear();
+        self.pos = 0;
+    }
+
+    fn seek(&mut self, frame: u64) {
+        self.inner.seek(frame);
+        self.buffer.clear();
+        self.pos = 0;
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let prebuffered = &self.buffer[self.pos..];
+        let len = prebuffered.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&prebuffered[..len]);
+        self.pos += len;
+        if len == buffer.len() {
+            return len;
+        }
+        len + self.inner.write_samples(&mut buffer[len..])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        mixer::MixerHandle, AudioEngineError, Mixer, ResamplePolicy, SampleRate, SliceSource,
+        SoundSource,
+    };
+
+    use super::{Prebuffer, PreparedSound};
+
+    #[test]
+    fn prebuffer() {
+        let samples: Vec<i16> = (0..10).collect();
+        let mut source = Prebuffer::new(SliceSource::new(samples, 2, 10), 2);
+        assert_eq!(source.buffer, [0, 1, 2, 3]);
+
+        let mut buffer = [0; 6];
+        assert_eq!(source.write_samples(&mut buffer), 6);
+        assert_eq!(buffer, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(source.write_samples(&mut buffer), 4);
+        assert_eq!(buffer[..4], [6, 7, 8, 9]);
+
+        source.reset();
+        let mut buffer = [0; 12];
+        assert_eq!(source.write_samples(&mut buffer), 10);
+        assert_eq!(buffer[..10], (0..10).collect::<Vec<_>>()[..]);
+    }
+
+    #[test]
+    fn config_changed() {
+        let mut mixer = Mixer::new(1, SampleRate(10));
+        let mut handle = MixerHandle::<()>::new(&mut mixer);
+        handle.resample_policy = ResamplePolicy::Deny;
+        let source = SliceSource::new(vec![1, 2, 3, 4], 1, 10);
+        let source = handle.convert_source(source).unwrap();
+        let prepared = PreparedSound::new(handle, (), source, 2);
+
+        // the source is converted again following the policy of the engine
+        mixer.set_config(1, SampleRate(20));
+        let err = prepared.into_sound().err();
+        assert!(matches!(
+            err,
+            Some(AudioEngineError::SampleRateMismatch {
+                source: 10,
+                output: 20
+            })
+        ));
+    }
+}
@@ -0,0 +1,54 @@
+//! Raising the priority of the audio thread, to reduce the risk of underruns under load.
+
+/// Try to give the current thread a real-time priority.
+///
+/// On Linux and other Unix systems this requests the `SCHED_FIFO` scheduling policy, which usually
+/// requires the `CAP_SYS_NICE` capability or a `rtprio` limit set for the user. If the system
+/// refuses it, a warning is logged and the thread keeps its normal priority. On macOS, iOS and
+/// Android, the audio threads of the hosts already have a real-time priority, and on the other
+/// platforms this is not supported, so this does nothing.
+pub(crate) fn promote_current_thread() {
+    imp::promote_current_thread()
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "ios", target_os = "android"))
+))]
+mod imp {
+    pub(super) fn promote_current_thread() {
+        // SAFETY: these functions are only called with the current thread, and valid pointers.
+        unsafe {
+            let policy = libc::SCHED_FIFO;
+            let min = libc::sched_get_priority_min(policy);
+            let max = libc::sched_get_priority_max(policy);
+            if min < 0 || max < 0 {
+                log::warn!("failed to query the real-time priorities of the audio thread");
+                return;
+            }
+            // A high priority, but below the maximum, that is usually reserved for the system.
+            let param = libc::sched_param {
+                sched_priority: min + (max - min) * 3 / 4,
+            };
+            let result = libc::pthread_setschedparam(libc::pthread_self(), policy, &param);
+            if result == 0 {
+                log::debug!("audio thread promoted to real-time priority");
+            } else {
+                log::warn!(
+                    "failed to set real-time priority for the audio thread: {}",
+                    std::io::Error::from_raw_os_error(result)
+                );
+            }
+        }
+    }
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "ios", target_os = "android"))
+)))]
+mod imp {
+    pub(super) fn promote_current_thread() {
+        log::debug!("real-time priority is not changed on this platform");
+    }
+}
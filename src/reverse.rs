@@ -0,0 +1,114 @@
+use crate::{AudioEngineError, SliceSource, SoundSource};
+
+/// A SoundSource that plays the samples of a [`SliceSource`] backwards.
+///
+/// The order of the frames is reversed, but the channels in each frame keep their order. Because
+/// the source needs to be played from its end, it must be fully in memory. Use
+/// [`from_source`](Reverse::from_source) to decode any other SoundSource to memory.
+pub struct Reverse<'a> {
+    source: SliceSource<'a>,
+    /// The number of frames not yet written, that is, the index of the frame after the next frame
+    /// to be written.
+    end: usize,
+}
+impl<'a> Reverse<'a> {
+    /// Create a new Reverse, that plays the given source backwards.
+    pub fn new(source: SliceSource<'a>) -> Self {
+        let end = source.samples().len() / source.channels() as usize;
+        Self { source, end }
+    }
+
+    /// Decode all samples of the given source to memory, and create a Reverse that plays them
+    /// backwards.
+    ///
+    /// Return an error if the source has more than `max_frames` frames, which is always the case
+    /// for sources that never end.
+    pub fn from_source<T: SoundSource>(
+        source: T,
+        max_frames: usize,
+    ) -> Result<Reverse<'static>, AudioEngineError> {
+        SliceSource::from_source(source, max_frames).map(Reverse::new)
+    }
+}
+impl SoundSource for Reverse<'_> {
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        self.source.size_hint()
+    }
+
+    fn reset(&mut self) {
+        self.end = self.source.samples().len() / self.channels() as usize;
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let channels = self.channels() as usize;
+        let samples = self.source.samples();
+        let mut len = 0;
+        for frame in buffer.chunks_exact_mut(channels) {
+            if self.end == 0 {
+                break;
+            }
+            self.end -= 1;
+            frame.copy_from_slice(&samples[self.end * channels..][..channels]);
+            len += channels;
+        }
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{SliceSource, SoundSource};
+
+    use super::Reverse;
+
+    #[test]
+    fn reverse() {
+        let samples = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let mut reverse = Reverse::new(SliceSource::new(samples, 2, 10));
+
+        let mut buffer = [0; 6];
+        assert_eq!(reverse.write_samples(&mut buffer), 6);
+        assert_eq!(buffer, [7, 8, 5, 6, 3, 4]);
+        assert_eq!(reverse.write_samples(&mut buffer), 2);
+        assert_eq!(buffer[..2], [1, 2]);
+
+        reverse.reset();
+        let mut buffer = [0; 10];
+        assert_eq!(reverse.write_samples(&mut buffer), 8);
+        assert_eq!(buffer[..8], [7, 8, 5, 6, 3, 4, 1, 2]);
+    }
+
+    #[test]
+    fn from_source() {
+        let mut source = crate::Constant::new(5, 2, 10);
+        source.len = Some(3000);
+        let mut reverse = Reverse::from_source(source, 3000).unwrap();
+        let mut buffer = vec![0; 8000];
+        assert_eq!(reverse.write_samples(&mut buffer), 6000);
+        assert!(buffer[..6000].iter().all(|&x| x == 5));
+
+        // a source that never ends can't be reversed
+        let source = crate::Constant::new(5, 2, 10);
+        assert!(Reverse::from_source(source, 3000).is_err());
+    }
+
+    #[test]
+    fn many_channels() {
+        // a frame doesn't fit in the buffers used for decoding, seeking and converting to f32
+        let mut source = crate::Constant::new(5, 2000, 10);
+        source.len = Some(3);
+        let mut reverse = Reverse::from_source(source, 3).unwrap();
+        reverse.seek(1);
+        let mut buffer = vec![0.0; 8000];
+        assert_eq!(reverse.write_samples_f32(&mut buffer), 4000);
+        assert_eq!(buffer[0], cpal::Sample::to_f32(&5i16));
+    }
+}
@@ -0,0 +1,114 @@
+//! Functions for adding the output of a sound to the mixer buffer.
+//!
+//! On x86 and x86_64, when the cpu supports AVX2 (detected at runtime), the loops are compiled
+//! with that target feature enabled, which allows them to process 16 samples at a time. Otherwise
+//! the scalar version is used.
+
+/// The number of samples processed on each iteration.
+const LANES: usize = 16;
+
+/// Add each sample of `src` to `dst`, saturating on overflow.
+pub(crate) fn mix(dst: &mut [i16], src: &[i16]) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: we just checked that the cpu supports AVX2.
+        return unsafe { avx2::mix(dst, src) };
+    }
+    mix_scalar(dst, src)
+}
+
+/// Add each sample of `src`, multiplied by `volume`, to `dst`, saturating on overflow.
+pub(crate) fn mix_volume(dst: &mut [i16], src: &[i16], volume: f32) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: we just checked that the cpu supports AVX2.
+        return unsafe { avx2::mix_volume(dst, src, volume) };
+    }
+    mix_volume_scalar(dst, src, volume)
+}
+
+#[inline(always)]
+fn mix_scalar(dst: &mut [i16], src: &[i16]) {
+    let mut dst_chunks = dst.chunks_exact_mut(LANES);
+    let mut src_chunks = src.chunks_exact(LANES);
+    for (d, s) in (&mut dst_chunks).zip(&mut src_chunks) {
+        for i in 0..LANES {
+            d[i] = d[i].saturating_add(s[i]);
+        }
+    }
+    let dst = dst_chunks.into_remainder();
+    let src = src_chunks.remainder();
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = d.saturating_add(*s);
+    }
+}
+
+#[inline(always)]
+fn mix_volume_scalar(dst: &mut [i16], src: &[i16], volume: f32) {
+    let mut dst_chunks = dst.chunks_exact_mut(LANES);
+    let mut src_chunks = src.chunks_exact(LANES);
+    for (d, s) in (&mut dst_chunks).zip(&mut src_chunks) {
+        for i in 0..LANES {
+            d[i] = d[i].saturating_add((s[i] as f32 * volume) as i16);
+        }
+    }
+    let dst = dst_chunks.into_remainder();
+    let src = src_chunks.remainder();
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = d.saturating_add((*s as f32 * volume) as i16);
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod avx2 {
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn mix(dst: &mut [i16], src: &[i16]) {
+        super::mix_scalar(dst, src)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn mix_volume(dst: &mut [i16], src: &[i16], volume: f32) {
+        super::mix_volume_scalar(dst, src, volume)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    /// Generate samples covering the full range of i16, with a length that is not a multiple of
+    /// the number of lanes.
+    fn samples(seed: u32) -> Vec<i16> {
+        let mut x = seed;
+        (0..1000)
+            .map(|_| {
+                x = x.wrapping_mul(1664525).wrapping_add(1013904223);
+                (x >> 16) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mix() {
+        let src = samples(1);
+        let mut dst = samples(2);
+        let mut expected = dst.clone();
+        for (d, s) in expected.iter_mut().zip(&src) {
+            *d = d.saturating_add(*s);
+        }
+        super::mix(&mut dst, &src);
+        assert_eq!(dst, expected);
+    }
+
+    #[test]
+    fn mix_volume() {
+        for &volume in &[0.0, 0.3, 0.5, 1.5, -2.0, 1e10] {
+            let src = samples(3);
+            let mut dst = samples(4);
+            let mut expected = dst.clone();
+            for (d, s) in expected.iter_mut().zip(&src) {
+                *d = d.saturating_add((*s as f32 * volume) as i16);
+            }
+            super::mix_volume(&mut dst, &src, volume);
+            assert_eq!(dst, expected, "volume {}", volume);
+        }
+    }
+}
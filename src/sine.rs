@@ -1,6 +1,6 @@
 use std::f64::consts::TAU;
 
-use crate::SoundSource;
+use crate::{clamp_param, SoundSource};
 
 /// A SoundSource that generates a sine wave with a given frequency.
 pub struct SineWave {
@@ -10,38 +10,141 @@ pub struct SineWave {
     pub sample_rate: u32,
     /// The frequency of the sine wave, in Hertz
     pub freq: f32,
+    channels: u16,
+    phase: f32,
+    amplitude: f32,
 }
 impl SineWave {
     /// Create a new SineWave SoundSource.
     ///
-    /// Created one the given sample_rate and frequency, both in Hertz.
+    /// Created one the given sample_rate and frequency, both in Hertz. The created SineWave is
+    /// mono, has a phase of 0.0 and a amplitude of 0.25.
     pub fn new(sample_rate: u32, freq: f32) -> Self {
         Self {
             i: 0,
             sample_rate,
             freq,
+            channels: 1,
+            phase: 0.0,
+            amplitude: 0.25,
         }
     }
+
+    /// Set the number of channels of this SoundSource. All channels receive the same samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` is zero.
+    pub fn set_channels(&mut self, channels: u16) {
+        assert!(channels > 0, "a SineWave can't have zero channels");
+        self.channels = channels;
+    }
+
+    /// Set the phase offset of the sine wave, in radians. With a phase of 0.0, the wave starts at
+    /// its peak, like a cosine.
+    pub fn set_phase(&mut self, phase: f32) {
+        self.phase = phase;
+    }
+
+    /// The phase offset of the sine wave, in radians.
+    pub fn phase(&self) -> f32 {
+        self.phase
+    }
+
+    /// Set the amplitude of the sine wave, clamped to the range `0.0..=1.0`, where 1.0 is the
+    /// maximum sample value.
+    pub fn set_amplitude(&mut self, amplitude: f32) {
+        self.amplitude = clamp_param(amplitude, 0.0, 1.0);
+    }
+
+    /// The amplitude of the sine wave.
+    pub fn amplitude(&self) -> f32 {
+        self.amplitude
+    }
+
+    /// Return the sample of the next frame, in the range -amplitude..=amplitude.
+    fn next_sample(&mut self) -> f64 {
+        // With a mantissa of 52 bits, at 96000 Hz, i as f64 will lose precision after 1486
+        // years.
+        let t = self.i as f64 / self.sample_rate as f64;
+        self.i = self.i.wrapping_add(1);
+        (self.freq as f64 * TAU * t + self.phase as f64).cos() * self.amplitude as f64
+    }
 }
 impl SoundSource for SineWave {
     fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
     fn channels(&self) -> u16 {
-        1
+        self.channels
     }
     fn reset(&mut self) {
         self.i = 0
     }
+    fn seek(&mut self, frame: u64) {
+        self.i = frame
+    }
     fn write_samples(&mut self, out: &mut [i16]) -> usize {
-        for o in out.iter_mut() {
-            // With a mantissa of 52 bits, at 96000 Hz, i as f64 will lose precision after 1486
-            // years.
-            let t = self.i as f64 / self.sample_rate() as f64;
-            let amplitude = (i16::max_value() / 4) as f64;
-            *o = ((self.freq as f64 * TAU * t).cos() * amplitude) as i16;
-            self.i += 1;
+        for frame in out.chunks_mut(self.channels as usize) {
+            frame.fill((self.next_sample() * i16::MAX as f64) as i16);
         }
         out.len()
     }
+    fn write_samples_f32(&mut self, out: &mut [f32]) -> usize {
+        for frame in out.chunks_mut(self.channels as usize) {
+            frame.fill(self.next_sample() as f32);
+        }
+        out.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SoundSource;
+
+    use super::SineWave;
+
+    #[test]
+    fn default() {
+        let mut sine = SineWave::new(4, 1.0);
+        assert_eq!(sine.channels(), 1);
+        assert_eq!(sine.phase(), 0.0);
+        assert_eq!(sine.amplitude(), 0.25);
+
+        let mut buffer = [0; 4];
+        assert_eq!(sine.write_samples(&mut buffer), 4);
+        assert_eq!(buffer, [8191, 0, -8191, 0]);
+    }
+
+    #[test]
+    fn channels_and_phase() {
+        let mut sine = SineWave::new(4, 1.0);
+        sine.set_channels(2);
+        sine.set_phase(std::f32::consts::PI);
+        assert_eq!(sine.channels(), 2);
+
+        let mut buffer = [0.0; 8];
+        assert_eq!(sine.write_samples_f32(&mut buffer), 8);
+        let expected = [-0.25, -0.25, 0.0, 0.0, 0.25, 0.25, 0.0, 0.0];
+        for (x, e) in buffer.iter().zip(expected) {
+            assert!((x - e).abs() < 1e-6, "{:?}", buffer);
+        }
+    }
+
+    #[test]
+    fn amplitude() {
+        let mut sine = SineWave::new(4, 1.0);
+        sine.set_amplitude(1.0);
+        let mut buffer = [0; 3];
+        sine.write_samples(&mut buffer);
+        assert_eq!(buffer[0], i16::MAX);
+        assert_eq!(buffer[2], -i16::MAX);
+
+        sine.set_amplitude(2.0);
+        assert_eq!(sine.amplitude(), 1.0);
+        sine.set_amplitude(f32::NAN);
+        assert_eq!(sine.amplitude(), 0.0);
+        sine.set_amplitude(-1.0);
+        assert_eq!(sine.amplitude(), 0.0);
+    }
 }
@@ -0,0 +1,181 @@
+use std::borrow::Cow;
+
+use crate::{AudioEngineError, SoundSource};
+
+/// A SoundSource that plays samples from memory.
+///
+/// The samples can be borrowed, like a `&[i16]`, or owned, like a `Vec<i16>`. To be played by a
+/// [`AudioEngine`](crate::AudioEngine) the source must be `'static`, so a owned `Vec<i16>` is
+/// usually what you want.
+///
+/// Samples of each channel must be interleaved.
+pub struct SliceSource<'a> {
+    samples: Cow<'a, [i16]>,
+    channels: u16,
+    sample_rate: u32,
+    /// The index of the next sample to be written.
+    i: usize,
+}
+impl<'a> SliceSource<'a> {
+    /// Create a new SliceSource, from the given samples, number of channels and sample rate.
+    ///
+    /// If the number of samples is not a multiple of `channels`, the last incomplete frame is
+    /// ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` is zero.
+    pub fn new(samples: impl Into<Cow<'a, [i16]>>, channels: u16, sample_rate: u32) -> Self {
+        assert!(channels > 0, "a SliceSource can't have zero channels");
+        let mut samples = samples.into();
+        let len = samples.len() / channels as usize * channels as usize;
+        if len != samples.len() {
+            match &mut samples {
+                Cow::Borrowed(x) => *x = &x[..len],
+                Cow::Owned(x) => x.truncate(len),
+            }
+        }
+        Self {
+            samples,
+            channels,
+            sample_rate,
+            i: 0,
+        }
+    }
+
+    /// The samples of this source.
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Return the samples of this source, cloning them if they are borrowed.
+    pub fn into_samples(self) -> Vec<i16> {
+        self.samples.into_owned()
+    }
+}
+impl SliceSource<'static> {
+    /// Decode all samples of the given source to memory, and create a SliceSource that plays
+    /// them, with the same number of channels and sample rate.
+    ///
+    /// This pays the cost of decoding up front, instead of in the audio thread. Return an error if
+    /// the source has more than `max_frames` frames, which is always the case for sources that
+    /// never end.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use audio_engine::{decode, AudioEngine, FormatHint, SliceSource};
+    /// let path = std::path::Path::new("music.ogg");
+    /// let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    /// let decoder = decode(file, FormatHint::from_path(path))?;
+    /// // at most 10 minutes
+    /// let source = SliceSource::from_source(decoder, 10 * 60 * 48000)?;
+    ///
+    /// let audio_engine = AudioEngine::new()?;
+    /// let mut sound = audio_engine.new_sound(source)?;
+    /// sound.play();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_source<T: SoundSource>(
+        mut source: T,
+        max_frames: usize,
+    ) -> Result<Self, AudioEngineError> {
+        let channels = source.channels() as usize;
+        if channels == 0 {
+            return Err(AudioEngineError::ZeroChannels);
+        }
+        let capacity = source
+            .size_hint()
+            .unwrap_or(0)
+            .min((max_frames * channels) as u64);
+        let mut samples = Vec::with_capacity(capacity as usize);
+        crate::with_scratch(channels as u16, |buffer| loop {
+            let len = source.write_samples(buffer);
+            samples.extend_from_slice(&buffer[..len]);
+            if samples.len() > max_frames * channels {
+                return Err(AudioEngineError::SourceTooLong);
+            }
+            if len < buffer.len() {
+                return Ok(());
+            }
+        })?;
+        Ok(SliceSource::new(
+            samples,
+            channels as u16,
+            source.sample_rate(),
+        ))
+    }
+}
+impl SoundSource for SliceSource<'_> {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn reset(&mut self) {
+        self.i = 0;
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let len = (self.samples.len() - self.i).min(buffer.len());
+        buffer[..len].copy_from_slice(&self.samples[self.i..self.i + len]);
+        self.i += len;
+        len
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        Some(self.samples.len() as u64)
+    }
+
+    fn seek(&mut self, frame: u64) {
+        let i = frame.saturating_mul(self.channels as u64);
+        self.i = i.min(self.samples.len() as u64) as usize;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SoundSource;
+
+    use super::SliceSource;
+
+    #[test]
+    fn slice_source() {
+        let samples = [1, 2, 3, 4, 5, 6, 7];
+        let mut source = SliceSource::new(&samples[..], 2, 10);
+        assert_eq!(source.samples(), &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(source.size_hint(), Some(6));
+
+        let mut buffer = [0; 4];
+        assert_eq!(source.write_samples(&mut buffer), 4);
+        assert_eq!(buffer, [1, 2, 3, 4]);
+        assert_eq!(source.write_samples(&mut buffer), 2);
+        assert_eq!(buffer[..2], [5, 6]);
+        assert_eq!(source.write_samples(&mut buffer), 0);
+
+        source.reset();
+        assert_eq!(source.write_samples(&mut buffer), 4);
+        assert_eq!(buffer, [1, 2, 3, 4]);
+
+        let source = SliceSource::new(vec![1, 2, 3], 1, 10);
+        assert_eq!(source.into_samples(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn from_source() {
+        let mut constant = crate::Constant::new(7, 2, 30);
+        constant.len = Some(3000);
+        let source = SliceSource::from_source(constant, 3000).unwrap();
+        assert_eq!(source.channels(), 2);
+        assert_eq!(source.sample_rate(), 30);
+        assert_eq!(source.samples(), &[7; 6000][..]);
+
+        let constant = crate::Constant::new(7, 2, 30);
+        assert!(SliceSource::from_source(constant, 3000).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+//! Conversions between `Duration` and sample counts.
+//!
+//! A frame is a sample for each channel, so a duration of one second has `sample_rate` frames, and
+//! `sample_rate * channels` interleaved samples.
+
+use std::{convert::TryFrom, time::Duration};
+
+/// The number of frames in the given duration, rounded to the nearest frame, saturating at
+/// `u64::MAX`.
+pub(crate) fn duration_to_frames(duration: Duration, sample_rate: u32) -> u64 {
+    // Computed in integers, to not lose precision in long durations. This can't overflow a u128,
+    // even for `Duration::MAX`.
+    let nanos = duration.as_nanos() * sample_rate as u128;
+    u64::try_from((nanos + 500_000_000) / 1_000_000_000).unwrap_or(u64::MAX)
+}
+
+/// The duration of the given number of frames, rounded down to the nanosecond, or zero if the
+/// sample rate is zero.
+pub(crate) fn frames_to_duration(frames: u64, sample_rate: u32) -> Duration {
+    if sample_rate == 0 {
+        return Duration::ZERO;
+    }
+    let nanos = frames as u128 * 1_000_000_000 / sample_rate as u128;
+    Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(duration_to_frames(Duration::from_secs(2), 44100), 88200);
+        assert_eq!(duration_to_frames(Duration::from_millis(1), 44100), 44);
+        assert_eq!(duration_to_frames(Duration::from_micros(15), 48000), 1);
+
+        // a year of samples don't lose precision
+        let year = Duration::from_secs(365 * 24 * 60 * 60);
+        assert_eq!(duration_to_frames(year, 96000), 365 * 24 * 60 * 60 * 96000);
+        let year = year + Duration::from_nanos(10_417);
+        assert_eq!(
+            duration_to_frames(year, 96000),
+            365 * 24 * 60 * 60 * 96000 + 1
+        );
+
+        // saturates instead of wrapping around
+        assert_eq!(duration_to_frames(Duration::MAX, u32::MAX), u64::MAX);
+        let long = Duration::from_secs(u64::MAX / 1000);
+        assert_eq!(duration_to_frames(long, 48000), u64::MAX);
+
+        assert_eq!(frames_to_duration(88200, 44100), Duration::from_secs(2));
+        assert_eq!(frames_to_duration(1, 48000), Duration::from_nanos(20_833));
+        assert_eq!(frames_to_duration(10, 0), Duration::ZERO);
+        let frames = 365 * 24 * 60 * 60 * 96000;
+        assert_eq!(
+            frames_to_duration(frames, 96000),
+            year - Duration::from_nanos(10_417)
+        );
+
+        let rate = crate::SampleRate::from(48000);
+        let duration = Duration::from_millis(10);
+        assert_eq!(rate.samples_for(duration), 480);
+        assert_eq!(rate.interleaved_samples_for(duration, 2), 960);
+        let long = Duration::from_secs(u64::MAX / 48000);
+        assert_eq!(rate.interleaved_samples_for(long, 2), u64::MAX);
+        assert_eq!(rate.duration_of(480), duration);
+    }
+}
@@ -0,0 +1,286 @@
+use std::f32::consts::TAU;
+
+use crate::{clamp_param, SoundSource};
+
+/// The length of each segment that is overlapped, in seconds.
+const SEGMENT: f32 = 0.02;
+
+/// A SoundSource that changes the speed of another source, without changing its pitch.
+///
+/// This uses WSOLA (waveform similarity overlap-add): the source is cut in short overlapping
+/// segments, that are read at the stretched speed, and each segment is moved by a few
+/// milliseconds to best match the waveform of the previous one, before being crossfaded with it.
+/// This works best for music and speech with a clear pitch, and may add some artifacts in large
+/// changes of speed.
+///
+/// The output is not delayed relative to the source, but the source is read ahead by up to 25 ms,
+/// and the output is produced in steps of 10 ms, so a change of rate only takes effect after the
+/// current step is written. [`reset`](SoundSource::reset) discards the samples read ahead and the
+/// partially overlapped segments.
+pub struct TimeStretch<T: SoundSource> {
+    inner: T,
+    rate: f32,
+    /// The length of each segment, in frames. Consecutive segments overlap by half of it.
+    segment: usize,
+    /// How many frames a segment can be moved from its nominal position.
+    tolerance: usize,
+    /// A Hann window with `segment` frames, used to crossfade the segments.
+    window: Vec<f32>,
+    /// The samples read from `inner` that may still be used by the next segments.
+    input: Vec<f32>,
+    /// The number of frames in `input` that were read from `inner`. Past it, `input` is padded
+    /// with silence.
+    input_len: usize,
+    /// If `inner` reached its end.
+    ended: bool,
+    /// The position in `input` where the next segment would start, if not moved, in frames.
+    pos: f64,
+    /// The position in `input` of the frame after the part of the last segment that was already
+    /// outputed, which the next segment should match. `None` if no segment was added yet.
+    natural: Option<usize>,
+    /// The sum of the windowed segments, with `segment` frames.
+    overlap: Vec<f32>,
+    /// The samples that are ready to be written.
+    output: Vec<i16>,
+    /// The number of samples of `output` already written.
+    written: usize,
+    /// A buffer to read the samples of `inner`.
+    buffer: Vec<i16>,
+}
+impl<T: SoundSource> TimeStretch<T> {
+    /// Create a new TimeStretch, with the given rate.
+    ///
+    /// See [`set_rate`](Self::set_rate).
+    pub fn new(inner: T, rate: f32) -> Self {
+        let channels = inner.channels() as usize;
+        let segment = ((SEGMENT * inner.sample_rate() as f32) as usize / 2 * 2).max(4);
+        let window = (0..segment)
+            .map(|i| 0.5 - 0.5 * (TAU * i as f32 / segment as f32).cos())
+            .collect();
+        Self {
+            inner,
+            rate: clamp_param(rate, 0.25, 4.0),
+            segment,
+            tolerance: segment / 4,
+            window,
+            input: Vec::new(),
+            input_len: 0,
+            ended: false,
+            pos: 0.0,
+            natural: None,
+            overlap: vec![0.0; segment * channels],
+            output: Vec::new(),
+            written: 0,
+            buffer: vec![0; 1024 * channels],
+        }
+    }
+
+    /// Set the speed of the playback, clamped to the range `0.25..=4.0`.
+    ///
+    /// A rate of 2.0 plays twice as fast, and 0.5 plays at half the speed, both without changing
+    /// the pitch.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = clamp_param(rate, 0.25, 4.0);
+    }
+
+    /// The speed of the playback.
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    /// Discard the samples read ahead from `inner` and the partially overlapped segments.
+    fn clear_state(&mut self) {
+        self.input.clear();
+        self.input_len = 0;
+        self.ended = false;
+        self.pos = 0.0;
+        self.natural = None;
+        self.overlap.fill(0.0);
+        self.output.clear();
+        self.written = 0;
+    }
+
+    /// Read `inner` until `input` has at least `frames` frames, padding it with silence if
+    /// `inner` ends.
+    fn fill_input(&mut self, frames: usize) {
+        let channels = self.inner.channels() as usize;
+        while !self.ended && self.input_len < frames {
+            let len = self.inner.write_samples(&mut self.buffer);
+            self.input.truncate(self.input_len * channels);
+            let samples = self.buffer[..len].iter().map(cpal::Sample::to_f32);
+            self.input.extend(samples);
+            self.input_len += len / channels;
+            self.ended = len < self.buffer.len();
+        }
+        if self.input.len() < frames * channels {
+            self.input.resize(frames * channels, 0.0);
+        }
+    }
+
+    /// The correlation between the `len` frames of `input` starting at `a` and at `b`.
+    fn correlation(&self, a: usize, b: usize, len: usize) -> f32 {
+        let channels = self.inner.channels() as usize;
+        let a = &self.input[a * channels..(a + len) * channels];
+        let b = &self.input[b * channels..(b + len) * channels];
+        a.iter().zip(b).map(|(a, b)| a * b).sum()
+    }
+
+    /// Add the next segment, filling `output`. Return false if the end of `inner` was reached.
+    fn next_segment(&mut self) -> bool {
+        let channels = self.inner.channels() as usize;
+        let hop = self.segment / 2;
+        let pos = self.pos as usize;
+        let first = pos.saturating_sub(self.tolerance);
+        let last = pos + self.tolerance;
+        let natural = self.natural;
+
+        self.fill_input(last.max(natural.unwrap_or(0)) + self.segment);
+        if self.ended && pos >= self.input_len {
+            return false;
+        }
+
+        // the segment that best continues the waveform of the previous segment
+        let start = match natural {
+            Some(natural) => {
+                let mut best = (f32::MIN, pos);
+                for x in first..=last {
+                    let correlation = self.correlation(natural, x, hop);
+                    if correlation > best.0 {
+                        best = (correlation, x);
+                    }
+                }
+                best.1
+            }
+            None => pos,
+        };
+
+        let input = &self.input[start * channels..(start + self.segment) * channels];
+        let frames = self.overlap.chunks_exact_mut(channels);
+        for (i, (out, frame)) in frames.zip(input.chunks_exact(channels)).enumerate() {
+            // the first segment don't fade in
+            let w = if natural.is_none() && i < hop {
+                1.0
+            } else {
+                self.window[i]
+            };
+            for (out, x) in out.iter_mut().zip(frame) {
+                *out += x * w;
+            }
+        }
+
+        // the first half of the overlap will not receive more segments
+        self.output.clear();
+        let samples = self.overlap[..hop * channels]
+            .iter()
+            .map(<i16 as cpal::Sample>::from);
+        self.output.extend(samples);
+        self.written = 0;
+        self.overlap.copy_within(hop * channels.., 0);
+        self.overlap[hop * channels..].fill(0.0);
+
+        self.pos += hop as f64 * self.rate as f64;
+
+        // discard the input that will not be used by the next segment
+        let discard = (start + hop).min((self.pos as usize).saturating_sub(self.tolerance));
+        self.input.drain(..discard * channels);
+        self.input_len = self.input_len.saturating_sub(discard);
+        self.pos -= discard as f64;
+        self.natural = Some(start + hop - discard);
+
+        true
+    }
+}
+impl<T: SoundSource> SoundSource for TimeStretch<T> {
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        let channels = self.inner.channels() as u64;
+        let len = self.inner.size_hint()? as f64 / self.rate as f64;
+        Some(len as u64 / channels * channels)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.clear_state();
+    }
+
+    fn seek(&mut self, frame: u64) {
+        self.inner.seek((frame as f64 * self.rate as f64) as u64);
+        self.clear_state();
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let mut len = 0;
+        while len < buffer.len() {
+            if self.written == self.output.len() && !self.next_segment() {
+                break;
+            }
+            let output = &self.output[self.written..];
+            let n = output.len().min(buffer.len() - len);
+            buffer[len..len + n].copy_from_slice(&output[..n]);
+            self.written += n;
+            len += n;
+        }
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Constant, SineWave, SoundSource};
+
+    use super::TimeStretch;
+
+    #[test]
+    fn duration() {
+        for rate in [0.5, 1.0, 2.0] {
+            let mut source = Constant::new(1000, 2, 8000);
+            source.len = Some(8000);
+            let mut stretch = TimeStretch::new(source, rate);
+            let mut buffer = vec![0; 4 * 8000 * 2];
+            let len = stretch.write_samples(&mut buffer);
+            let expected = (8000.0 / rate) as usize * 2;
+            assert!(len.abs_diff(expected) <= 160 * 2, "{} {}", len, expected);
+            assert!(buffer[..len - 160 * 2]
+                .iter()
+                .all(|x| (x - 1000).abs() <= 1));
+        }
+    }
+
+    #[test]
+    fn keep_pitch() {
+        // count the number of times the wave crosses zero
+        let crossings = |x: &[i16]| x.windows(2).filter(|x| (x[0] < 0) != (x[1] < 0)).count();
+
+        let mut stretch = TimeStretch::new(SineWave::new(8000, 400.0), 0.5);
+        let mut buffer = vec![0; 8000];
+        stretch.write_samples(&mut buffer);
+        let count = crossings(&buffer);
+        assert!((790..=810).contains(&count), "{}", count);
+
+        stretch.set_rate(2.0);
+        stretch.write_samples(&mut buffer);
+        let count = crossings(&buffer);
+        assert!((790..=810).contains(&count), "{}", count);
+    }
+
+    #[test]
+    fn reset() {
+        let mut stretch = TimeStretch::new(SineWave::new(8000, 400.0), 0.5);
+        let mut first = vec![0; 1000];
+        stretch.write_samples(&mut first);
+        // stop in the middle of a segment
+        stretch.write_samples(&mut [0; 123]);
+
+        stretch.reset();
+        let mut buffer = vec![0; 1000];
+        stretch.write_samples(&mut buffer);
+        assert_eq!(buffer, first);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{
+    clamp_param,
+    lfo::{Lfo, LfoShape, MAX_RATE},
+    Effect, SoundSource,
+};
+
+/// A effect that periodically modulates the amplitude of a buffer.
+///
+/// The amplitude is modulated by a low-frequency oscillator, from 1.0 down to `1.0 - depth`. The
+/// oscillator starts at the maximum amplitude, and restarts on `reset`.
+///
+/// To modulate a single source, see [`Tremolo`].
+pub struct TremoloEffect {
+    lfo: Lfo,
+    depth: f32,
+}
+impl TremoloEffect {
+    /// Create a new TremoloEffect, with the given rate in Hertz and depth, and a sine shape.
+    ///
+    /// See [`set_rate`](Self::set_rate) and [`set_depth`](Self::set_depth).
+    pub fn new(rate: f32, depth: f32) -> Self {
+        Self {
+            lfo: Lfo::new(clamp_param(rate, 0.0, MAX_RATE), LfoShape::Sine),
+            depth: clamp_param(depth, 0.0, 1.0),
+        }
+    }
+
+    /// Set the frequency of the modulation, in Hertz, clamped to the range `0.0..=20.0`.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.lfo.rate = clamp_param(rate, 0.0, MAX_RATE);
+    }
+
+    /// The frequency of the modulation, in Hertz.
+    pub fn rate(&self) -> f32 {
+        self.lfo.rate
+    }
+
+    /// Set how much the amplitude is lowered, clamped to the range `0.0..=1.0`.
+    ///
+    /// A depth of 0.0 leaves the amplitude unchanged, and a depth of 1.0 makes the amplitude go
+    /// down to silence.
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = clamp_param(depth, 0.0, 1.0);
+    }
+
+    /// How much the amplitude is lowered.
+    pub fn depth(&self) -> f32 {
+        self.depth
+    }
+
+    /// Set the shape of the modulation.
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.lfo.shape = shape;
+    }
+
+    /// The shape of the modulation.
+    pub fn shape(&self) -> LfoShape {
+        self.lfo.shape
+    }
+
+    /// The gain of the next frame.
+    fn next_gain(&mut self, sample_rate: u32) -> f32 {
+        1.0 - self.depth * self.lfo.next(sample_rate)
+    }
+}
+impl Effect for TremoloEffect {
+    fn process(&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32) {
+        for frame in buffer.chunks_exact_mut(channels as usize) {
+            let gain = self.next_gain(sample_rate);
+            for sample in frame {
+                *sample *= gain;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.lfo.reset();
+    }
+}
+
+/// A SoundSource that periodically modulates the amplitude of another source.
+///
+/// This applies a [`TremoloEffect`] to the samples of the inner source.
+pub struct Tremolo<T: SoundSource> {
+    inner: T,
+    effect: TremoloEffect,
+}
+impl<T: SoundSource> Tremolo<T> {
+    /// Create a new Tremolo, with the given rate in Hertz and depth, and a sine shape.
+    ///
+    /// See [`set_rate`](Self::set_rate) and [`set_depth`](Self::set_depth).
+    pub fn new(inner: T, rate: f32, depth: f32) -> Self {
+        let effect = TremoloEffect::new(rate, depth);
+        Self { inner, effect }
+    }
+
+    /// Set the frequency of the modulation, in Hertz. See [`TremoloEffect::set_rate`].
+    pub fn set_rate(&mut self, rate: f32) {
+        self.effect.set_rate(rate);
+    }
+
+    /// The frequency of the modulation, in Hertz.
+    pub fn rate(&self) -> f32 {
+        self.effect.rate()
+    }
+
+    /// Set how much the amplitude is lowered. See [`TremoloEffect::set_depth`].
+    pub fn set_depth(&mut self, depth: f32) {
+        self.effect.set_depth(depth);
+    }
+
+    /// How much the amplitude is lowered.
+    pub fn depth(&self) -> f32 {
+        self.effect.depth()
+    }
+
+    /// Set the shape of the modulation.
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.effect.set_shape(shape);
+    }
+
+    /// The shape of the modulation.
+    pub fn shape(&self) -> LfoShape {
+        self.effect.shape()
+    }
+}
+impl<T: SoundSource> SoundSource for Tremolo<T> {
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        self.inner.size_hint()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.effect.reset();
+    }
+
+    fn seek(&mut self, frame: u64) {
+        // the modulation continues from where it was
+        self.inner.seek(frame);
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let len = self.inner.write_samples(buffer);
+        let sample_rate = self.inner.sample_rate();
+        for frame in buffer[..len].chunks_exact_mut(self.inner.channels() as usize) {
+            let gain = self.effect.next_gain(sample_rate);
+            for sample in frame {
+                *sample = (*sample as f32 * gain) as i16;
+            }
+        }
+        len
+    }
+
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        let len = self.inner.write_samples_f32(buffer);
+        let (channels, sample_rate) = (self.inner.channels(), self.inner.sample_rate());
+        self.effect
+            .process(&mut buffer[..len], channels, sample_rate);
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Constant, Effect, LfoShape, SliceSource, SoundSource};
+
+    use super::{Tremolo, TremoloEffect};
+
+    #[test]
+    fn tremolo() {
+        let mut tremolo = Tremolo::new(Constant::new(100, 2, 4), 1.0, 0.5);
+        tremolo.set_shape(LfoShape::Triangle);
+        let mut buffer = [0; 10];
+        tremolo.write_samples(&mut buffer);
+        assert_eq!(buffer, [100, 100, 75, 75, 50, 50, 75, 75, 100, 100]);
+
+        tremolo.reset();
+        let mut buffer = [0.0; 4];
+        tremolo.write_samples_f32(&mut buffer);
+        let expected = 100.0 / i16::MAX as f32;
+        assert!((buffer[0] - expected).abs() < 1e-3);
+        assert!((buffer[2] - expected * 0.75).abs() < 1e-3);
+    }
+
+    #[test]
+    fn seek() {
+        let samples: Vec<i16> = (0..8).map(|x| x * 10).collect();
+        let mut tremolo = Tremolo::new(SliceSource::new(samples, 1, 4), 1.0, 0.0);
+        tremolo.seek(5);
+        let mut buffer = [0; 4];
+        assert_eq!(tremolo.write_samples(&mut buffer), 3);
+        assert_eq!(buffer[..3], [50, 60, 70]);
+    }
+
+    #[test]
+    fn negative_rate() {
+        let mut tremolo = Tremolo::new(Constant::new(100, 1, 4), -1.0, 0.5);
+        assert_eq!(tremolo.rate(), 0.0);
+        tremolo.set_rate(f32::NAN);
+        assert_eq!(tremolo.rate(), 0.0);
+        tremolo.set_rate(-1.0);
+        assert_eq!(tremolo.rate(), 0.0);
+
+        let mut buffer = [0; 8];
+        tremolo.write_samples(&mut buffer);
+        assert!(buffer.iter().all(|&x| x <= 100));
+    }
+
+    #[test]
+    fn tremolo_effect() {
+        let mut tremolo = TremoloEffect::new(1.0, 0.5);
+        tremolo.set_shape(LfoShape::Triangle);
+        let mut buffer = [1.0; 10];
+        tremolo.process(&mut buffer, 2, 4);
+        assert_eq!(
+            buffer,
+            [1.0, 1.0, 0.75, 0.75, 0.5, 0.5, 0.75, 0.75, 1.0, 1.0]
+        );
+
+        tremolo.reset();
+        let mut buffer = [1.0; 4];
+        tremolo.process(&mut buffer, 1, 4);
+        assert_eq!(buffer, [1.0, 0.75, 0.5, 0.75]);
+    }
+}
@@ -16,9 +16,11 @@ use core::fmt::{self, Debug};
 /// clone a shared `Unshared<T>` reference which would result in accessing the same inner value
 /// concurrently.
 pub struct Unshared<T> {
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
     inner: T,
 }
 
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
 impl<T> Unshared<T> {
     pub fn new(inner: T) -> Self {
         Unshared { inner }
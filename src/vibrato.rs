@@ -0,0 +1,285 @@
+use crate::{
+    clamp_param,
+    lfo::{Lfo, LfoShape, MAX_RATE},
+    Effect, SoundSource,
+};
+
+/// The maximum depth of a [`VibratoEffect`], in seconds.
+const MAX_DEPTH: f32 = 0.05;
+
+/// A effect that periodically modulates the pitch of a buffer.
+///
+/// The pitch is modulated by reading the buffer through a delay line, whose delay is changed by a
+/// low-frequency oscillator from 0 up to `depth` seconds. Samples between frames are read using
+/// linear interpolation. The oscillator starts at zero delay, and restarts on `reset`.
+///
+/// To modulate a single source, see [`Vibrato`].
+pub struct VibratoEffect {
+    lfo: Lfo,
+    depth: f32,
+    /// The last frames processed, in a ring buffer.
+    delay_line: Vec<f32>,
+    /// The index of the frame in `delay_line` where the next frame will be written.
+    write_pos: usize,
+}
+impl VibratoEffect {
+    /// Create a new VibratoEffect, with the given rate in Hertz and depth in seconds, and a sine
+    /// shape.
+    ///
+    /// See [`set_rate`](Self::set_rate) and [`set_depth`](Self::set_depth).
+    pub fn new(rate: f32, depth: f32) -> Self {
+        Self {
+            lfo: Lfo::new(clamp_param(rate, 0.0, MAX_RATE), LfoShape::Sine),
+            depth: clamp_param(depth, 0.0, MAX_DEPTH),
+            delay_line: Vec::new(),
+            write_pos: 0,
+        }
+    }
+
+    /// Set the frequency of the modulation, in Hertz, clamped to the range `0.0..=20.0`.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.lfo.rate = clamp_param(rate, 0.0, MAX_RATE);
+    }
+
+    /// The frequency of the modulation, in Hertz.
+    pub fn rate(&self) -> f32 {
+        self.lfo.rate
+    }
+
+    /// Set the maximum delay of the modulation, in seconds, clamped to the range `0.0..=0.05`.
+    ///
+    /// The larger the depth, the larger the change in pitch. A depth of a few milliseconds is
+    /// usually enough.
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = clamp_param(depth, 0.0, MAX_DEPTH);
+    }
+
+    /// The maximum delay of the modulation, in seconds.
+    pub fn depth(&self) -> f32 {
+        self.depth
+    }
+
+    /// Set the shape of the modulation.
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.lfo.shape = shape;
+    }
+
+    /// The shape of the modulation.
+    pub fn shape(&self) -> LfoShape {
+        self.lfo.shape
+    }
+
+    /// Resize the delay line to the given format, clearing it if its length changes.
+    fn prepare(&mut self, channels: u16, sample_rate: u32) {
+        // two extra frames, for the current frame and the interpolation
+        let frames = (MAX_DEPTH * sample_rate as f32).ceil() as usize + 2;
+        let len = frames * channels as usize;
+        if self.delay_line.len() != len {
+            self.delay_line = vec![0.0; len];
+            self.write_pos = 0;
+        }
+    }
+
+    fn clear_delay_line(&mut self) {
+        self.delay_line.fill(0.0);
+    }
+}
+impl Effect for VibratoEffect {
+    fn process(&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32) {
+        self.prepare(channels, sample_rate);
+        let channels = channels as usize;
+        let frames = self.delay_line.len() / channels;
+        let depth = self.depth * sample_rate as f32;
+        for frame in buffer.chunks_exact_mut(channels) {
+            let w = self.write_pos * channels;
+            self.delay_line[w..w + channels].copy_from_slice(frame);
+
+            // the float position of the delayed frame in the delay line
+            let delay = depth * self.lfo.next(sample_rate);
+            let mut j = self.write_pos as f32 - delay;
+            if j < 0.0 {
+                j += frames as f32;
+            }
+            let t = j.fract();
+            let curr = j as usize * channels;
+            let next = (j as usize + 1) % frames * channels;
+
+            let curr = &self.delay_line[curr..curr + channels];
+            let next = &self.delay_line[next..next + channels];
+            for ((out, curr), next) in frame.iter_mut().zip(curr).zip(next) {
+                // interpolate by t, curr and next sample
+                *out = curr * (1.0 - t) + next * t;
+            }
+
+            self.write_pos = (self.write_pos + 1) % frames;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.lfo.reset();
+        self.clear_delay_line();
+        self.write_pos = 0;
+    }
+}
+
+/// A SoundSource that periodically modulates the pitch of another source.
+///
+/// This applies a [`VibratoEffect`] to the samples of the inner source.
+pub struct Vibrato<T: SoundSource> {
+    inner: T,
+    effect: VibratoEffect,
+    /// A buffer to process the samples of `inner` in `f32`.
+    buffer: Vec<f32>,
+}
+impl<T: SoundSource> Vibrato<T> {
+    /// Create a new Vibrato, with the given rate in Hertz and depth in seconds, and a sine shape.
+    ///
+    /// See [`set_rate`](Self::set_rate) and [`set_depth`](Self::set_depth).
+    pub fn new(inner: T, rate: f32, depth: f32) -> Self {
+        Self {
+            inner,
+            effect: VibratoEffect::new(rate, depth),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Set the frequency of the modulation, in Hertz. See [`VibratoEffect::set_rate`].
+    pub fn set_rate(&mut self, rate: f32) {
+        self.effect.set_rate(rate);
+    }
+
+    /// The frequency of the modulation, in Hertz.
+    pub fn rate(&self) -> f32 {
+        self.effect.rate()
+    }
+
+    /// Set the maximum delay of the modulation, in seconds. See [`VibratoEffect::set_depth`].
+    pub fn set_depth(&mut self, depth: f32) {
+        self.effect.set_depth(depth);
+    }
+
+    /// The maximum delay of the modulation, in seconds.
+    pub fn depth(&self) -> f32 {
+        self.effect.depth()
+    }
+
+    /// Set the shape of the modulation.
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.effect.set_shape(shape);
+    }
+
+    /// The shape of the modulation.
+    pub fn shape(&self) -> LfoShape {
+        self.effect.shape()
+    }
+}
+impl<T: SoundSource> SoundSource for Vibrato<T> {
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn size_hint(&self) -> Option<u64> {
+        self.inner.size_hint()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.effect.reset();
+    }
+
+    fn seek(&mut self, frame: u64) {
+        // the modulation continues from where it was, but the delayed frames are discarded
+        self.inner.seek(frame);
+        self.effect.clear_delay_line();
+    }
+
+    fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let len = self.inner.write_samples(buffer);
+        let (channels, sample_rate) = (self.inner.channels(), self.inner.sample_rate());
+        // Only allocates if the buffer is larger than any previous one. The samples are kept in
+        // the scale of i16, as the effect is linear.
+        let mut samples = std::mem::take(&mut self.buffer);
+        samples.clear();
+        samples.extend(buffer[..len].iter().map(|&x| x as f32));
+        self.effect.process(&mut samples, channels, sample_rate);
+        for (out, x) in buffer.iter_mut().zip(&samples) {
+            *out = *x as i16;
+        }
+        self.buffer = samples;
+        len
+    }
+
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        let len = self.inner.write_samples_f32(buffer);
+        let (channels, sample_rate) = (self.inner.channels(), self.inner.sample_rate());
+        self.effect
+            .process(&mut buffer[..len], channels, sample_rate);
+        len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Effect, LfoShape, SliceSource, SoundSource};
+
+    use super::{Vibrato, VibratoEffect};
+
+    #[test]
+    fn vibrato() {
+        // a ramp, so the delay can be read from the output
+        let samples: Vec<i16> = (0..16).flat_map(|x| [x * 10, -x * 10]).collect();
+        let mut vibrato = Vibrato::new(SliceSource::new(samples, 2, 100), 12.5, 0.02);
+        vibrato.set_shape(LfoShape::Triangle);
+
+        // the delay goes from 0 to 2 frames, and back to 0, in each 8 frames
+        let mut buffer = [0; 32];
+        assert_eq!(vibrato.write_samples(&mut buffer), 32);
+        let left: Vec<i16> = buffer.iter().step_by(2).copied().collect();
+        assert_eq!(
+            left,
+            [0, 5, 10, 15, 20, 35, 50, 65, 80, 85, 90, 95, 100, 115, 130, 145]
+        );
+        assert!(buffer.chunks(2).all(|x| x[0] == -x[1]));
+    }
+
+    #[test]
+    fn negative_rate() {
+        let samples: Vec<i16> = (0..64).collect();
+        let mut vibrato = Vibrato::new(SliceSource::new(samples, 1, 100), 12.5, 0.02);
+        vibrato.set_shape(LfoShape::Triangle);
+        vibrato.set_rate(-12.5);
+        assert_eq!(vibrato.rate(), 0.0);
+
+        let mut buffer = [0; 64];
+        assert_eq!(vibrato.write_samples(&mut buffer), 64);
+    }
+
+    #[test]
+    fn vibrato_effect() {
+        let mut vibrato = VibratoEffect::new(12.5, 0.02);
+        vibrato.set_shape(LfoShape::Triangle);
+
+        // the same ramp as in the source test, but split across buffers
+        let mut buffer: Vec<f32> = (0..16).map(|x| x as f32 / 100.0).collect();
+        vibrato.process(&mut buffer[..5], 1, 100);
+        vibrato.process(&mut buffer[5..], 1, 100);
+        let expected = [
+            0, 5, 10, 15, 20, 35, 50, 65, 80, 85, 90, 95, 100, 115, 130, 145,
+        ];
+        for (x, e) in buffer.iter().zip(expected) {
+            assert!((x * 1000.0 - e as f32).abs() < 1e-3, "{:?}", buffer);
+        }
+
+        // reset restarts the modulation
+        vibrato.reset();
+        let mut buffer: Vec<f32> = (0..8).map(|x| x as f32 / 100.0).collect();
+        vibrato.process(&mut buffer, 1, 100);
+        for (x, e) in buffer.iter().zip(expected) {
+            assert!((x * 1000.0 - e as f32).abs() < 1e-3, "{:?}", buffer);
+        }
+    }
+}
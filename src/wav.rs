@@ -1,9 +1,15 @@
 use hound::WavReader;
-use std::io::{Read, Seek};
+use std::{
+    io::{self, Cursor, Read, Seek},
+    time::Duration,
+};
 
-use crate::SoundSource;
+use crate::{ForwardReader, SampleRate, SoundSource};
 
 /// A SourceSource, from wav encoded sound data.
+///
+/// Supports 8, 16, 24 and 32-bit integer samples, and 32-bit float samples, with any number of
+/// channels, including files using `WAVE_FORMAT_EXTENSIBLE`.
 pub struct WavDecoder<T: Seek + Read + Send + 'static> {
     reader: WavReader<T>,
     channels: u16,
@@ -20,17 +26,23 @@ impl<T: Seek + Read + Send + 'static> WavDecoder<T> {
         })
     }
 
+    /// The format of the wav data, including its number of channels, sample rate, bits per
+    /// sample and sample format.
+    pub fn spec(&self) -> hound::WavSpec {
+        self.reader.spec()
+    }
+
     #[allow(clippy::needless_range_loop)]
-    fn inner_write_sample<S: hound::Sample>(
+    fn inner_write_sample<S: hound::Sample, O>(
         &mut self,
-        buffer: &mut [i16],
-        to_i16: impl Fn(S) -> i16,
+        buffer: &mut [O],
+        convert: impl Fn(S) -> O,
     ) -> usize {
         let mut samples = self.reader.samples::<S>();
         for i in 0..buffer.len() {
             if let Some(sample) = samples.next() {
                 buffer[i] = match sample {
-                    Ok(x) => to_i16(x),
+                    Ok(x) => convert(x),
                     Err(err) => {
                         log::error!("error while decoding wav: {}", err);
                         // Returning the current number of decoded samples before the error,
@@ -48,9 +60,46 @@ impl<T: Seek + Read + Send + 'static> WavDecoder<T> {
         buffer.len()
     }
 }
+impl<B: AsRef<[u8]> + Send + 'static> WavDecoder<Cursor<B>> {
+    /// Create a new WavDecoder from .wav data in memory.
+    ///
+    /// This accepts any bytes, like the `&'static [u8; N]` returned by `include_bytes!`, a
+    /// `Vec<u8>` or a `Arc<[u8]>`, without needing to wrap them in a `Cursor`.
+    pub fn from_bytes(bytes: B) -> Result<Self, hound::Error> {
+        Self::new(Cursor::new(bytes))
+    }
+}
+impl<R: Read + Send + 'static> WavDecoder<ForwardReader<R>> {
+    /// Create a new WavDecoder from a `Read`-only source, like a socket or a pipe.
+    ///
+    /// The data is decoded as it is read, without buffering it all. Because the source cannot
+    /// seek back, the decoder cannot be reset: on `reset` an error is logged and the decoder keeps
+    /// its position. Use [`with_reopen`](Self::with_reopen) for sources that can be opened again.
+    pub fn from_reader(reader: R) -> Result<Self, hound::Error> {
+        Self::new(ForwardReader::new(reader))
+    }
+
+    /// Create a new WavDecoder from a `Read`-only source, that is opened by calling `reopen`.
+    ///
+    /// On `reset`, `reopen` is called again, and the stream is decoded from the start.
+    pub fn with_reopen(
+        reopen: impl FnMut() -> io::Result<R> + Send + 'static,
+    ) -> Result<Self, hound::Error> {
+        Self::new(ForwardReader::with_reopen(reopen)?)
+    }
+}
 impl<T: Seek + Read + Send + 'static> SoundSource for WavDecoder<T> {
     fn reset(&mut self) {
-        self.reader.seek(0).unwrap();
+        if let Err(err) = self.reader.seek(0) {
+            log::error!("error while seeking wav: {}", err);
+        }
+    }
+
+    fn seek(&mut self, frame: u64) {
+        let frame = frame.min(self.reader.duration() as u64) as u32;
+        if let Err(err) = self.reader.seek(frame) {
+            log::error!("error while seeking wav: {}", err);
+        }
     }
 
     fn channels(&self) -> u16 {
@@ -61,6 +110,15 @@ impl<T: Seek + Read + Send + 'static> SoundSource for WavDecoder<T> {
         self.sample_rate
     }
 
+    fn size_hint(&self) -> Option<u64> {
+        Some(self.reader.len() as u64)
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        let frames = self.reader.duration() as u64;
+        Some(SampleRate(self.sample_rate).duration_of(frames))
+    }
+
     fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
         let sample_format = self.reader.spec().sample_format;
         let bits_per_sample = self.reader.spec().bits_per_sample;
@@ -72,22 +130,31 @@ impl<T: Seek + Read + Send + 'static> SoundSource for WavDecoder<T> {
                 self.inner_write_sample(buffer, |x: i32| (x >> (bits_per_sample - 16)) as i16)
             }
             // 16bit
-            (hound::SampleFormat::Int, x) if x == 16 => self.inner_write_sample(buffer, |x: i16| x),
+            (hound::SampleFormat::Int, 16) => self.inner_write_sample(buffer, |x: i16| x),
             // 8bit
             (hound::SampleFormat::Int, _) => {
                 self.inner_write_sample(buffer, |x: i8| (x as i16) << 8)
             }
         }
     }
-}
 
-fn f32_to_i16(mut x: f32) -> i16 {
-    if x > 1.0 {
-        x = 1.0
-    }
-    if x < -1.0 {
-        x = -1.0
+    fn write_samples_f32(&mut self, buffer: &mut [f32]) -> usize {
+        let sample_format = self.reader.spec().sample_format;
+        let bits_per_sample = self.reader.spec().bits_per_sample;
+        match (sample_format, bits_per_sample) {
+            (hound::SampleFormat::Float, _) => self.inner_write_sample(buffer, |x: f32| x),
+            // 24bit or 32bit, which would lose precision if converted to i16
+            (hound::SampleFormat::Int, x) if x > 16 => {
+                let scale = 1.0 / (1u32 << (bits_per_sample - 1)) as f32;
+                self.inner_write_sample(buffer, |x: i32| x as f32 * scale)
+            }
+            _ => crate::write_samples_f32_from_i16(self, buffer),
+        }
     }
+}
+
+fn f32_to_i16(x: f32) -> i16 {
+    let x = x.clamp(-1.0, 1.0);
     if x >= 0.0 {
         (x * i16::MAX as f32) as i16
     } else {
@@ -0,0 +1,33 @@
+//! Check that `decode` picks the right decoder from the hint or the content of the data.
+#![cfg(all(feature = "ogg", feature = "wav"))]
+
+use std::io::Cursor;
+
+use audio_engine::{decode, DecodeError, FormatHint};
+
+const OGG: &[u8] = include_bytes!("../examples/pipe.ogg");
+const WAV: &[u8] = include_bytes!("../examples/0.wav");
+
+#[test]
+fn sniff() {
+    let ogg = decode(Cursor::new(OGG), FormatHint::Unknown).unwrap();
+    let wav = decode(Cursor::new(WAV), FormatHint::Unknown).unwrap();
+    assert!(ogg.duration().is_some());
+    assert!(wav.duration().is_some());
+
+    let err = decode(Cursor::new(&[0u8; 32][..]), FormatHint::Unknown);
+    assert!(matches!(err, Err(DecodeError::UnknownFormat)));
+}
+
+#[test]
+fn hint() {
+    decode(Cursor::new(OGG), FormatHint::Extension("ogg")).unwrap();
+    decode(Cursor::new(WAV), FormatHint::MimeType("audio/wav")).unwrap();
+
+    // a unknown extension falls back to the content
+    decode(Cursor::new(WAV), FormatHint::Extension("bin")).unwrap();
+
+    // a wrong hint is trusted
+    let err = decode(Cursor::new(WAV), FormatHint::Extension("ogg"));
+    assert!(matches!(err, Err(DecodeError::Ogg(_))));
+}
@@ -0,0 +1,214 @@
+//! Check time-based features through a headless engine, advancing it by a exact number of samples.
+
+use std::time::Duration;
+
+use audio_engine::{
+    AudioEngine, AudioEngineError, Constant, ResamplePolicy, Silence, SliceSource, SoundSource,
+};
+
+#[test]
+fn play_at() {
+    let (engine, mut mixer) = AudioEngine::headless(1, 1000);
+    let mut source = Constant::new(1000, 1, 1000);
+    source.len = Some(5);
+    let mut sound = engine.new_sound(source).unwrap();
+    sound.play_at(Duration::from_millis(3));
+
+    let mut buffer = [0; 10];
+    mixer.write_samples(&mut buffer);
+    assert_eq!(buffer, [0, 0, 0, 1000, 1000, 1000, 1000, 1000, 0, 0]);
+}
+
+#[test]
+fn fade() {
+    let (engine, mut mixer) = AudioEngine::headless(1, 1000);
+    let mut sound = engine.new_sound(Constant::new(1000, 1, 1000)).unwrap();
+    sound.set_volume(0.0);
+    sound.automate_volume(&[(Duration::from_millis(10), 1.0)]);
+    sound.play();
+
+    let mut buffer = [0; 12];
+    mixer.write_samples(&mut buffer);
+    for (i, x) in buffer.iter().enumerate() {
+        assert!((x - i.min(10) as i16 * 100).abs() <= 1, "{:?}", buffer);
+    }
+    assert_eq!(sound.volume(), 1.0);
+}
+
+#[test]
+fn looping() {
+    let (engine, mut mixer) = AudioEngine::headless(1, 1000);
+    let source = SliceSource::new(vec![1, 2, 3], 1, 1000);
+    let mut sound = engine.new_sound(source).unwrap();
+    sound.set_loop(true);
+    sound.play();
+
+    let mut buffer = [0; 7];
+    mixer.write_samples(&mut buffer);
+    assert_eq!(buffer, [1, 2, 3, 1, 2, 3, 1]);
+
+    sound.set_loop(false);
+    mixer.write_samples(&mut buffer);
+    assert_eq!(buffer, [2, 3, 0, 0, 0, 0, 0]);
+    assert_eq!(engine.playing_count(), 0);
+}
+
+#[test]
+fn loop_region() {
+    let (engine, mut mixer) = AudioEngine::headless(1, 1000);
+    let source = SliceSource::new(vec![1, 2, 3, 4], 1, 1000);
+    let mut sound = engine.new_sound(source).unwrap();
+    sound.set_loop(true);
+    sound.play();
+
+    // the region is empty once converted to frames
+    let region = sound.set_loop_region(Duration::from_micros(1100), Duration::from_micros(1200));
+    assert!(matches!(region, Err(AudioEngineError::InvalidLoopRegion)));
+
+    let region = sound.set_loop_region(Duration::from_millis(1), Duration::from_millis(3));
+    assert!(region.is_ok());
+    let mut buffer = [0; 7];
+    mixer.write_samples(&mut buffer);
+    assert_eq!(buffer, [1, 2, 3, 2, 3, 2, 3]);
+}
+
+#[test]
+fn resample_policy() {
+    let (engine, _mixer) = AudioEngine::builder()
+        .sample_rate(1000)
+        .resample_policy(ResamplePolicy::Deny)
+        .build_headless();
+    assert!(engine.new_sound(Constant::new(0, 1, 1000)).is_ok());
+    let err = engine.new_sound(Constant::new(0, 1, 500)).err().unwrap();
+    assert!(matches!(
+        err,
+        AudioEngineError::SampleRateMismatch {
+            source: 500,
+            output: 1000
+        }
+    ));
+}
+
+#[test]
+fn invalid_source() {
+    let (engine, _mixer) = AudioEngine::headless(1, 1000);
+    let err = engine.new_sound(Constant::new(0, 0, 1000)).err().unwrap();
+    assert!(matches!(err, AudioEngineError::ZeroChannels));
+    let err = engine.new_sound(Constant::new(0, 1, 0)).err().unwrap();
+    assert!(matches!(err, AudioEngineError::ZeroSampleRate));
+    assert!(engine
+        .new_sound_boxed(Box::new(Silence::new(0, 0)))
+        .is_err());
+}
+
+#[test]
+fn replace_source() {
+    let (engine, mut mixer) = AudioEngine::builder()
+        .channels(1)
+        .sample_rate(1000)
+        .resample_policy(ResamplePolicy::Deny)
+        .build_headless();
+    let mut sound = engine.new_sound(Constant::new(1000, 1, 1000)).unwrap();
+    sound.set_volume(0.5);
+    sound.set_loop(true);
+    sound.play();
+
+    let mut buffer = [0; 4];
+    mixer.write_samples(&mut buffer);
+    assert_eq!(buffer, [500; 4]);
+
+    // the new source is converted to mono, and keeps the volume and the loop
+    let source = SliceSource::new(vec![2000, 2000, 4000, 4000], 2, 1000);
+    sound.replace_source(source).unwrap();
+    mixer.write_samples(&mut buffer);
+    assert_eq!(buffer, [1000, 2000, 1000, 2000]);
+
+    assert!(sound.replace_source(Constant::new(0, 1, 500)).is_err());
+}
+
+#[test]
+fn prepare() {
+    let (engine, mut mixer) = AudioEngine::headless(1, 1000);
+    let mut source = Constant::new(1000, 1, 1000);
+    source.len = Some(200);
+    let mut prepared = engine.prepare(source).unwrap();
+    while !prepared.is_ready() {
+        std::thread::yield_now();
+    }
+    let _sound = prepared.play().unwrap();
+
+    let mut buffer = vec![0; 300];
+    mixer.write_samples(&mut buffer);
+    assert!(buffer[..200].iter().all(|&x| x == 1000));
+    assert!(buffer[200..].iter().all(|&x| x == 0));
+}
+
+#[test]
+fn clipping() {
+    let (engine, mut mixer) = AudioEngine::headless(1, 1000);
+    let mut sound = engine.new_sound(Constant::new(20000, 1, 1000)).unwrap();
+    sound.play();
+
+    let mut buffer = [0; 4];
+    mixer.write_samples(&mut buffer);
+    assert!(!engine.clipped_since_last_check());
+
+    sound.set_volume(2.0);
+    mixer.write_samples(&mut buffer);
+    assert!(engine.clipped_since_last_check());
+    assert!(!engine.clipped_since_last_check());
+
+    let mut buffer = [0.0; 4];
+    mixer.write_samples_f32(&mut buffer);
+    assert!(engine.clipped_since_last_check());
+}
+
+#[test]
+fn duration_and_position() {
+    let (engine, mut mixer) = AudioEngine::headless(2, 1000);
+    // resampled and converted to stereo
+    let source = SliceSource::new(vec![0; 500], 1, 500);
+    let mut sound = engine.new_sound(source).unwrap();
+    assert_eq!(sound.duration(), Some(Duration::from_secs(1)));
+
+    sound.play();
+    let mut buffer = [0; 500];
+    mixer.write_samples(&mut buffer);
+    assert_eq!(sound.position(), Duration::from_millis(250));
+
+    let endless = engine.new_sound(Constant::new(0, 1, 1000)).unwrap();
+    assert_eq!(endless.duration(), None);
+}
+
+#[test]
+#[cfg(feature = "wav")]
+fn recording() {
+    let path =
+        std::env::temp_dir().join(format!("audio-engine-recording-{}.wav", std::process::id()));
+    let (engine, mut mixer) = AudioEngine::headless(2, 1000);
+    let mut sound = engine.new_sound(Constant::new(1000, 2, 1000)).unwrap();
+    sound.play();
+
+    let mut buffer = [0; 8];
+    mixer.write_samples(&mut buffer);
+    engine.start_recording(&path).unwrap();
+    mixer.write_samples(&mut buffer);
+    sound.set_volume(0.5);
+    mixer.write_samples_f32(&mut [0.0; 4]);
+    engine.stop_recording().unwrap();
+    mixer.write_samples(&mut buffer);
+
+    let reader = hound::WavReader::open(&path).unwrap();
+    assert_eq!(reader.spec().channels, 2);
+    assert_eq!(reader.spec().sample_rate, 1000);
+    let samples = reader.into_samples::<i16>().collect::<Result<Vec<_>, _>>();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        samples.unwrap(),
+        [1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 500, 500, 500, 500]
+    );
+
+    // a file that can't be created is reported
+    let err = engine.start_recording(path.join("recording.wav")).err();
+    assert!(matches!(err, Some(AudioEngineError::Recording(_))));
+}
@@ -0,0 +1,208 @@
+//! Check that mixing samples don't allocate or free memory, after the first buffer.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use audio_engine::{
+    converter::{ChannelConverter, SampleRateConverter},
+    AudioEngine, Constant, Mixer, SampleRate, SineWave, SoundSource,
+};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static DEALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|x| x.set(x.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        DEALLOCATIONS.with(|x| x.set(x.get() + 1));
+        System.dealloc(ptr, layout)
+    }
+
+    /// Counted as both a allocation and a deallocation.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|x| x.set(x.get() + 1));
+        DEALLOCATIONS.with(|x| x.set(x.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Call `f`, and return the number of allocations and deallocations it made in this thread.
+fn count(f: impl FnOnce()) -> (usize, usize) {
+    let before = (
+        ALLOCATIONS.with(|x| x.get()),
+        DEALLOCATIONS.with(|x| x.get()),
+    );
+    f();
+    (
+        ALLOCATIONS.with(|x| x.get()) - before.0,
+        DEALLOCATIONS.with(|x| x.get()) - before.1,
+    )
+}
+
+#[test]
+fn mixer_dont_allocate() {
+    let mut mixer = Mixer::new(2, SampleRate(48000));
+
+    let sources: Vec<Box<dyn SoundSource + Send>> = vec![
+        Box::new(SineWave::new(48000, 440.0)),
+        Box::new(ChannelConverter::new(SineWave::new(48000, 220.0), 2)),
+        Box::new(ChannelConverter::new(
+            SampleRateConverter::new(SineWave::new(44100, 330.0), 48000),
+            2,
+        )),
+    ];
+    for source in sources {
+        let id = mixer.add_sound((), source);
+        mixer.set_volume(id, 0.5);
+        mixer.set_loop(id, true);
+        mixer.play(id);
+    }
+
+    #[cfg(feature = "wav")]
+    {
+        let wav = audio_engine::WavDecoder::new(std::io::Cursor::new(
+            &include_bytes!("../examples/0.wav")[..],
+        ))
+        .unwrap();
+        let id = mixer.add_sound((), Box::new(wav));
+        mixer.set_loop(id, true);
+        mixer.play(id);
+    }
+
+    let mut buffer = vec![0; 1024];
+
+    let mut buffer_f32 = vec![0.0; 1024];
+
+    // warm-up
+    mixer.write_samples(&mut buffer);
+    mixer.write_samples_f32(&mut buffer_f32);
+
+    let counts = count(|| {
+        for _ in 0..100 {
+            mixer.write_samples(&mut buffer);
+            mixer.write_samples_f32(&mut buffer_f32);
+        }
+    });
+    assert_eq!(counts, (0, 0));
+}
+
+#[test]
+fn sound_end_dont_allocate() {
+    let mut mixer = Mixer::new(2, SampleRate(48000));
+    let ended = Arc::new(AtomicUsize::new(0));
+
+    // more sounds than the initial capacity of the mixer, ending in the same buffer. They are not
+    // removed, because without a handle the mixer drops removed sounds itself.
+    for _ in 0..100 {
+        let mut source = Constant::new(1000, 2, 48000);
+        source.len = Some(48000);
+        let id = mixer.add_sound((), Box::new(source));
+        let ended = ended.clone();
+        mixer.set_on_end(id, move || {
+            ended.fetch_add(1, Ordering::Relaxed);
+        });
+        mixer.mark_to_remove(id, false);
+        mixer.play(id);
+    }
+
+    let mut buffer = vec![0; 1024];
+    mixer.write_samples(&mut buffer);
+
+    let counts = count(|| {
+        for _ in 0..100 {
+            mixer.write_samples(&mut buffer);
+        }
+    });
+    assert_eq!(counts, (0, 0));
+    assert_eq!(ended.load(Ordering::Relaxed), 100);
+}
+
+/// lewton allocates while decoding each packet, so this only checks that the memory it allocates
+/// is freed, and that the decoded samples don't keep growing.
+#[cfg(feature = "ogg")]
+#[test]
+fn ogg_dont_leak() {
+    let mut mixer = Mixer::new(2, SampleRate(48000));
+    let ogg =
+        audio_engine::OggDecoder::from_bytes(&include_bytes!("../examples/pipe.ogg")[..]).unwrap();
+    let id = mixer.add_sound((), Box::new(ogg));
+    mixer.set_loop(id, true);
+    mixer.play(id);
+
+    let mut buffer = vec![0; 1024];
+    mixer.write_samples(&mut buffer);
+
+    let (allocations, deallocations) = count(|| {
+        for _ in 0..100 {
+            mixer.write_samples(&mut buffer);
+        }
+    });
+    assert_eq!(allocations, deallocations);
+}
+
+#[test]
+fn engine_dont_allocate() {
+    let (engine, mut mixer) = AudioEngine::headless(2, 48000);
+    let mut buffer = vec![0; 1024];
+
+    let ended = Arc::new(AtomicUsize::new(0));
+    let mut sounds = Vec::new();
+    for _ in 0..10 {
+        let mut source = Constant::new(1000, 1, 44100);
+        source.len = Some(44100);
+        let mut sound = engine.new_sound(source).unwrap();
+        let ended = ended.clone();
+        sound.on_end(move || {
+            ended.fetch_add(1, Ordering::Relaxed);
+        });
+        sounds.push(sound);
+    }
+    // warm-up, with a sound playing so the scratch buffers of the mixer are allocated
+    sounds[0].play();
+    mixer.write_samples(&mut buffer);
+
+    // only the mixer is counted, the commands and the removed sounds are allocated and freed by
+    // the handles
+    let mut counts = (0, 0);
+    for i in 0..100 {
+        let sound = &mut sounds[i % 10];
+        sound.set_volume(0.5);
+        sound.play();
+        if i % 7 == 0 {
+            sounds[i % 3].stop();
+        }
+        if i % 30 == 0 {
+            let mut sound = engine.new_sound(SineWave::new(48000, 440.0)).unwrap();
+            sound.play();
+            sounds.push(sound);
+        }
+        if i == 50 {
+            // the sound is removed, and sent back to be dropped
+            sounds.pop();
+        }
+        let (allocations, deallocations) = count(|| {
+            mixer.write_samples(&mut buffer);
+        });
+        counts.0 += allocations;
+        counts.1 += deallocations;
+    }
+    assert_eq!(counts, (0, 0));
+    assert!(ended.load(Ordering::Relaxed) > 0);
+}
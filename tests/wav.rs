@@ -0,0 +1,180 @@
+//! Check that WavDecoder correctly decodes wav files in all formats supported by hound, including
+//! multichannel files using WAVE_FORMAT_EXTENSIBLE.
+#![cfg(feature = "wav")]
+
+use std::io::Cursor;
+
+use audio_engine::{SoundSource, WavDecoder};
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+/// The number of frames in each test file.
+const FRAMES: usize = 100;
+
+/// Generate a sample in the range -1.0..1.0, different for each frame and channel.
+fn sample(frame: usize, channel: usize) -> f32 {
+    ((frame * 7 + channel * 13) % 64) as f32 / 32.0 - 1.0
+}
+
+/// Encode a wav file with the given spec, returning it and its samples in i16.
+///
+/// hound writes the file using WAVE_FORMAT_EXTENSIBLE if it has more than 2 channels or more than
+/// 16 bits per sample.
+fn encode(spec: WavSpec) -> (Vec<u8>, Vec<i16>) {
+    let mut data = Cursor::new(Vec::new());
+    let mut expected = Vec::new();
+    {
+        let mut writer = WavWriter::new(&mut data, spec).unwrap();
+        for frame in 0..FRAMES {
+            for channel in 0..spec.channels as usize {
+                let x = sample(frame, channel);
+                let bits = spec.bits_per_sample;
+                match spec.sample_format {
+                    SampleFormat::Float => writer.write_sample(x).unwrap(),
+                    SampleFormat::Int if bits == 8 => {
+                        writer.write_sample((x * 128.0) as i8).unwrap()
+                    }
+                    SampleFormat::Int if bits == 16 => {
+                        writer.write_sample((x * 32768.0) as i16).unwrap()
+                    }
+                    SampleFormat::Int => writer
+                        .write_sample((x * (1u32 << (bits - 1)) as f32) as i32)
+                        .unwrap(),
+                }
+                expected.push((x * 32768.0) as i16);
+            }
+        }
+        writer.finalize().unwrap();
+    }
+    (data.into_inner(), expected)
+}
+
+fn check(channels: u16, bits_per_sample: u16, sample_format: SampleFormat) {
+    let spec = WavSpec {
+        channels,
+        sample_rate: 44100,
+        bits_per_sample,
+        sample_format,
+    };
+    let (data, expected) = encode(spec);
+
+    let mut decoder = WavDecoder::new(Cursor::new(data)).unwrap();
+    assert_eq!(decoder.channels(), channels);
+    assert_eq!(decoder.sample_rate(), 44100);
+    assert_eq!(decoder.spec(), spec);
+    assert_eq!(decoder.size_hint(), Some(expected.len() as u64));
+
+    let mut output = vec![0; expected.len() + 2 * channels as usize];
+    let len = decoder.write_samples(&mut output);
+    assert_eq!(len, expected.len(), "{:?}", spec);
+    for (i, (a, b)) in output.iter().zip(&expected).enumerate() {
+        // float samples may be rounded differently
+        assert!(
+            (a - b).abs() <= 1,
+            "{:?}: sample {}: {} != {}",
+            spec,
+            i,
+            a,
+            b
+        );
+    }
+
+    // decode again after reset
+    decoder.reset();
+    let mut output2 = vec![0; output.len()];
+    assert_eq!(decoder.write_samples(&mut output2), len);
+    assert_eq!(output, output2);
+
+    // decode as f32
+    decoder.reset();
+    let mut output_f32 = vec![0.0; output.len()];
+    assert_eq!(decoder.write_samples_f32(&mut output_f32), len);
+    for (i, (a, b)) in output_f32.iter().zip(&expected).enumerate() {
+        let a = (a * 32768.0) as i16;
+        assert!(
+            (a - b).abs() <= 1,
+            "{:?}: f32 sample {}: {} != {}",
+            spec,
+            i,
+            a,
+            b
+        );
+    }
+}
+
+#[test]
+fn int_formats() {
+    for &channels in &[1, 2, 4, 6, 8] {
+        for &bits in &[8, 16, 24, 32] {
+            check(channels, bits, SampleFormat::Int);
+        }
+    }
+}
+
+#[test]
+fn float_format() {
+    for &channels in &[1, 2, 4, 6, 8] {
+        check(channels, 32, SampleFormat::Float);
+    }
+}
+
+#[test]
+fn from_reader() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let (data, expected) = encode(spec);
+    let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+
+    let mut decoder = WavDecoder::from_reader(data).unwrap();
+    let mut output = vec![0; expected.len() + 4];
+    assert_eq!(decoder.write_samples(&mut output), expected.len());
+    assert_eq!(output[..expected.len()], expected[..]);
+
+    let mut decoder = WavDecoder::with_reopen(move || Ok(data)).unwrap();
+    assert_eq!(decoder.write_samples(&mut output), expected.len());
+    decoder.reset();
+    let mut output2 = vec![0; output.len()];
+    assert_eq!(decoder.write_samples(&mut output2), expected.len());
+    assert_eq!(output, output2);
+}
+
+#[test]
+fn seek() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 24,
+        sample_format: SampleFormat::Int,
+    };
+    let (data, expected) = encode(spec);
+    let mut decoder = WavDecoder::new(Cursor::new(data)).unwrap();
+
+    decoder.seek(40);
+    let mut output = vec![0; expected.len()];
+    let len = expected.len() - 80;
+    assert_eq!(decoder.write_samples(&mut output), len);
+    assert_eq!(output[..len], expected[80..]);
+
+    // seeking past the end ends the sound
+    decoder.seek(FRAMES as u64 + 10);
+    assert_eq!(decoder.write_samples(&mut output), 0);
+    decoder.seek(0);
+    assert_eq!(decoder.write_samples(&mut output), expected.len());
+}
+
+#[test]
+fn duration() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 1000,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let (data, _) = encode(spec);
+    let decoder = WavDecoder::new(Cursor::new(data)).unwrap();
+    let expected = std::time::Duration::from_millis(FRAMES as u64);
+    assert_eq!(decoder.duration(), Some(expected));
+}